@@ -0,0 +1,390 @@
+//! Reading and writing of Redis append-only files.
+//!
+//! An AOF file is a sequence of RESP arrays, one per command. When `aof-use-rdb-preamble` is
+//! enabled the file starts with an RDB snapshot, which is skipped by the readers here.
+use crate::{dump_append, parse, Error, ParseError, RESPOwned, RESP};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+const RDB_MAGIC: &[u8] = b"REDIS";
+const RDB_EOF: u8 = 0xFF;
+const RDB_CHECKSUM_LEN: usize = 8;
+const READ_CHUNK: usize = 8192;
+
+/// A command read from an AOF file along with its position in the file.
+#[derive(Debug, PartialEq)]
+pub struct Entry<'a> {
+    /// Byte offset of the command from the start of the file.
+    pub offset: u64,
+    /// Length of the command in bytes.
+    pub len: usize,
+    pub command: RESP<'a>,
+}
+
+/// Iterates the commands of an AOF file held in memory (or memory mapped), borrowing from it.
+pub struct SliceReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    done: bool,
+}
+
+impl<'a> SliceReader<'a> {
+    pub fn new(buf: &'a [u8]) -> SliceReader<'a> {
+        SliceReader {
+            buf,
+            pos: 0,
+            done: false,
+        }
+    }
+}
+
+impl<'a> Iterator for SliceReader<'a> {
+    type Item = Result<Entry<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if self.pos == 0 && self.buf.starts_with(RDB_MAGIC) {
+            match rdb_preamble_len(self.buf, true) {
+                Some(n) => self.pos = n,
+                None => {
+                    self.done = true;
+                    return Some(Err(ParseError::Incomplete));
+                }
+            }
+        }
+        if self.pos == self.buf.len() {
+            self.done = true;
+            return None;
+        }
+        match parse(&self.buf[self.pos..]) {
+            Ok((len, command)) => {
+                let offset = self.pos as u64;
+                self.pos += len;
+                Some(Ok(Entry {
+                    offset,
+                    len,
+                    command,
+                }))
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Iterates the commands of an AOF file read from a `Read`, such as a `File`.
+///
+/// A truncated final command is reported as `ParseError::Incomplete` or
+/// `ParseError::CLRFNotFound`, mirroring what Redis calls a truncated AOF.
+pub struct Reader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    start: usize,
+    offset: u64,
+    eof: bool,
+    started: bool,
+    done: bool,
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(inner: R) -> Reader<R> {
+        Reader {
+            inner,
+            buf: Vec::new(),
+            start: 0,
+            offset: 0,
+            eof: false,
+            started: false,
+            done: false,
+        }
+    }
+
+    /// Reads another chunk into the buffer, discarding bytes which have already been consumed.
+    fn fill(&mut self) -> Result<(), Error> {
+        self.buf.drain(..self.start);
+        self.start = 0;
+        let len = self.buf.len();
+        self.buf.resize(len + READ_CHUNK, 0);
+        let n = match self.inner.read(&mut self.buf[len..]) {
+            Ok(n) => n,
+            Err(err) => {
+                self.buf.truncate(len);
+                return Err(Error::Io(err));
+            }
+        };
+        self.buf.truncate(len + n);
+        if n == 0 {
+            self.eof = true;
+        }
+        Ok(())
+    }
+
+    fn skip_preamble(&mut self) -> Result<(), Error> {
+        while self.buf.len() < RDB_MAGIC.len() && !self.eof {
+            self.fill()?;
+        }
+        if !self.buf.starts_with(RDB_MAGIC) {
+            return Ok(());
+        }
+        loop {
+            let scanned = &self.buf[self.start..];
+            if let Some(n) = rdb_preamble_len(scanned, self.eof) {
+                self.start += n;
+                self.offset += n as u64;
+                return Ok(());
+            }
+            if self.eof {
+                return Err(Error::Parse(ParseError::Incomplete));
+            }
+            // Keep enough of the tail around to recognize an EOF opcode split across reads.
+            let keep = RDB_CHECKSUM_LEN + 1;
+            let scanned = scanned.len().saturating_sub(keep);
+            self.start += scanned;
+            self.offset += scanned as u64;
+            self.fill()?;
+        }
+    }
+
+    fn read_entry(&mut self) -> Result<Option<Entry<'static>>, Error> {
+        if !self.started {
+            self.started = true;
+            self.skip_preamble()?;
+        }
+        loop {
+            if self.start == self.buf.len() && self.eof {
+                return Ok(None);
+            }
+            match parse(&self.buf[self.start..]) {
+                Ok((len, command)) => {
+                    let command: RESPOwned = command.into_owned();
+                    let offset = self.offset;
+                    self.start += len;
+                    self.offset += len as u64;
+                    return Ok(Some(Entry {
+                        offset,
+                        len,
+                        command,
+                    }));
+                }
+                Err(ParseError::Incomplete) | Err(ParseError::CLRFNotFound) if !self.eof => {
+                    self.fill()?;
+                }
+                Err(err) => return Err(Error::Parse(err)),
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = Result<Entry<'static>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let entry = self.read_entry();
+        if !matches!(entry, Ok(Some(_))) {
+            self.done = true;
+        }
+        entry.transpose()
+    }
+}
+
+/// When a `Writer` makes appended commands durable, matching Redis's `appendfsync` options.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FsyncPolicy {
+    /// Fsync after every appended command.
+    Always,
+    /// Fsync when at least a second has passed since the last fsync.
+    EverySec,
+    /// Leave it to the operating system, only fsyncing on an explicit `Writer::fsync`.
+    No,
+}
+
+type FsyncHook<W> = Box<dyn FnMut(&mut W) -> io::Result<()>>;
+
+/// Appends commands to an AOF file.
+///
+/// Durability is delegated to an fsync hook, called with the underlying writer after it has been
+/// flushed, so that wrappers such as `BufWriter<File>` can be synced too.
+pub struct Writer<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+    offset: u64,
+    policy: FsyncPolicy,
+    fsync: FsyncHook<W>,
+    last_fsync: Instant,
+}
+
+impl<W: Write> Writer<W> {
+    /// Creates a writer which never fsyncs, such as for test fixtures.
+    pub fn new(inner: W) -> Writer<W> {
+        Writer::with_fsync(inner, FsyncPolicy::No, |_| Ok(()))
+    }
+
+    /// Creates a writer which calls `fsync` according to `policy`.
+    pub fn with_fsync<F>(inner: W, policy: FsyncPolicy, fsync: F) -> Writer<W>
+    where
+        F: FnMut(&mut W) -> io::Result<()> + 'static,
+    {
+        Writer {
+            inner,
+            buf: Vec::new(),
+            offset: 0,
+            policy,
+            fsync: Box::new(fsync),
+            last_fsync: Instant::now(),
+        }
+    }
+
+    /// Appends a command, returning the offset it was written at.
+    pub fn append(&mut self, command: &RESP) -> Result<u64, Error> {
+        self.buf.clear();
+        let n = dump_append(command, &mut self.buf)?;
+        self.inner.write_all(&self.buf)?;
+        let offset = self.offset;
+        self.offset += n as u64;
+        let due = match self.policy {
+            FsyncPolicy::Always => true,
+            FsyncPolicy::EverySec => self.last_fsync.elapsed() >= Duration::from_secs(1),
+            FsyncPolicy::No => false,
+        };
+        if due {
+            self.fsync()?;
+        }
+        Ok(offset)
+    }
+
+    /// Flushes and fsyncs everything appended so far, regardless of the policy.
+    pub fn fsync(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        (self.fsync)(&mut self.inner)?;
+        self.last_fsync = Instant::now();
+        Ok(())
+    }
+
+    /// Returns the number of bytes appended so far.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl Writer<File> {
+    /// Creates a writer which fsyncs the file with `File::sync_data` according to `policy`.
+    pub fn file(file: File, policy: FsyncPolicy) -> Writer<File> {
+        Writer::with_fsync(file, policy, |f| f.sync_data())
+    }
+}
+
+/// Returns the length of the RDB preamble at the start of `buf`, or `None` if its end hasn't been
+/// found yet.
+///
+/// An RDB file ends with the EOF opcode followed by a checksum. Since finding it exactly would
+/// mean decoding the whole RDB format, the preamble is taken to end at the first EOF opcode whose
+/// checksum is followed by a command, or by the end of the file when `eof` is set.
+fn rdb_preamble_len(buf: &[u8], eof: bool) -> Option<usize> {
+    let mut pos = 0;
+    while let Some(i) = buf[pos..].iter().position(|&b| b == RDB_EOF) {
+        let end = pos + i + 1 + RDB_CHECKSUM_LEN;
+        match buf.get(end) {
+            Some(b'*') => return Some(end),
+            None if end == buf.len() && eof => return Some(end),
+            None => return None,
+            Some(_) => pos += i + 1,
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow::Borrowed;
+
+    fn set(key: &str) -> RESP<'_> {
+        RESP::Array(vec![
+            RESP::BulkString(Borrowed("set")),
+            RESP::BulkString(Borrowed(key)),
+            RESP::BulkString(Borrowed("1")),
+        ])
+    }
+
+    #[test]
+    fn test_readers() {
+        let commands: &[u8] = b"*3\r\n$3\r\nset\r\n$3\r\nfoo\r\n$1\r\n1\r\n\
+                                *3\r\n$3\r\nset\r\n$3\r\nbar\r\n$1\r\n1\r\n";
+        // The value 255 is stored as `\xc0\xff` and must not be mistaken for the EOF opcode.
+        let mut preamble =
+            b"REDIS0009\xfa\x09redis-ver\x057.0.0\xfe\x00\x00\x03foo\xc0\xff".to_vec();
+        preamble.extend_from_slice(&[0xff, 1, 2, 3, 4, 5, 6, 7, 8]);
+        let mut with_preamble = preamble.clone();
+        with_preamble.extend_from_slice(commands);
+
+        let test_cases: Vec<(&[u8], u64)> =
+            vec![(commands, 0), (&with_preamble, preamble.len() as u64)];
+        for (file, first) in test_cases {
+            let expected = vec![
+                Entry {
+                    offset: first,
+                    len: 29,
+                    command: set("foo"),
+                },
+                Entry {
+                    offset: first + 29,
+                    len: 29,
+                    command: set("bar"),
+                },
+            ];
+            let entries: Result<Vec<_>, _> = SliceReader::new(file).collect();
+            assert_eq!(entries, Ok(expected));
+
+            let entries: Vec<_> = Reader::new(file).map(|e| e.unwrap()).collect();
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[0].offset, first);
+            assert_eq!(entries[1].command, set("bar"));
+        }
+    }
+
+    #[test]
+    fn test_writer() {
+        let fsyncs = std::rc::Rc::new(std::cell::Cell::new(0));
+        let counter = fsyncs.clone();
+        let mut writer = Writer::with_fsync(Vec::new(), FsyncPolicy::Always, move |_| {
+            counter.set(counter.get() + 1);
+            Ok(())
+        });
+        assert_eq!(writer.append(&set("foo")).unwrap(), 0);
+        assert_eq!(writer.append(&set("bar")).unwrap(), 29);
+        assert_eq!(fsyncs.get(), 2);
+
+        let file = writer.into_inner();
+        let commands: Vec<_> = SliceReader::new(&file)
+            .map(|e| e.unwrap().command)
+            .collect();
+        assert_eq!(commands, vec![set("foo"), set("bar")]);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let file: &[u8] = b"*1\r\n$4\r\nping\r\n*1\r\n$4\r\npi";
+        let entries: Vec<_> = SliceReader::new(file).collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1], Err(ParseError::Incomplete));
+
+        let entries: Vec<_> = Reader::new(file).collect();
+        assert_eq!(entries.len(), 2);
+        assert!(matches!(
+            entries[1],
+            Err(Error::Parse(ParseError::Incomplete))
+        ));
+    }
+}
@@ -0,0 +1,262 @@
+//! Deliberately broken encoding, for testing how clients and servers cope with protocol
+//! violations.
+//!
+//! Faults are chosen by a seeded generator, so a failing run can be reproduced from its seed.
+use crate::rng::Rng;
+use crate::{
+    dump_append, Error, ARRAY_BYTE, ATTRIBUTE_BYTE, BIG_NUMBER_BYTE, BLOB_ERROR_BYTE, BOOLEAN_BYTE,
+    BULK_STRING_BYTE, DOUBLE_BYTE, ERROR_BYTE, INTEGER_BYTE, MAP_BYTE, NULL_BYTE, PUSH_BYTE, RESP,
+    SET_BYTE, SIMPLE_STRING_BYTE, VERBATIM_STRING_BYTE,
+};
+use std::io::Write;
+
+/// The bytes a type byte may be flipped to, including one which isn't a type at all.
+const TYPE_BYTES: [u8; 16] = [
+    SIMPLE_STRING_BYTE,
+    ERROR_BYTE,
+    INTEGER_BYTE,
+    BULK_STRING_BYTE,
+    ARRAY_BYTE,
+    NULL_BYTE,
+    BOOLEAN_BYTE,
+    DOUBLE_BYTE,
+    BIG_NUMBER_BYTE,
+    BLOB_ERROR_BYTE,
+    VERBATIM_STRING_BYTE,
+    MAP_BYTE,
+    SET_BYTE,
+    ATTRIBUTE_BYTE,
+    PUSH_BYTE,
+    b'?',
+];
+
+/// The types whose header holds a length.
+const LENGTH_BYTES: [u8; 8] = [
+    BULK_STRING_BYTE,
+    BLOB_ERROR_BYTE,
+    VERBATIM_STRING_BYTE,
+    ARRAY_BYTE,
+    MAP_BYTE,
+    SET_BYTE,
+    ATTRIBUTE_BYTE,
+    PUSH_BYTE,
+];
+
+/// The percentage chance of each fault being applied to a frame.
+///
+/// At most one of the faults which break the frame is applied, tried in the order of the
+/// fields. Splitting leaves the bytes intact, so it may be applied along with any of them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChaosOptions {
+    pub truncate: u8,
+    pub flip_type: u8,
+    pub corrupt_length: u8,
+    pub split: u8,
+}
+
+impl Default for ChaosOptions {
+    fn default() -> ChaosOptions {
+        ChaosOptions {
+            truncate: 5,
+            flip_type: 5,
+            corrupt_length: 5,
+            split: 20,
+        }
+    }
+}
+
+/// A fault applied to a frame by a `ChaosWriter`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    /// The frame was cut off after this many bytes.
+    Truncate(usize),
+    /// The type byte was replaced by this one.
+    FlipType(u8),
+    /// The length in the frame's header was replaced by this one.
+    CorruptLength(i64),
+    /// The frame was written in separate writes, starting at each of these offsets.
+    Split(Vec<usize>),
+}
+
+/// Encodes frames, breaking some of them according to `ChaosOptions`.
+pub struct ChaosWriter<W: Write> {
+    inner: W,
+    options: ChaosOptions,
+    rng: Rng,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> ChaosWriter<W> {
+    pub fn new(inner: W, options: ChaosOptions, seed: u64) -> ChaosWriter<W> {
+        ChaosWriter {
+            inner,
+            options,
+            rng: Rng::new(seed),
+            buf: Vec::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Encodes and writes a frame, returning the faults applied to it.
+    pub fn write_frame(&mut self, resp: &RESP) -> Result<Vec<Fault>, Error> {
+        self.buf.clear();
+        dump_append(resp, &mut self.buf)?;
+        let mut faults = Vec::new();
+        if self.roll(self.options.truncate) {
+            let len = self.rng.between(1, self.buf.len() - 1);
+            self.buf.truncate(len);
+            faults.push(Fault::Truncate(len));
+        } else if self.roll(self.options.flip_type) {
+            let from = self.buf[0];
+            let mut to = from;
+            while to == from {
+                to = TYPE_BYTES[self.rng.below(TYPE_BYTES.len() as u64) as usize];
+            }
+            self.buf[0] = to;
+            faults.push(Fault::FlipType(to));
+        } else if self.roll(self.options.corrupt_length) {
+            if let Some(len) = self.corrupt_length() {
+                faults.push(Fault::CorruptLength(len));
+            }
+        }
+
+        if self.roll(self.options.split) {
+            let offsets = self.split_offsets();
+            let mut ends = offsets.iter().skip(1).copied().collect::<Vec<_>>();
+            ends.push(self.buf.len());
+            for (&start, end) in offsets.iter().zip(ends) {
+                self.inner.write_all(&self.buf[start..end])?;
+                self.inner.flush()?;
+            }
+            faults.push(Fault::Split(offsets));
+        } else {
+            self.inner.write_all(&self.buf)?;
+        }
+        Ok(faults)
+    }
+
+    fn roll(&mut self, percent: u8) -> bool {
+        self.rng.below(100) < percent as u64
+    }
+
+    /// Replaces the length in the header of the frame, if it has one, with a wrong one.
+    fn corrupt_length(&mut self) -> Option<i64> {
+        if !LENGTH_BYTES.contains(&self.buf[0]) {
+            return None;
+        }
+        let end = self.buf.iter().position(|&b| b == b'\r')?;
+        let old: i64 = std::str::from_utf8(&self.buf[1..end]).ok()?.parse().ok()?;
+        let len = match self.rng.below(4) {
+            0 => old + 1,
+            1 if old > 0 => old - 1,
+            2 => 1 << 40,
+            _ => -2,
+        };
+        self.buf.splice(1..end, len.to_string().into_bytes());
+        Some(len)
+    }
+
+    /// Picks where to split the frame: after its type byte, and between the `\r` and `\n` of
+    /// each line, where a parser is most likely to mishandle a partial read.
+    fn split_offsets(&mut self) -> Vec<usize> {
+        let mut offsets = vec![0];
+        if self.buf.len() > 1 {
+            offsets.push(1);
+        }
+        for (i, &b) in self.buf.iter().enumerate().skip(1) {
+            if b == b'\r' && i + 1 < self.buf.len() && self.rng.below(2) == 0 {
+                offsets.push(i + 1);
+            }
+        }
+        offsets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use std::io;
+
+    /// Records each write separately.
+    #[derive(Default)]
+    struct Writes(Vec<Vec<u8>>);
+
+    impl Write for Writes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.push(buf.to_vec());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_chaos_writer() {
+        let frame = RESP::command(vec!["SET", "key", "value"]);
+        let only = |truncate, flip_type, corrupt_length, split| ChaosOptions {
+            truncate,
+            flip_type,
+            corrupt_length,
+            split,
+        };
+
+        let mut writer = ChaosWriter::new(Vec::new(), only(100, 0, 0, 0), 1);
+        match writer.write_frame(&frame).unwrap().as_slice() {
+            [Fault::Truncate(len)] => {
+                assert_eq!(writer.get_ref().len(), *len);
+                assert!(parse(writer.get_ref()).is_err());
+            }
+            faults => panic!("unexpected {:?}", faults),
+        }
+
+        let mut writer = ChaosWriter::new(Vec::new(), only(0, 100, 0, 0), 2);
+        match writer.write_frame(&frame).unwrap().as_slice() {
+            [Fault::FlipType(to)] => assert_eq!(writer.get_ref()[0], *to),
+            faults => panic!("unexpected {:?}", faults),
+        }
+
+        let mut writer = ChaosWriter::new(Vec::new(), only(0, 0, 100, 0), 3);
+        match writer.write_frame(&frame).unwrap().as_slice() {
+            [Fault::CorruptLength(len)] => {
+                assert!(writer
+                    .get_ref()
+                    .starts_with(format!("*{}\r\n", len).as_bytes()));
+                assert_ne!(parse(writer.get_ref()).ok(), Some((33, frame.clone())));
+            }
+            faults => panic!("unexpected {:?}", faults),
+        }
+        // Simple types have no length to corrupt.
+        let faults = writer.write_frame(&RESP::Integer(1)).unwrap();
+        assert!(faults.is_empty());
+
+        // Splitting keeps the bytes intact.
+        let mut writer = ChaosWriter::new(Writes::default(), only(0, 0, 0, 100), 4);
+        let faults = writer.write_frame(&frame).unwrap();
+        let writes = writer.into_inner().0;
+        assert_eq!(faults, vec![Fault::Split(vec![0, 1, 7, 12, 21, 25])]);
+        assert_eq!(writes[0], b"*");
+        assert_eq!(writes[1], b"3\r\n$3\r");
+        assert_eq!(parse(&writes.concat()), Ok((33, frame.clone())));
+
+        // The same seed breaks the same frames in the same ways.
+        let run = |seed| {
+            let mut writer = ChaosWriter::new(Vec::new(), ChaosOptions::default(), seed);
+            let faults: Vec<_> = (0..50)
+                .map(|_| writer.write_frame(&frame).unwrap())
+                .collect();
+            (faults, writer.into_inner())
+        };
+        assert_eq!(run(5), run(5));
+        assert_ne!(run(5), run(6));
+    }
+}
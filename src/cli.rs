@@ -0,0 +1,183 @@
+//! Rendering of values the way `redis-cli` prints them, so that output can be pasted back into
+//! it.
+use crate::{format_double, RESP};
+use std::fmt::Write;
+
+/// Quotes a string, escaping quotes, backslashes and unprintable bytes exactly as `redis-cli`
+/// does.
+pub fn escape(s: &str) -> String {
+    escape_bytes(s.as_bytes())
+}
+
+/// Quotes bytes like `escape`, such as a whole frame which may not be UTF-8.
+pub fn escape_bytes(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() + 2);
+    out.push('"');
+    for &b in bytes {
+        match b {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            0x07 => out.push_str("\\a"),
+            0x08 => out.push_str("\\b"),
+            b' '..=b'~' => out.push(b as char),
+            _ => write!(out, "\\x{:02x}", b).unwrap(),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Reverses `escape`, returning the bytes of a quoted string, or `None` if it isn't one.
+///
+/// Escapes are interpreted the way `redis-cli` reads its arguments, so a backslash followed by
+/// any other character stands for that character.
+pub fn unescape(s: &str) -> Option<Vec<u8>> {
+    let inner = s.strip_prefix('"')?.strip_suffix('"')?;
+    let mut out = Vec::with_capacity(inner.len());
+    let mut bytes = inner.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'\\' => match bytes.next()? {
+                b'x' => {
+                    let hex = [bytes.next()?, bytes.next()?];
+                    let hex = std::str::from_utf8(&hex).ok()?;
+                    out.push(u8::from_str_radix(hex, 16).ok()?);
+                }
+                b'n' => out.push(b'\n'),
+                b'r' => out.push(b'\r'),
+                b't' => out.push(b'\t'),
+                b'a' => out.push(0x07),
+                b'b' => out.push(0x08),
+                b => out.push(b),
+            },
+            // An unescaped quote would have ended the string.
+            b'"' => return None,
+            b => out.push(b),
+        }
+    }
+    Some(out)
+}
+
+/// Renders a reply as `redis-cli` prints it to a terminal.
+pub fn render(resp: &RESP) -> String {
+    match resp {
+        RESP::SimpleString(s) => s.to_string(),
+        RESP::BulkString(s) => escape(s),
+        RESP::VerbatimString(_, s) => s.to_string(),
+        RESP::Error(s) | RESP::BlobError(s) => format!("(error) {}", s),
+        RESP::Integer(i) => format!("(integer) {}", i),
+        RESP::Double(d) => format!("(double) {}", format_double(*d)),
+        RESP::BigNumber(s) => format!("(big number) {}", s),
+        RESP::Boolean(b) => format!("({})", b),
+        RESP::NullBulkString | RESP::NullArray | RESP::Null => "(nil)".to_string(),
+        RESP::Array(elements) | RESP::Push(elements) => {
+            render_elements(elements.iter().map(render), ')', "(empty array)")
+        }
+        RESP::Set(elements) => render_elements(elements.iter().map(render), '~', "(empty set)"),
+        RESP::Map(pairs) => {
+            let entries = pairs.iter().map(|(k, v)| {
+                let key = render(k);
+                let indent = " ".repeat(key.len() + 4);
+                format!(
+                    "{} => {}",
+                    key,
+                    render(v).replace('\n', &format!("\n{}", indent))
+                )
+            });
+            render_elements(entries, '#', "(empty hash)")
+        }
+        // redis-cli doesn't show attributes.
+        RESP::Attribute(_, resp) => render(resp),
+    }
+}
+
+/// Numbers each element, indenting its continuation lines to line up under its first.
+fn render_elements<I>(elements: I, mark: char, empty: &str) -> String
+where
+    I: ExactSizeIterator<Item = String>,
+{
+    if elements.len() == 0 {
+        return empty.to_string();
+    }
+    let width = elements.len().to_string().len();
+    let mut out = String::new();
+    for (i, element) in elements.enumerate() {
+        let prefix = format!("{:>width$}{} ", i + 1, mark, width = width);
+        let indent = " ".repeat(prefix.len());
+        for (j, line) in element.lines().enumerate() {
+            if i > 0 || j > 0 {
+                out.push('\n');
+            }
+            out.push_str(if j == 0 { &prefix } else { &indent });
+            out.push_str(line);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow::Borrowed;
+
+    #[test]
+    fn test_escape() {
+        let tests = vec![
+            ("plain", "\"plain\""),
+            ("a \"quoted\"\tword\\", "\"a \\\"quoted\\\"\\tword\\\\\""),
+            ("line\r\n\x07\x08\x00\x7f", "\"line\\r\\n\\a\\b\\x00\\x7f\""),
+            ("é", "\"\\xc3\\xa9\""),
+        ];
+        for (s, escaped) in tests {
+            assert_eq!(escape(s), escaped);
+            assert_eq!(unescape(escaped), Some(s.as_bytes().to_vec()));
+        }
+        assert_eq!(unescape("\"\\x0a\\q\""), Some(b"\nq".to_vec()));
+        for invalid in &[
+            "unquoted",
+            "\"open",
+            "\"a\"b\"",
+            "\"\\x4\"",
+            "\"\\xzz\"",
+            "\"\\\"",
+        ] {
+            assert_eq!(unescape(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let bulk = |s| RESP::BulkString(Borrowed(s));
+        let tests = vec![
+            (RESP::SimpleString(Borrowed("OK")), "OK"),
+            (bulk("a\nb"), "\"a\\nb\""),
+            (RESP::Integer(-1), "(integer) -1"),
+            (RESP::NullBulkString, "(nil)"),
+            (RESP::Error(Borrowed("ERR no")), "(error) ERR no"),
+            (RESP::Array(vec![]), "(empty array)"),
+            (
+                RESP::Array(vec![bulk("a"), RESP::Array(vec![bulk("b"), bulk("c")])]),
+                "1) \"a\"\n2) 1) \"b\"\n   2) \"c\"",
+            ),
+            (
+                RESP::Array((0..10).map(RESP::Integer).collect()),
+                " 1) (integer) 0\n 2) (integer) 1\n 3) (integer) 2\n 4) (integer) 3\n 5) \
+                 (integer) 4\n 6) (integer) 5\n 7) (integer) 6\n 8) (integer) 7\n 9) \
+                 (integer) 8\n10) (integer) 9",
+            ),
+            (
+                RESP::Map(vec![
+                    (bulk("k"), bulk("v")),
+                    (bulk("s"), RESP::Set(vec![bulk("x"), bulk("y")])),
+                ]),
+                "1# \"k\" => \"v\"\n2# \"s\" => 1~ \"x\"\n          2~ \"y\"",
+            ),
+        ];
+        for (resp, rendered) in tests {
+            assert_eq!(render(&resp), rendered);
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! A blocking connection to a RESP server.
+use crate::decoder::Decoder;
+use crate::metrics::Metrics;
+use crate::record::Direction;
+use crate::trace::Trace;
+use crate::{dump_append, Error, RESPOwned, RESP};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Where a server listens.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Endpoint {
+    /// A `host:port` address.
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl Endpoint {
+    /// Parses either a `host:port` address or a socket path such as `/var/run/redis.sock`, which
+    /// may also be given as `unix://<path>`. A `redis://host:port` URL is also accepted.
+    pub fn parse(s: &str) -> Endpoint {
+        let s = s.strip_prefix("redis://").unwrap_or(s);
+        #[cfg(unix)]
+        {
+            if let Some(path) = s.strip_prefix("unix://") {
+                return Endpoint::Unix(path.into());
+            }
+            if s.starts_with('/') {
+                return Endpoint::Unix(s.into());
+            }
+        }
+        Endpoint::Tcp(s.to_string())
+    }
+
+    pub fn connect(&self) -> io::Result<Stream> {
+        match self {
+            Endpoint::Tcp(addr) => {
+                let stream = TcpStream::connect(addr.as_str())?;
+                stream.set_nodelay(true)?;
+                Ok(Stream::Tcp(stream))
+            }
+            #[cfg(unix)]
+            Endpoint::Unix(path) => Ok(Stream::Unix(UnixStream::connect(path)?)),
+        }
+    }
+}
+
+/// A stream connected to an `Endpoint`.
+#[derive(Debug)]
+pub enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    pub fn try_clone(&self) -> io::Result<Stream> {
+        match self {
+            Stream::Tcp(s) => s.try_clone().map(Stream::Tcp),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.try_clone().map(Stream::Unix),
+        }
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.shutdown(how),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.shutdown(how),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.flush(),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.flush(),
+        }
+    }
+}
+
+/// Sends and receives RESP objects over a stream, by default a `TcpStream`.
+///
+/// Any `Read + Write` stream works, so TLS is supported by wrapping the `TcpStream` in a TLS
+/// stream (such as rustls's `StreamOwned`, configured with the SNI name and root certificates the
+/// server needs) and passing it to `Connection::new`.
+pub struct Connection<S = TcpStream> {
+    stream: S,
+    decoder: Decoder,
+    out: Vec<u8>,
+    metrics: Option<Arc<Metrics>>,
+    trace: Option<Trace>,
+}
+
+impl Connection<TcpStream> {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Connection<TcpStream>> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Ok(Connection::new(stream))
+    }
+}
+
+impl Connection<Stream> {
+    pub fn open(endpoint: &Endpoint) -> io::Result<Connection<Stream>> {
+        Ok(Connection::new(endpoint.connect()?))
+    }
+}
+
+impl<S: Read + Write> Connection<S> {
+    pub fn new(stream: S) -> Connection<S> {
+        Connection {
+            stream,
+            decoder: Decoder::new(),
+            out: Vec::new(),
+            metrics: None,
+            trace: None,
+        }
+    }
+
+    /// Starts logging every frame sent and received to `trace`, or stops logging if it's `None`.
+    pub fn set_trace(&mut self, trace: Option<Trace>) {
+        self.trace = trace;
+    }
+
+    /// Records each object sent and received, and parse errors, in `metrics`.
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.decoder.set_metrics(metrics.clone());
+        self.metrics = Some(metrics);
+    }
+
+    /// Writes an object to the stream in full.
+    pub fn send(&mut self, resp: &RESP) -> Result<(), Error> {
+        self.out.clear();
+        let n = dump_append(resp, &mut self.out)?;
+        if let Some(metrics) = &self.metrics {
+            metrics.record_encoded(n);
+        }
+        if let Some(trace) = &mut self.trace {
+            trace.log(Direction::Sent, &self.out)?;
+        }
+        self.stream.write_all(&self.out)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    /// Reads the next object from the stream, blocking until it has arrived in full.
+    pub fn recv(&mut self) -> Result<RESPOwned, Error> {
+        loop {
+            if let Some((n, resp)) = self.decoder.decode()? {
+                if let Some(trace) = &mut self.trace {
+                    trace.log(Direction::Received, self.decoder.last_frame(n))?;
+                }
+                return Ok(resp);
+            }
+            if self.decoder.read_from(&mut self.stream)? == 0 {
+                return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+            }
+        }
+    }
+
+    /// Reads the next object, which must be a bulk string, copying its contents into `w` as they
+    /// arrive. See `Decoder::copy_bulk`.
+    pub fn recv_bulk_to<W: Write>(&mut self, w: &mut W) -> Result<Option<u64>, Error> {
+        self.decoder.copy_bulk(&mut self.stream, w)
+    }
+
+    /// Sends a command and waits for its reply.
+    pub fn call(&mut self, command: &RESP) -> Result<RESPOwned, Error> {
+        self.send(command)?;
+        self.recv()
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow::Borrowed;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_call() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 14];
+            stream.read_exact(&mut request).unwrap();
+            assert_eq!(&request, b"*1\r\n$4\r\nPING\r\n");
+            // Split the reply to exercise partial reads.
+            stream.write_all(b"+PO").unwrap();
+            stream.flush().unwrap();
+            thread::sleep(std::time::Duration::from_millis(10));
+            stream.write_all(b"NG\r\n").unwrap();
+        });
+
+        let mut conn = Connection::connect(addr).unwrap();
+        let reply = conn.call(&RESP::command(vec!["PING"])).unwrap();
+        assert_eq!(reply, RESP::SimpleString(Borrowed("PONG")));
+        server.join().unwrap();
+        assert!(matches!(conn.recv(), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn test_endpoint_parse() {
+        assert_eq!(
+            Endpoint::parse("localhost:6379"),
+            Endpoint::Tcp("localhost:6379".to_string())
+        );
+        assert_eq!(
+            Endpoint::parse("redis://localhost:6379"),
+            Endpoint::Tcp("localhost:6379".to_string())
+        );
+        #[cfg(unix)]
+        {
+            let path = PathBuf::from("/var/run/redis.sock");
+            assert_eq!(
+                Endpoint::parse("/var/run/redis.sock"),
+                Endpoint::Unix(path.clone())
+            );
+            assert_eq!(
+                Endpoint::parse("unix:///var/run/redis.sock"),
+                Endpoint::Unix(path)
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket() {
+        use std::os::unix::net::UnixListener;
+
+        let path = std::env::temp_dir().join(format!("resp-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut conn = Connection::new(stream);
+            conn.recv().unwrap();
+            conn.send(&RESP::SimpleString(Borrowed("PONG"))).unwrap();
+        });
+
+        let mut conn = Connection::open(&Endpoint::Unix(path.clone())).unwrap();
+        let reply = conn.call(&RESP::command(vec!["PING"])).unwrap();
+        assert_eq!(reply, RESP::SimpleString(Borrowed("PONG")));
+        server.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
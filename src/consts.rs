@@ -0,0 +1,271 @@
+//! Encoding of frames at compile time, for replies which never change, along with the frames
+//! of the most common replies.
+//!
+//! Each encoder takes the length of its frame as a const parameter, which its matching `_len`
+//! function computes, so the frame can be stored in a constant:
+//!
+//! ```
+//! use resp::consts::{simple_string, simple_string_len};
+//!
+//! const PONG: [u8; simple_string_len("PONG")] = simple_string("PONG");
+//! assert_eq!(&PONG, b"+PONG\r\n");
+//! ```
+use crate::{ARRAY_BYTE, BULK_STRING_BYTE, ERROR_BYTE, INTEGER_BYTE, RESP, SIMPLE_STRING_BYTE};
+use std::borrow::Cow::Borrowed;
+
+pub const OK: &[u8] = &simple_string::<{ simple_string_len("OK") }>("OK");
+pub const PONG: &[u8] = &simple_string::<{ simple_string_len("PONG") }>("PONG");
+pub const QUEUED: &[u8] = &simple_string::<{ simple_string_len("QUEUED") }>("QUEUED");
+pub const NULL_BULK: &[u8] = b"$-1\r\n";
+pub const NULL_ARRAY: &[u8] = b"*-1\r\n";
+pub const ZERO: &[u8] = &integer::<{ integer_len(0) }>(0);
+pub const ONE: &[u8] = &integer::<{ integer_len(1) }>(1);
+
+// The values the frames above encode.
+pub const OK_RESP: RESP<'static> = RESP::SimpleString(Borrowed("OK"));
+pub const PONG_RESP: RESP<'static> = RESP::SimpleString(Borrowed("PONG"));
+pub const QUEUED_RESP: RESP<'static> = RESP::SimpleString(Borrowed("QUEUED"));
+pub const NULL_BULK_RESP: RESP<'static> = RESP::NullBulkString;
+pub const NULL_ARRAY_RESP: RESP<'static> = RESP::NullArray;
+pub const ZERO_RESP: RESP<'static> = RESP::Integer(0);
+pub const ONE_RESP: RESP<'static> = RESP::Integer(1);
+
+/// Returns the length of the simple string or error frame holding `s`.
+pub const fn simple_string_len(s: &str) -> usize {
+    s.len() + 3
+}
+
+/// Encodes a simple string, failing to compile if it contains a line break.
+pub const fn simple_string<const N: usize>(s: &str) -> [u8; N] {
+    line(SIMPLE_STRING_BYTE, s.as_bytes())
+}
+
+/// Encodes an error, failing to compile if it contains a line break. Its length is given by
+/// `simple_string_len`.
+pub const fn error<const N: usize>(s: &str) -> [u8; N] {
+    line(ERROR_BYTE, s.as_bytes())
+}
+
+/// Returns the length of the integer frame holding `i`.
+pub const fn integer_len(i: i64) -> usize {
+    let mut digits = 1;
+    let mut rest = i.unsigned_abs() / 10;
+    while rest > 0 {
+        digits += 1;
+        rest /= 10;
+    }
+    digits + (i < 0) as usize + 3
+}
+
+/// Encodes an integer.
+pub const fn integer<const N: usize>(i: i64) -> [u8; N] {
+    assert!(N == integer_len(i), "N must be integer_len(i)");
+    let mut out = [0; N];
+    out[0] = INTEGER_BYTE;
+    if i < 0 {
+        out[1] = b'-';
+    }
+    let mut rest = i.unsigned_abs();
+    let mut j = N - 3;
+    loop {
+        out[j] = b'0' + (rest % 10) as u8;
+        rest /= 10;
+        if rest == 0 {
+            break;
+        }
+        j -= 1;
+    }
+    out[N - 2] = b'\r';
+    out[N - 1] = b'\n';
+    out
+}
+
+/// Returns the length of the command frame, an array of bulk strings, holding `args`.
+pub const fn command_len(args: &[&str]) -> usize {
+    let mut len = decimal_len(args.len()) + 3;
+    let mut i = 0;
+    while i < args.len() {
+        len += decimal_len(args[i].len()) + 3 + args[i].len() + 2;
+        i += 1;
+    }
+    len
+}
+
+/// Encodes a command. See `resp_cmd!`.
+pub const fn command<const N: usize>(args: &[&str]) -> [u8; N] {
+    assert!(N == command_len(args), "N must be command_len(args)");
+    let mut out = [0; N];
+    let mut n = header(&mut out, 0, ARRAY_BYTE, args.len());
+    let mut i = 0;
+    while i < args.len() {
+        let bytes = args[i].as_bytes();
+        n = header(&mut out, n, BULK_STRING_BYTE, bytes.len());
+        let mut j = 0;
+        while j < bytes.len() {
+            out[n + j] = bytes[j];
+            j += 1;
+        }
+        out[n + j] = b'\r';
+        out[n + j + 1] = b'\n';
+        n += j + 2;
+        i += 1;
+    }
+    out
+}
+
+/// Encodes a command at runtime, as `resp_cmd!` does for arguments which aren't literals.
+pub fn command_bytes(args: &[&str]) -> Vec<u8> {
+    let len: usize = args
+        .iter()
+        .map(|arg| decimal_len(arg.len()) + arg.len() + 5)
+        .sum();
+    let mut out = Vec::with_capacity(len + decimal_len(args.len()) + 3);
+    write_header(&mut out, ARRAY_BYTE, args.len());
+    for arg in args {
+        write_header(&mut out, BULK_STRING_BYTE, arg.len());
+        out.extend_from_slice(arg.as_bytes());
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
+
+fn write_header(out: &mut Vec<u8>, kind: u8, len: usize) {
+    out.push(kind);
+    out.extend_from_slice(len.to_string().as_bytes());
+    out.extend_from_slice(b"\r\n");
+}
+
+/// Encodes a command into wire bytes, as a `Cow<'static, [u8]>`.
+///
+/// When every argument is a string literal, the command is encoded at compile time and borrowed
+/// from a constant. Otherwise each argument may be anything which is `AsRef<str>`, and the
+/// command is encoded when the macro runs.
+///
+/// ```
+/// use resp::resp_cmd;
+///
+/// assert_eq!(&*resp_cmd!("PING"), b"*1\r\n$4\r\nPING\r\n");
+/// let key = String::from("k");
+/// assert_eq!(&*resp_cmd!("GET", key), b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n");
+/// ```
+#[macro_export]
+macro_rules! resp_cmd {
+    ($($arg:literal),+ $(,)?) => {{
+        const ARGS: &[&str] = &[$($arg),+];
+        const BYTES: [u8; $crate::consts::command_len(ARGS)] = $crate::consts::command(ARGS);
+        ::std::borrow::Cow::<'static, [u8]>::Borrowed(&BYTES)
+    }};
+    ($($arg:expr),+ $(,)?) => {
+        ::std::borrow::Cow::<'static, [u8]>::Owned($crate::consts::command_bytes(&[
+            $(::std::convert::AsRef::<str>::as_ref(&$arg)),+
+        ]))
+    };
+}
+
+/// Writes the header of a frame of type `kind` declaring `len` at `offset`, returning the
+/// offset after it.
+const fn header(out: &mut [u8], offset: usize, kind: u8, len: usize) -> usize {
+    out[offset] = kind;
+    let digits = decimal_len(len);
+    let mut rest = len;
+    let mut j = digits;
+    while j > 0 {
+        out[offset + j] = b'0' + (rest % 10) as u8;
+        rest /= 10;
+        j -= 1;
+    }
+    out[offset + digits + 1] = b'\r';
+    out[offset + digits + 2] = b'\n';
+    offset + digits + 3
+}
+
+const fn decimal_len(n: usize) -> usize {
+    let mut digits = 1;
+    let mut rest = n / 10;
+    while rest > 0 {
+        digits += 1;
+        rest /= 10;
+    }
+    digits
+}
+
+const fn line<const N: usize>(kind: u8, bytes: &[u8]) -> [u8; N] {
+    assert!(N == bytes.len() + 3, "N must be simple_string_len(s)");
+    let mut out = [0; N];
+    out[0] = kind;
+    let mut i = 0;
+    while i < bytes.len() {
+        assert!(
+            bytes[i] != b'\r' && bytes[i] != b'\n',
+            "simple strings and errors can't contain line breaks"
+        );
+        out[i + 1] = bytes[i];
+        i += 1;
+    }
+    out[N - 2] = b'\r';
+    out[N - 1] = b'\n';
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dump, parse};
+
+    const ERR: [u8; simple_string_len("ERR no")] = error("ERR no");
+    const MIN: [u8; integer_len(i64::MIN)] = integer(i64::MIN);
+
+    #[test]
+    fn test_const_encoding() {
+        let mut buf = [0; 32];
+        let n = dump(&RESP::Error(Borrowed("ERR no")), &mut buf).unwrap();
+        assert_eq!(&ERR, &buf[..n]);
+        let n = dump(&RESP::Integer(i64::MIN), &mut buf).unwrap();
+        assert_eq!(&MIN, &buf[..n]);
+
+        for i in [0, 7, 10, -1, -10, 12345, i64::MAX].iter() {
+            let expected = format!(":{}\r\n", i);
+            assert_eq!(integer_len(*i), expected.len());
+        }
+        assert_eq!(&integer::<4>(0), b":0\r\n");
+        assert_eq!(&integer::<6>(-42), b":-42\r\n");
+    }
+
+    #[test]
+    fn test_resp_cmd() {
+        let value = "v".repeat(12);
+        let test_cases = vec![
+            (resp_cmd!("PING"), RESP::command(vec!["PING"])),
+            (
+                resp_cmd!("CLIENT", "SETNAME", "worker-10"),
+                RESP::command(vec!["CLIENT", "SETNAME", "worker-10"]),
+            ),
+            (
+                resp_cmd!("SET", "k", value),
+                RESP::command(vec!["SET", "k", &value]),
+            ),
+        ];
+        for (bytes, command) in test_cases {
+            let mut buf = [0; 64];
+            let n = dump(&command, &mut buf).unwrap();
+            assert_eq!(&*bytes, &buf[..n]);
+        }
+        assert!(matches!(resp_cmd!("PING"), Borrowed(_)));
+    }
+
+    #[test]
+    fn test_consts() {
+        let test_cases = vec![
+            (OK, OK_RESP),
+            (PONG, PONG_RESP),
+            (QUEUED, QUEUED_RESP),
+            (NULL_BULK, NULL_BULK_RESP),
+            (NULL_ARRAY, NULL_ARRAY_RESP),
+            (ZERO, ZERO_RESP),
+            (ONE, ONE_RESP),
+        ];
+        for (bytes, resp) in test_cases {
+            assert_eq!(parse(bytes), Ok((bytes.len(), resp)));
+        }
+    }
+}
@@ -0,0 +1,267 @@
+//! Conversion of plain Rust values into `RESP` objects, so handlers can return them as replies
+//! without building trees by hand.
+use crate::{RESPOwned, RESP};
+use std::borrow::Cow::Owned;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::hash::BuildHasher;
+use std::iter::FromIterator;
+
+/// Collects values into an array.
+impl<'a> FromIterator<RESP<'a>> for RESP<'a> {
+    fn from_iter<I: IntoIterator<Item = RESP<'a>>>(iter: I) -> RESP<'a> {
+        RESP::Array(iter.into_iter().collect())
+    }
+}
+
+/// Collects pairs into a map.
+impl<'a> FromIterator<(RESP<'a>, RESP<'a>)> for RESP<'a> {
+    fn from_iter<I: IntoIterator<Item = (RESP<'a>, RESP<'a>)>>(iter: I) -> RESP<'a> {
+        RESP::Map(iter.into_iter().collect())
+    }
+}
+
+/// Appends values to an array, set or push.
+///
+/// Panics if the object is any other type.
+impl<'a> Extend<RESP<'a>> for RESP<'a> {
+    fn extend<I: IntoIterator<Item = RESP<'a>>>(&mut self, iter: I) {
+        match self {
+            RESP::Array(elements) | RESP::Set(elements) | RESP::Push(elements) => {
+                elements.extend(iter)
+            }
+            _ => panic!("can't extend {:?} with elements", self.kind()),
+        }
+    }
+}
+
+/// Appends pairs to a map or attribute.
+///
+/// Panics if the object is any other type.
+impl<'a> Extend<(RESP<'a>, RESP<'a>)> for RESP<'a> {
+    fn extend<I: IntoIterator<Item = (RESP<'a>, RESP<'a>)>>(&mut self, iter: I) {
+        match self {
+            RESP::Map(pairs) | RESP::Attribute(pairs, _) => pairs.extend(iter),
+            _ => panic!("can't extend {:?} with pairs", self.kind()),
+        }
+    }
+}
+
+/// A value which can be encoded as a `RESP` object.
+pub trait ToResp {
+    fn to_resp(&self) -> RESPOwned;
+}
+
+impl ToResp for RESP<'_> {
+    fn to_resp(&self) -> RESPOwned {
+        self.clone().into_owned()
+    }
+}
+
+impl<T: ToResp + ?Sized> ToResp for &T {
+    fn to_resp(&self) -> RESPOwned {
+        (**self).to_resp()
+    }
+}
+
+impl<T: ToResp + ?Sized> ToResp for Box<T> {
+    fn to_resp(&self) -> RESPOwned {
+        (**self).to_resp()
+    }
+}
+
+/// Strings are encoded as bulk strings, since they may hold any text.
+impl ToResp for str {
+    fn to_resp(&self) -> RESPOwned {
+        RESP::BulkString(Owned(self.to_string()))
+    }
+}
+
+impl ToResp for String {
+    fn to_resp(&self) -> RESPOwned {
+        self.as_str().to_resp()
+    }
+}
+
+impl ToResp for bool {
+    fn to_resp(&self) -> RESPOwned {
+        RESP::Boolean(*self)
+    }
+}
+
+macro_rules! impl_integer {
+    ($($t:ty)*) => {$(
+        /// Integers outside the range of an `i64` are encoded as big numbers.
+        impl ToResp for $t {
+            fn to_resp(&self) -> RESPOwned {
+                match i64::try_from(*self) {
+                    Ok(i) => RESP::Integer(i),
+                    Err(_) => RESP::BigNumber(Owned(self.to_string())),
+                }
+            }
+        }
+    )*};
+}
+
+impl_integer!(i8 i16 i32 i64 i128 isize u8 u16 u32 u64 u128 usize);
+
+impl ToResp for f32 {
+    fn to_resp(&self) -> RESPOwned {
+        RESP::Double(f64::from(*self))
+    }
+}
+
+impl ToResp for f64 {
+    fn to_resp(&self) -> RESPOwned {
+        RESP::Double(*self)
+    }
+}
+
+/// `None` is encoded as a null bulk string, which clients of both protocol versions understand.
+impl<T: ToResp> ToResp for Option<T> {
+    fn to_resp(&self) -> RESPOwned {
+        match self {
+            Some(value) => value.to_resp(),
+            None => RESP::NullBulkString,
+        }
+    }
+}
+
+impl<T: ToResp> ToResp for [T] {
+    fn to_resp(&self) -> RESPOwned {
+        RESP::Array(self.iter().map(ToResp::to_resp).collect())
+    }
+}
+
+impl<T: ToResp, const N: usize> ToResp for [T; N] {
+    fn to_resp(&self) -> RESPOwned {
+        self[..].to_resp()
+    }
+}
+
+impl<T: ToResp> ToResp for Vec<T> {
+    fn to_resp(&self) -> RESPOwned {
+        self[..].to_resp()
+    }
+}
+
+impl<K: ToResp, V: ToResp, S: BuildHasher> ToResp for HashMap<K, V, S> {
+    fn to_resp(&self) -> RESPOwned {
+        RESP::Map(
+            self.iter()
+                .map(|(k, v)| (k.to_resp(), v.to_resp()))
+                .collect(),
+        )
+    }
+}
+
+impl<K: ToResp, V: ToResp> ToResp for BTreeMap<K, V> {
+    fn to_resp(&self) -> RESPOwned {
+        RESP::Map(
+            self.iter()
+                .map(|(k, v)| (k.to_resp(), v.to_resp()))
+                .collect(),
+        )
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($t:ident)+) => {
+        /// Tuples are encoded as arrays of their elements.
+        impl<$($t: ToResp),+> ToResp for ($($t,)+) {
+            #[allow(non_snake_case)]
+            fn to_resp(&self) -> RESPOwned {
+                let ($($t,)+) = self;
+                RESP::Array(vec![$($t.to_resp()),+])
+            }
+        }
+    };
+}
+
+impl_tuple!(A);
+impl_tuple!(A B);
+impl_tuple!(A B C);
+impl_tuple!(A B C D);
+impl_tuple!(A B C D E);
+impl_tuple!(A B C D E F);
+impl_tuple!(A B C D E F G);
+impl_tuple!(A B C D E F G H);
+impl_tuple!(A B C D E F G H I);
+impl_tuple!(A B C D E F G H I J);
+impl_tuple!(A B C D E F G H I J K);
+impl_tuple!(A B C D E F G H I J K L);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow::Borrowed;
+
+    #[test]
+    fn test_to_resp() {
+        let mut map = BTreeMap::new();
+        map.insert("a", vec![1u8, 2]);
+        let tests: Vec<(Box<dyn ToResp>, RESPOwned)> = vec![
+            (Box::new("foo"), RESP::BulkString(Borrowed("foo"))),
+            (Box::new(-7i32), RESP::Integer(-7)),
+            (
+                Box::new(u64::MAX),
+                RESP::BigNumber(Borrowed("18446744073709551615")),
+            ),
+            (Box::new(1.5f32), RESP::Double(1.5)),
+            (Box::new(true), RESP::Boolean(true)),
+            (Box::new(None::<String>), RESP::NullBulkString),
+            (
+                Box::new(Some("x".to_string())),
+                RESP::BulkString(Borrowed("x")),
+            ),
+            (
+                Box::new(map),
+                RESP::Map(vec![(
+                    RESP::BulkString(Borrowed("a")),
+                    RESP::Array(vec![RESP::Integer(1), RESP::Integer(2)]),
+                )]),
+            ),
+            (
+                Box::new(("k", 1, [Some(2.0)])),
+                RESP::Array(vec![
+                    RESP::BulkString(Borrowed("k")),
+                    RESP::Integer(1),
+                    RESP::Array(vec![RESP::Double(2.0)]),
+                ]),
+            ),
+            (
+                Box::new((1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12)),
+                RESP::Array((1..=12).map(RESP::Integer).collect()),
+            ),
+        ];
+        for (value, expected) in tests {
+            assert_eq!(value.to_resp(), expected);
+        }
+    }
+
+    #[test]
+    fn test_collect() {
+        let mut arr: RESP = (1..=2).map(RESP::Integer).collect();
+        arr.extend(vec![RESP::Null]);
+        assert_eq!(
+            arr,
+            RESP::Array(vec![RESP::Integer(1), RESP::Integer(2), RESP::Null])
+        );
+
+        let mut map: RESP = vec![("a", 1)]
+            .into_iter()
+            .map(|(k, v)| (RESP::SimpleString(Borrowed(k)), RESP::Integer(v)))
+            .collect();
+        map.extend(vec![(RESP::Null, RESP::Boolean(false))]);
+        assert_eq!(
+            map,
+            RESP::Map(vec![
+                (RESP::SimpleString(Borrowed("a")), RESP::Integer(1)),
+                (RESP::Null, RESP::Boolean(false)),
+            ])
+        );
+
+        let result = std::panic::catch_unwind(|| RESP::Integer(1).extend(vec![RESP::Null]));
+        assert!(result.is_err());
+    }
+}
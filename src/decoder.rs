@@ -0,0 +1,1159 @@
+//! Incremental decoding of RESP objects from bytes as they arrive.
+use crate::lexer::{Lexer, Token};
+use crate::{
+    metrics::{error_kind, Metrics, ERROR_KINDS},
+    parse_traced, parse_with, peek_len, peek_type, read_len, Error, ParseError, ParseOptions,
+    RESPOwned, RespType, BLOB_ERROR_BYTE, BULK_STRING_BYTE, RESP, VERBATIM_STRING_BYTE,
+};
+use std::borrow::Cow::{self, Borrowed, Owned};
+use std::collections::HashSet;
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::sync::Arc;
+
+const READ_CHUNK: usize = 8192;
+
+/// Buffers bytes until they make up complete RESP objects.
+#[derive(Debug, Default)]
+pub struct Decoder {
+    /// Shared with the frames `decode_shared` returns, and only copied on a write while any of
+    /// them are still around.
+    buf: Arc<Vec<u8>>,
+    start: usize,
+    options: ParseOptions,
+    interner: Interner,
+    metrics: Option<Arc<Metrics>>,
+    stats: DecoderStats,
+    recovery: Option<RecoveryHandler>,
+    version: Option<Version>,
+    track_types: bool,
+    /// The types of the frames decoded so far, including nested ones, if tracked.
+    seen: Vec<RespType>,
+    attributes: AttributePolicy,
+}
+
+/// What a decoder does with RESP3 attributes, which describe the value that follows them.
+#[derive(Debug, Default)]
+pub enum AttributePolicy {
+    /// Decode an attribute along with the value it describes, as `RESP::Attribute`.
+    #[default]
+    Attach,
+    /// Discard attributes, decoding only the values they describe.
+    Strip,
+    /// Pass each attribute's pairs to a handler, decoding only the value it describes.
+    Route(AttributeHandler),
+}
+
+impl AttributePolicy {
+    /// Passes each attribute's pairs to `handler`.
+    pub fn route<F>(handler: F) -> AttributePolicy
+    where
+        F: FnMut(Vec<(RESPOwned, RESPOwned)>) + Send + 'static,
+    {
+        AttributePolicy::Route(AttributeHandler(Box::new(handler)))
+    }
+}
+
+type AttributeFn = Box<dyn FnMut(Vec<(RESPOwned, RESPOwned)>) + Send>;
+
+/// A handler for attributes, from `AttributePolicy::route`.
+pub struct AttributeHandler(AttributeFn);
+
+impl fmt::Debug for AttributeHandler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("AttributeHandler")
+    }
+}
+
+/// A version of the protocol, which decides the types of frame a peer may send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Version {
+    Resp2,
+    Resp3,
+}
+
+/// What a decoder does with a malformed frame, as decided by its recovery handler.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Recovery {
+    /// Fail with the error, such as to drop the connection, as without a handler.
+    Fail,
+    /// Discard bytes up to the end of the next line, then carry on decoding.
+    ///
+    /// A frame may well continue past its first line, so whatever follows is only likely to be
+    /// the start of a frame, and may fail again.
+    Skip,
+    /// Discard bytes like `Skip`, and decode this value in place of them.
+    Substitute(RESPOwned),
+}
+
+type RecoveryFn = Box<dyn FnMut(&ParseError, &[u8]) -> Recovery + Send>;
+
+struct RecoveryHandler(RecoveryFn);
+
+impl fmt::Debug for RecoveryHandler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("RecoveryHandler")
+    }
+}
+
+/// What a decoder has seen of its peer, to tell slow peers, which cause many partial reads,
+/// from misbehaving ones, which cause errors.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DecoderStats {
+    /// The number of frames decoded.
+    pub frames: u64,
+    /// The number of times `decode` found only part of a frame.
+    pub partial_reads: u64,
+    /// The number of times `decode` failed on an invalid frame.
+    pub errors: u64,
+    /// The size of the largest frame decoded.
+    pub largest_frame: usize,
+    /// The number of times a recovery handler skipped past a malformed frame.
+    pub resyncs: u64,
+    /// The number of each kind of parse error, named by `metrics::ERROR_KINDS`.
+    pub error_kinds: [u64; ERROR_KINDS.len()],
+    /// The type byte of the value the last error was about, which may be nested inside the
+    /// frame, or the byte itself for `UnknownByte`.
+    pub last_error_byte: Option<u8>,
+}
+
+/// What a decoder holds between calls, for handing a connection over to another thread or
+/// process part way through a frame, such as during a proxy's hot upgrade.
+///
+/// Only the buffered bytes and stats are kept. The parse options, intern table, metrics and
+/// recovery handler are up to whoever resumes decoding.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DecoderState {
+    /// The bytes which haven't been decoded yet, starting at the start of a frame.
+    pub buffered: Vec<u8>,
+    pub stats: DecoderStats,
+}
+
+/// The size of a state's stats in `DecoderState::to_bytes`.
+const STATS_LEN: usize = 8 * (5 + ERROR_KINDS.len()) + 2;
+
+impl DecoderState {
+    /// Returns how many more bytes the next frame needs to be complete, if that's known: 0 if
+    /// nothing or a whole frame is buffered, or the rest of the last string of a frame whose
+    /// header has arrived.
+    pub fn remaining(&self) -> Option<usize> {
+        remaining(&self.buffered)
+    }
+
+    /// Encodes the state as the stats, each count as a little endian `u64` and the last error
+    /// byte as a flag and the byte, followed by the buffered bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let stats = &self.stats;
+        let mut bytes = Vec::with_capacity(STATS_LEN + self.buffered.len());
+        let fields = [
+            stats.frames,
+            stats.partial_reads,
+            stats.errors,
+            stats.largest_frame as u64,
+            stats.resyncs,
+        ];
+        for field in fields.iter().chain(&stats.error_kinds) {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        match stats.last_error_byte {
+            Some(byte) => bytes.extend_from_slice(&[1, byte]),
+            None => bytes.extend_from_slice(&[0, 0]),
+        }
+        bytes.extend_from_slice(&self.buffered);
+        bytes
+    }
+
+    /// Decodes a state encoded by `to_bytes`, or returns `None` if it's too short to be one.
+    pub fn from_bytes(bytes: &[u8]) -> Option<DecoderState> {
+        if bytes.len() < STATS_LEN {
+            return None;
+        }
+        let field = |i: usize| u64::from_le_bytes(bytes[8 * i..8 * i + 8].try_into().unwrap());
+        let mut error_kinds = [0; ERROR_KINDS.len()];
+        for (i, count) in error_kinds.iter_mut().enumerate() {
+            *count = field(5 + i);
+        }
+        let last_error_byte = match bytes[STATS_LEN - 2] {
+            0 => None,
+            _ => Some(bytes[STATS_LEN - 1]),
+        };
+        Some(DecoderState {
+            buffered: bytes[STATS_LEN..].to_vec(),
+            stats: DecoderStats {
+                frames: field(0),
+                partial_reads: field(1),
+                errors: field(2),
+                largest_frame: field(3) as usize,
+                resyncs: field(4),
+                error_kinds,
+                last_error_byte,
+            },
+        })
+    }
+}
+
+/// Returns how many more bytes the frame at the start of `buf` needs, if that's known. See
+/// `DecoderState::remaining`.
+fn remaining(buf: &[u8]) -> Option<usize> {
+    let mut lexer = Lexer::new(buf);
+    // The number of values left in the frame, as in `skip_value`.
+    let mut pending: usize = 1;
+    while pending > 0 {
+        let token = match lexer.next_token() {
+            Ok(Some(token)) => token,
+            Ok(None) if lexer.offset() == 0 => return Some(0),
+            Err(ParseError::Incomplete) if pending == 1 => {
+                let rest = &buf[lexer.offset()..];
+                return match (peek_type(rest), peek_len(rest)) {
+                    (Ok(BULK_STRING_BYTE), Ok(Some(len)))
+                    | (Ok(BLOB_ERROR_BYTE), Ok(Some(len)))
+                    | (Ok(VERBATIM_STRING_BYTE), Ok(Some(len))) => {
+                        let header = rest.windows(2).position(|w| w == b"\r\n")? + 2;
+                        let len = usize::try_from(len).ok()?;
+                        Some(header + len + 2 - rest.len())
+                    }
+                    _ => None,
+                };
+            }
+            _ => return None,
+        };
+        pending -= 1;
+        let children = match token {
+            Token::ArrayStart(n) | Token::SetStart(n) | Token::PushStart(n) => n,
+            Token::MapStart(n) => n.saturating_mul(2),
+            Token::AttributeStart(n) => n.saturating_mul(2).saturating_add(1),
+            _ => 0,
+        };
+        pending = pending.saturating_add(children);
+    }
+    Some(0)
+}
+
+/// Replaces each attribute in `resp` with the value it describes, passing its pairs to `handler`.
+fn take_attributes(
+    resp: RESPOwned,
+    handler: &mut dyn FnMut(Vec<(RESPOwned, RESPOwned)>),
+) -> RESPOwned {
+    let mut all = |elements: Vec<RESPOwned>| {
+        elements
+            .into_iter()
+            .map(|e| take_attributes(e, handler))
+            .collect()
+    };
+    match resp {
+        RESP::Attribute(attrs, value) => {
+            handler(attrs);
+            take_attributes(*value, handler)
+        }
+        RESP::Array(elements) => RESP::Array(all(elements)),
+        RESP::Set(elements) => RESP::Set(all(elements)),
+        RESP::Push(elements) => RESP::Push(all(elements)),
+        RESP::Map(pairs) => RESP::Map(
+            pairs
+                .into_iter()
+                .map(|(k, v)| (take_attributes(k, handler), take_attributes(v, handler)))
+                .collect(),
+        ),
+        resp => resp,
+    }
+}
+
+/// Calls `f` with the type of `resp` and each value nested in it, stopping as soon as it
+/// returns true. Returns whether it did.
+fn any_type<F: FnMut(RespType) -> bool>(resp: &RESP, f: &mut F) -> bool {
+    if f(resp.kind()) {
+        return true;
+    }
+    match resp {
+        RESP::Array(elements) | RESP::Set(elements) | RESP::Push(elements) => {
+            elements.iter().any(|e| any_type(e, f))
+        }
+        RESP::Map(pairs) => pairs.iter().any(|(k, v)| any_type(k, f) || any_type(v, f)),
+        RESP::Attribute(attrs, value) => {
+            attrs.iter().any(|(k, v)| any_type(k, f) || any_type(v, f)) || any_type(value, f)
+        }
+        _ => false,
+    }
+}
+
+/// Counts how many decoded strings were found in a decoder's intern table.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct InternStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl InternStats {
+    /// Returns the fraction of strings which were found, or 0 if none have been looked up.
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Interner {
+    table: HashSet<&'static str>,
+    stats: InternStats,
+}
+
+impl Interner {
+    fn owned(&mut self, resp: RESP) -> RESPOwned {
+        if self.table.is_empty() {
+            return resp.into_owned();
+        }
+        match resp {
+            RESP::SimpleString(s) => RESP::SimpleString(self.string(s)),
+            RESP::Error(s) => RESP::Error(self.string(s)),
+            RESP::BulkString(s) => RESP::BulkString(self.string(s)),
+            RESP::BigNumber(s) => RESP::BigNumber(self.string(s)),
+            RESP::BlobError(s) => RESP::BlobError(self.string(s)),
+            RESP::VerbatimString(format, s) => {
+                RESP::VerbatimString(self.string(format), self.string(s))
+            }
+            RESP::Array(arr) => RESP::Array(self.all(arr)),
+            RESP::Map(pairs) => RESP::Map(self.pairs(pairs)),
+            RESP::Set(set) => RESP::Set(self.all(set)),
+            RESP::Attribute(attrs, resp) => {
+                RESP::Attribute(self.pairs(attrs), Box::new(self.owned(*resp)))
+            }
+            RESP::Push(arr) => RESP::Push(self.all(arr)),
+            resp => resp.into_owned(),
+        }
+    }
+
+    fn string(&mut self, s: Cow<str>) -> Cow<'static, str> {
+        match self.table.get(&*s) {
+            Some(interned) => {
+                self.stats.hits += 1;
+                Borrowed(interned)
+            }
+            None => {
+                self.stats.misses += 1;
+                Owned(s.into_owned())
+            }
+        }
+    }
+
+    fn all(&mut self, elements: Vec<RESP>) -> Vec<RESPOwned> {
+        elements.into_iter().map(|e| self.owned(e)).collect()
+    }
+
+    fn pairs(&mut self, pairs: Vec<(RESP, RESP)>) -> Vec<(RESPOwned, RESPOwned)> {
+        pairs
+            .into_iter()
+            .map(|(k, v)| (self.owned(k), self.owned(v)))
+            .collect()
+    }
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder::default()
+    }
+
+    /// Creates a decoder which parses as relaxed by `options`.
+    pub fn with_options(options: ParseOptions) -> Decoder {
+        Decoder {
+            options,
+            ..Decoder::default()
+        }
+    }
+
+    /// Creates a decoder which carries on from `state`, such as one sent over by another
+    /// process, parsing as relaxed by `options`.
+    pub fn from_state(state: DecoderState, options: ParseOptions) -> Decoder {
+        Decoder {
+            buf: Arc::new(state.buffered),
+            options,
+            stats: state.stats,
+            ..Decoder::default()
+        }
+    }
+
+    /// Returns a copy of the bytes buffered and the stats, from which `from_state` can carry on
+    /// decoding.
+    pub fn state(&self) -> DecoderState {
+        DecoderState {
+            buffered: self.buffered().to_vec(),
+            stats: self.stats,
+        }
+    }
+
+    /// Decodes the objects in a sequence of byte chunks, such as the segments of an HTTP body,
+    /// however the objects are split between them.
+    pub fn from_chunks<'c, I>(chunks: I) -> Chunks<I::IntoIter>
+    where
+        I: IntoIterator<Item = &'c [u8]>,
+    {
+        Chunks {
+            decoder: Decoder::new(),
+            chunks: Some(chunks.into_iter()),
+        }
+    }
+
+    /// Adds `strings` to the decoder's intern table. Decoded strings equal to one of them borrow
+    /// it rather than allocating a copy, which suits the few command, channel and field names
+    /// that make up most of a busy connection's strings.
+    pub fn intern(&mut self, strings: &[&'static str]) {
+        self.interner.table.extend(strings);
+    }
+
+    /// Returns how often decoded strings have been found in the intern table.
+    pub fn intern_stats(&self) -> InternStats {
+        self.interner.stats
+    }
+
+    /// Calls `handler` with each parse error and the bytes which failed to parse, starting with
+    /// the malformed frame, to decide how to recover from it.
+    pub fn set_recovery<F>(&mut self, handler: F)
+    where
+        F: FnMut(&ParseError, &[u8]) -> Recovery + Send + 'static,
+    {
+        self.recovery = Some(RecoveryHandler(Box::new(handler)));
+    }
+
+    /// Only accepts frames of the types `version` has. A RESP2 decoder fails on a frame which
+    /// holds a RESP3 type with `UnknownByte`, as a RESP2 parser would.
+    ///
+    /// By default a decoder accepts both.
+    pub fn lock_version(&mut self, version: Version) {
+        self.version = Some(version);
+    }
+
+    /// Notes the types of the frames decoded from now on in `seen_types`.
+    ///
+    /// This walks every decoded frame, so it's off by default.
+    pub fn track_types(&mut self) {
+        self.track_types = true;
+    }
+
+    /// Sets what happens to attributes, including those nested in aggregates.
+    pub fn set_attribute_policy(&mut self, policy: AttributePolicy) {
+        self.attributes = policy;
+    }
+
+    /// Returns the types of the frames decoded since `track_types` was called, including those
+    /// nested in aggregates, in the order they were first seen.
+    pub fn seen_types(&self) -> &[RespType] {
+        &self.seen
+    }
+
+    /// Returns the oldest version with every type in `seen_types`, which is `Resp2` until a
+    /// RESP3 type is seen, or `None` if types aren't being tracked.
+    pub fn seen_version(&self) -> Option<Version> {
+        if !self.track_types {
+            None
+        } else if self.seen.iter().any(|kind| kind.is_resp3()) {
+            Some(Version::Resp3)
+        } else {
+            Some(Version::Resp2)
+        }
+    }
+
+    /// Returns what the decoder has seen so far.
+    pub fn stats(&self) -> DecoderStats {
+        self.stats
+    }
+
+    /// Records each decoded frame and parse error in `metrics`.
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Adds bytes to the end of the buffer.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.compact();
+        Arc::make_mut(&mut self.buf).extend_from_slice(bytes);
+    }
+
+    /// Adds an owned buffer of bytes, such as one a completion based read filled, to the end of
+    /// the buffer. If nothing is buffered, `bytes` becomes the buffer rather than being copied,
+    /// so frames from `decode_shared` can go on to borrow it.
+    pub fn push_owned(&mut self, bytes: Vec<u8>) {
+        if self.buffered().is_empty() {
+            self.buf = Arc::new(bytes);
+            self.start = 0;
+        } else {
+            self.feed(&bytes);
+        }
+    }
+
+    /// Reads once from `r` into the buffer, returning the number of bytes read.
+    pub fn read_from<R: Read>(&mut self, r: &mut R) -> io::Result<usize> {
+        self.compact();
+        let buf = Arc::make_mut(&mut self.buf);
+        let len = buf.len();
+        buf.resize(len + READ_CHUNK, 0);
+        match r.read(&mut buf[len..]) {
+            Ok(n) => {
+                buf.truncate(len + n);
+                Ok(n)
+            }
+            Err(err) => {
+                buf.truncate(len);
+                Err(err)
+            }
+        }
+    }
+
+    /// Decodes the next object, returning it along with the number of bytes it took up, or `None`
+    /// if the buffer doesn't hold a complete object yet.
+    pub fn decode(&mut self) -> Result<Option<(usize, RESPOwned)>, ParseError> {
+        loop {
+            let err = match self.decode_frame() {
+                Err(err) => err,
+                decoded => return decoded,
+            };
+            let recovery = match &mut self.recovery {
+                Some(RecoveryHandler(handler)) => handler(&err, &self.buf[self.start..]),
+                None => Recovery::Fail,
+            };
+            if recovery == Recovery::Fail {
+                return Err(err);
+            }
+            self.stats.resyncs += 1;
+            let buffered = self.buffered();
+            let n = buffered
+                .windows(2)
+                .position(|w| w == b"\r\n")
+                .map_or(buffered.len(), |i| i + 2);
+            self.start += n;
+            if let Recovery::Substitute(resp) = recovery {
+                return Ok(Some((n, resp)));
+            }
+        }
+    }
+
+    fn decode_frame(&mut self) -> Result<Option<(usize, RESPOwned)>, ParseError> {
+        let (seen, version, track_types) = (&mut self.seen, self.version, self.track_types);
+        let mut failed = None;
+        let parsed = parse_traced(&self.buf[self.start..], 0, self.options, &mut failed);
+        let parsed = parsed.and_then(|(n, resp)| {
+            if version == Some(Version::Resp2) {
+                let mut resp3 = None;
+                any_type(&resp, &mut |kind| {
+                    resp3 = Some(kind).filter(|kind| kind.is_resp3());
+                    resp3.is_some()
+                });
+                if let Some(kind) = resp3 {
+                    failed = Some(kind.byte());
+                    return Err(ParseError::UnknownByte(kind.byte()));
+                }
+            }
+            if track_types {
+                any_type(&resp, &mut |kind| {
+                    if !seen.contains(&kind) {
+                        seen.push(kind);
+                    }
+                    false
+                });
+            }
+            Ok((n, resp))
+        });
+        match parsed {
+            Ok((n, resp)) => {
+                let mut resp = self.interner.owned(resp);
+                match &mut self.attributes {
+                    AttributePolicy::Attach => {}
+                    AttributePolicy::Strip => resp = take_attributes(resp, &mut |_| {}),
+                    AttributePolicy::Route(AttributeHandler(handler)) => {
+                        resp = take_attributes(resp, handler)
+                    }
+                }
+                self.record_frame(n);
+                Ok(Some((n, resp)))
+            }
+            Err(err) => self.record_failure(err, failed).map(|_| None),
+        }
+    }
+
+    /// Decodes the next frame without copying its strings out of the buffer, returning `None` if
+    /// the buffer doesn't hold a complete frame yet.
+    ///
+    /// The frame shares the buffer, which suits buffers handed over with `push_owned`. While
+    /// it's alive, adding bytes copies only those which haven't been decoded into a new buffer.
+    /// Unlike `decode`, this doesn't apply a version lock, attribute policy or recovery handler.
+    pub fn decode_shared(&mut self) -> Result<Option<SharedFrame>, ParseError> {
+        let (start, options) = (self.start, self.options);
+        let mut failed = None;
+        match parse_traced(&self.buf[start..], 0, options, &mut failed) {
+            Ok((n, _)) => {
+                self.record_frame(n);
+                Ok(Some(SharedFrame {
+                    buf: Arc::clone(&self.buf),
+                    range: start..start + n,
+                    options,
+                }))
+            }
+            Err(err) => self.record_failure(err, failed).map(|_| None),
+        }
+    }
+
+    /// Consumes a decoded frame of `n` bytes and counts it.
+    fn record_frame(&mut self, n: usize) {
+        self.start += n;
+        self.stats.frames += 1;
+        self.stats.largest_frame = self.stats.largest_frame.max(n);
+        if let Some(metrics) = &self.metrics {
+            metrics.record_decoded(n);
+        }
+    }
+
+    /// Counts a failed parse, returning `Ok` if the frame is only incomplete.
+    fn record_failure(&mut self, err: ParseError, failed: Option<u8>) -> Result<(), ParseError> {
+        if let ParseError::Incomplete | ParseError::CLRFNotFound = err {
+            if !self.buffered().is_empty() {
+                self.stats.partial_reads += 1;
+            }
+            return Ok(());
+        }
+        self.stats.errors += 1;
+        if let Some(kind) = error_kind(&err) {
+            self.stats.error_kinds[kind] += 1;
+        }
+        self.stats.last_error_byte = failed;
+        if let Some(metrics) = &self.metrics {
+            metrics.record_error(&err);
+        }
+        Err(err)
+    }
+
+    /// Decodes the next object, which must be a bulk string, by copying its contents into `w` as
+    /// they're read from `r` rather than buffering all of them. Returns the length of the string,
+    /// or `None` if it's null.
+    ///
+    /// This suits values too large to hold in memory, such as `DUMP` payloads.
+    pub fn copy_bulk<R: Read, W: Write>(
+        &mut self,
+        r: &mut R,
+        w: &mut W,
+    ) -> Result<Option<u64>, Error> {
+        let (header, len) = loop {
+            let buf = self.buffered();
+            if let Some(&kind) = buf.first() {
+                if kind != BULK_STRING_BYTE {
+                    return Err(Error::Parse(ParseError::InvalidValue(kind)));
+                }
+                match read_len(buf, 1, self.options) {
+                    Ok((n, len)) => break (n + 1, len),
+                    Err(ParseError::Incomplete) | Err(ParseError::CLRFNotFound) => {}
+                    Err(err) => return Err(Error::Parse(err)),
+                }
+            }
+            self.read_more(r)?;
+        };
+        self.start += header;
+        if len < 0 {
+            return Ok(None);
+        }
+        let mut remaining = len as u64;
+        while remaining > 0 {
+            if self.buffered().is_empty() {
+                self.read_more(r)?;
+            }
+            let n = remaining.min(self.buffered().len() as u64) as usize;
+            w.write_all(&self.buffered()[..n])?;
+            self.start += n;
+            remaining -= n as u64;
+        }
+        // Skip the CRLF after the contents, checking it as `parse` does.
+        while self.buffered().is_empty() {
+            self.read_more(r)?;
+        }
+        if self.options.lenient_line_endings && self.buffered()[0] == b'\n' {
+            self.start += 1;
+            return Ok(Some(len as u64));
+        }
+        while self.buffered().len() < 2 {
+            self.read_more(r)?;
+        }
+        if &self.buffered()[..2] != b"\r\n" {
+            return Err(Error::Parse(ParseError::InvalidValue(BULK_STRING_BYTE)));
+        }
+        self.start += 2;
+        Ok(Some(len as u64))
+    }
+
+    /// Returns the type byte of the next object, or `None` if none of it has arrived yet.
+    pub fn peek_type(&self) -> Result<Option<u8>, ParseError> {
+        match peek_type(self.buffered()) {
+            Ok(kind) => Ok(Some(kind)),
+            Err(ParseError::Incomplete) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Returns the length declared by the next object's header, or `None` if its header hasn't
+    /// arrived yet or it doesn't declare one. See `resp::peek_len`.
+    ///
+    /// This allows rejecting an object which is too large before buffering all of it.
+    pub fn peek_len(&self) -> Result<Option<i64>, ParseError> {
+        match peek_len(self.buffered()) {
+            Err(ParseError::Incomplete) | Err(ParseError::CLRFNotFound) => Ok(None),
+            len => len,
+        }
+    }
+
+    /// Returns the bytes of the frame just decoded, which took up `n` bytes.
+    pub(crate) fn last_frame(&self, n: usize) -> &[u8] {
+        &self.buf[self.start - n..self.start]
+    }
+
+    /// Returns the bytes which haven't been decoded yet.
+    pub fn buffered(&self) -> &[u8] {
+        &self.buf[self.start..]
+    }
+
+    fn read_more<R: Read>(&mut self, r: &mut R) -> io::Result<()> {
+        if self.read_from(r)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+
+    fn compact(&mut self) {
+        if Arc::get_mut(&mut self.buf).is_none() {
+            // Frames still share the buffer, so move what's left to a new one.
+            self.buf = Arc::new(self.buffered().to_vec());
+        } else {
+            Arc::make_mut(&mut self.buf).drain(..self.start);
+        }
+        self.start = 0;
+    }
+}
+
+/// A frame from `Decoder::decode_shared`, which holds on to the buffer it was decoded from
+/// rather than copying its strings out.
+#[derive(Debug, Clone)]
+pub struct SharedFrame {
+    buf: Arc<Vec<u8>>,
+    range: Range<usize>,
+    options: ParseOptions,
+}
+
+impl SharedFrame {
+    /// Returns the bytes of the frame.
+    pub fn bytes(&self) -> &[u8] {
+        &self.buf[self.range.clone()]
+    }
+
+    /// Returns the frame as a `RESP` object borrowing its strings from the buffer.
+    pub fn resp(&self) -> RESP<'_> {
+        match parse_with(self.bytes(), self.options) {
+            Ok((_, resp)) => resp,
+            Err(_) => unreachable!("shared frames are parsed when decoded"),
+        }
+    }
+}
+
+/// The objects decoded from chunks of bytes, from `Decoder::from_chunks`.
+///
+/// Ends after the first error, which is `Incomplete` if the chunks end part way through an
+/// object.
+pub struct Chunks<I> {
+    decoder: Decoder,
+    chunks: Option<I>,
+}
+
+impl<'c, I: Iterator<Item = &'c [u8]>> Iterator for Chunks<I> {
+    type Item = Result<RESPOwned, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let chunks = self.chunks.as_mut()?;
+        loop {
+            match self.decoder.decode() {
+                Ok(Some((_, resp))) => return Some(Ok(resp)),
+                Ok(None) => match chunks.next() {
+                    Some(chunk) => self.decoder.feed(chunk),
+                    None => break,
+                },
+                Err(err) => {
+                    self.chunks = None;
+                    return Some(Err(err));
+                }
+            }
+        }
+        self.chunks = None;
+        if self.decoder.buffered().is_empty() {
+            None
+        } else {
+            Some(Err(ParseError::Incomplete))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_in_pieces() {
+        let bytes = b"+OK\r\n*2\r\n$3\r\nfoo\r\n:1\r\n";
+        let mut decoder = Decoder::new();
+        let mut decoded = Vec::new();
+        for b in bytes.iter() {
+            decoder.feed(&[*b]);
+            while let Some(frame) = decoder.decode().unwrap() {
+                decoded.push(frame);
+            }
+        }
+        assert_eq!(
+            decoded,
+            vec![
+                (5, RESP::SimpleString(Borrowed("OK"))),
+                (
+                    17,
+                    RESP::Array(vec![RESP::BulkString(Borrowed("foo")), RESP::Integer(1)])
+                ),
+            ]
+        );
+        assert_eq!(decoder.buffered(), b"");
+    }
+
+    #[test]
+    fn test_copy_bulk() {
+        let body = vec![b'x'; 3 * READ_CHUNK];
+        let mut stream = Vec::new();
+        stream.extend_from_slice(&body[10..]);
+        stream.extend_from_slice(b"\r\n$-1\r\n+OK\r\n");
+        let mut stream = io::Cursor::new(stream);
+
+        let mut decoder = Decoder::new();
+        decoder.feed(format!("${}\r\n", body.len()).as_bytes());
+        decoder.feed(&body[..10]);
+        let mut out = Vec::new();
+        let len = decoder.copy_bulk(&mut stream, &mut out).unwrap();
+        assert_eq!(len, Some(body.len() as u64));
+        assert_eq!(out, body);
+        assert!(decoder.buffered().len() <= READ_CHUNK);
+
+        assert_eq!(decoder.copy_bulk(&mut stream, &mut out).unwrap(), None);
+        assert!(matches!(
+            decoder.copy_bulk(&mut stream, &mut out),
+            Err(Error::Parse(ParseError::InvalidValue(b'+')))
+        ));
+        decoder.read_from(&mut stream).unwrap();
+        assert_eq!(
+            decoder.decode(),
+            Ok(Some((5, RESP::SimpleString(Borrowed("OK")))))
+        );
+
+        let mut decoder = Decoder::new();
+        assert!(matches!(
+            decoder.copy_bulk(&mut &b"$2\r\nSYNT"[..], &mut Vec::new()),
+            Err(Error::Parse(ParseError::InvalidValue(b'$')))
+        ));
+    }
+
+    #[test]
+    fn test_peek() {
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.peek_type(), Ok(None));
+        decoder.feed(b"$10");
+        assert_eq!(decoder.peek_type(), Ok(Some(b'$')));
+        assert_eq!(decoder.peek_len(), Ok(None));
+        decoder.feed(b"0\r\n");
+        assert_eq!(decoder.peek_len(), Ok(Some(100)));
+        assert_eq!(decoder.decode(), Ok(None));
+    }
+
+    #[test]
+    fn test_intern() {
+        let mut decoder = Decoder::new();
+        decoder.intern(&["message", "news"]);
+        decoder.feed(b"*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\nhello\r\n");
+        match decoder.decode().unwrap().unwrap().1 {
+            RESP::Array(arr) => {
+                assert!(matches!(arr[0], RESP::BulkString(Borrowed("message"))));
+                assert!(matches!(arr[1], RESP::BulkString(Borrowed("news"))));
+                assert_eq!(arr[2], RESP::BulkString(Borrowed("hello")));
+                assert!(matches!(arr[2], RESP::BulkString(Owned(_))));
+            }
+            resp => panic!("unexpected {:?}", resp),
+        }
+        let stats = decoder.intern_stats();
+        assert_eq!((stats.hits, stats.misses), (2, 1));
+        assert!((stats.hit_rate() - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(Decoder::new().intern_stats().hit_rate(), 0.0);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.decode(), Ok(None));
+        decoder.feed(b"+OK\r\n$5\r\nhel");
+        while decoder.decode().unwrap().is_some() {}
+        decoder.feed(b"lo\r\n?\r\n");
+        assert!(decoder.decode().unwrap().is_some());
+        assert!(decoder.decode().is_err());
+        assert_eq!(
+            decoder.stats(),
+            DecoderStats {
+                frames: 2,
+                partial_reads: 1,
+                errors: 1,
+                largest_frame: 11,
+                resyncs: 0,
+                error_kinds: [1, 0, 0, 0, 0, 0],
+                last_error_byte: Some(b'?'),
+            }
+        );
+
+        decoder.set_recovery(|_, _| Recovery::Skip);
+        decoder.feed(b":1x\r\n#y\r\n");
+        assert_eq!(decoder.decode(), Ok(None));
+        let stats = decoder.stats();
+        // The `?` is still buffered, so it fails again before being skipped.
+        assert_eq!(stats.errors, 4);
+        assert_eq!(stats.error_kinds, [2, 0, 0, 0, 2, 0]);
+        assert_eq!(stats.last_error_byte, Some(b'#'));
+
+        let mut decoder = Decoder::new();
+        decoder.feed(b"*1\r\n:x\r\n");
+        assert!(decoder.decode().is_err());
+        assert_eq!(decoder.stats().last_error_byte, Some(b':'));
+        let test_cases: Vec<(&[u8], u8)> = vec![
+            (b":abc\r\n", b':'),
+            (b",x\r\n", b','),
+            (b"%1\r\n+k\r\n,1.5.5\r\n", b','),
+            (b"*1\r\n$x\r\n", b'$'),
+            (b"~1\r\n+\xff\r\n", b'+'),
+            (b"*2\r\n:1\r\n:99999999999999999999\r\n", b':'),
+        ];
+        for (bytes, expected) in test_cases {
+            let mut decoder = Decoder::new();
+            decoder.feed(bytes);
+            assert!(decoder.decode().is_err());
+            assert_eq!(
+                decoder.stats().last_error_byte,
+                Some(expected),
+                "{:?}",
+                bytes
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_chunks() {
+        let chunks: Vec<&[u8]> = vec![b"+O", b"", b"K\r\n$3\r", b"\nfoo\r\n:1\r\n:2"];
+        let frames: Vec<_> = Decoder::from_chunks(chunks).collect();
+        assert_eq!(
+            frames,
+            vec![
+                Ok(RESP::SimpleString(Borrowed("OK"))),
+                Ok(RESP::BulkString(Borrowed("foo"))),
+                Ok(RESP::Integer(1)),
+                Err(ParseError::Incomplete),
+            ]
+        );
+
+        let chunks: Vec<&[u8]> = vec![b":1\r\n?", b":2\r\n"];
+        let frames: Vec<_> = Decoder::from_chunks(chunks).collect();
+        assert_eq!(
+            frames,
+            vec![Ok(RESP::Integer(1)), Err(ParseError::UnknownByte(b'?'))]
+        );
+    }
+
+    #[test]
+    fn test_push_owned() {
+        let mut decoder = Decoder::new();
+        let bytes = b"+OK\r\n:1".to_vec();
+        let ptr = bytes.as_ptr();
+        decoder.push_owned(bytes);
+        assert_eq!(decoder.buffered().as_ptr(), ptr);
+        assert_eq!(
+            decoder.decode(),
+            Ok(Some((5, RESP::SimpleString(Borrowed("OK")))))
+        );
+        decoder.push_owned(b"\r\n".to_vec());
+        assert_eq!(decoder.decode(), Ok(Some((4, RESP::Integer(1)))));
+        assert_eq!(decoder.buffered(), b"");
+
+        let bytes = b"*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n$3\r\nb".to_vec();
+        let ptr = bytes.as_ptr();
+        decoder.push_owned(bytes);
+        let frame = decoder.decode_shared().unwrap().unwrap();
+        assert_eq!(frame.bytes().as_ptr(), ptr);
+        let resp = frame.resp();
+        assert_eq!(resp, RESP::command(vec!["foo", "bar"]));
+        match &resp {
+            RESP::Array(elements) => match &elements[0] {
+                RESP::BulkString(Borrowed(s)) => assert_eq!(s.as_ptr(), ptr.wrapping_add(8)),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+        assert_eq!(decoder.decode_shared().unwrap().map(|f| f.range), None);
+
+        // The frame is still alive, so only the undecoded bytes move to a new buffer.
+        decoder.feed(b"az\r\n");
+        assert_eq!(decoder.buffered(), b"$3\r\nbaz\r\n");
+        assert_eq!(frame.bytes(), b"*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n");
+        let frame = decoder.decode_shared().unwrap().unwrap();
+        assert_eq!(frame.resp(), RESP::BulkString(Borrowed("baz")));
+        assert_eq!(decoder.stats().frames, 4);
+    }
+
+    #[test]
+    fn test_recovery() {
+        let bytes = b"?bad\r\n+OK\r\n:x\r\n:1\r\n";
+        let test_cases = vec![
+            (Recovery::Fail, vec![Err(ParseError::UnknownByte(b'?'))]),
+            (
+                Recovery::Skip,
+                vec![
+                    Ok(Some((5, RESP::SimpleString(Borrowed("OK"))))),
+                    Ok(Some((4, RESP::Integer(1)))),
+                ],
+            ),
+            (
+                Recovery::Substitute(RESP::Null),
+                vec![
+                    Ok(Some((6, RESP::Null))),
+                    Ok(Some((5, RESP::SimpleString(Borrowed("OK"))))),
+                    Ok(Some((4, RESP::Null))),
+                    Ok(Some((4, RESP::Integer(1)))),
+                ],
+            ),
+        ];
+        for (recovery, expected) in test_cases {
+            let mut decoder = Decoder::new();
+            decoder.set_recovery(move |_, _| recovery.clone());
+            decoder.feed(bytes);
+            let mut decoded = Vec::new();
+            loop {
+                match decoder.decode() {
+                    Ok(None) => break,
+                    Err(err) => {
+                        decoded.push(Err(err));
+                        break;
+                    }
+                    frame => decoded.push(frame),
+                }
+            }
+            assert_eq!(decoded, expected);
+        }
+
+        let mut decoder = Decoder::new();
+        decoder.set_recovery(|err, bytes| {
+            assert_eq!(
+                (err, bytes),
+                (&ParseError::UnknownByte(b'?'), &b"?\r\n"[..])
+            );
+            Recovery::Skip
+        });
+        decoder.feed(b"?\r\n");
+        assert_eq!(decoder.decode(), Ok(None));
+        assert_eq!(decoder.stats().resyncs, 1);
+    }
+
+    #[test]
+    fn test_state() {
+        let mut decoder = Decoder::new();
+        decoder.feed(b"+OK\r\n*2\r\n:1\r\n$5\r\nab");
+        decoder.decode().unwrap().unwrap();
+        assert_eq!(decoder.decode(), Ok(None));
+        let state = decoder.state();
+        assert_eq!(state.buffered, b"*2\r\n:1\r\n$5\r\nab");
+        assert_eq!(state.remaining(), Some(5));
+
+        let state = DecoderState::from_bytes(&state.to_bytes()).unwrap();
+        let mut resumed = Decoder::from_state(state, ParseOptions::default());
+        assert_eq!(resumed.stats(), decoder.stats());
+        resumed.feed(b"cde\r\n");
+        assert_eq!(resumed.state().remaining(), Some(0));
+        assert_eq!(
+            resumed.decode().unwrap().unwrap().1,
+            RESP::Array(vec![RESP::Integer(1), RESP::BulkString(Borrowed("abcde"))])
+        );
+        assert_eq!(resumed.stats().frames, 2);
+
+        let test_cases: Vec<(&[u8], Option<usize>)> = vec![
+            (b"", Some(0)),
+            (b"$3\r\n", Some(5)),
+            (b"*2\r\n", None),
+            (b"*2\r\n$1\r\n", None),
+            (b"+OK", None),
+            (b"?", None),
+        ];
+        for (buffered, expected) in test_cases {
+            let state = DecoderState {
+                buffered: buffered.to_vec(),
+                ..DecoderState::default()
+            };
+            assert_eq!(state.remaining(), expected, "{:?}", buffered);
+        }
+        assert_eq!(DecoderState::from_bytes(&[0; 39]), None);
+    }
+
+    #[test]
+    fn test_version() {
+        let mut decoder = Decoder::new();
+        decoder.feed(b"+OK\r\n");
+        decoder.decode().unwrap().unwrap();
+        assert_eq!(decoder.seen_types(), &[]);
+        assert_eq!(decoder.seen_version(), None);
+        decoder.track_types();
+        decoder.feed(b"*2\r\n+OK\r\n:1\r\n");
+        decoder.decode().unwrap().unwrap();
+        assert_eq!(decoder.seen_version(), Some(Version::Resp2));
+        decoder.feed(b"%1\r\n+k\r\n#t\r\n");
+        decoder.decode().unwrap().unwrap();
+        assert_eq!(decoder.seen_version(), Some(Version::Resp3));
+        assert_eq!(
+            decoder.seen_types(),
+            &[
+                RespType::Array,
+                RespType::SimpleString,
+                RespType::Integer,
+                RespType::Map,
+                RespType::Boolean,
+            ]
+        );
+
+        let mut decoder = Decoder::new();
+        decoder.lock_version(Version::Resp2);
+        decoder.track_types();
+        decoder.feed(b"*1\r\n_\r\n");
+        assert_eq!(decoder.decode(), Err(ParseError::UnknownByte(b'_')));
+        assert_eq!(decoder.buffered().len(), 7);
+        assert_eq!(decoder.stats().errors, 1);
+        assert_eq!(decoder.seen_types(), &[]);
+    }
+
+    #[test]
+    fn test_attribute_policy() {
+        let buf = b"*2\r\n|1\r\n+ttl\r\n:3\r\n:1\r\n:2\r\n";
+        let attrs = vec![(RESP::SimpleString(Borrowed("ttl")), RESP::Integer(3))];
+        let stripped = RESP::Array(vec![RESP::Integer(1), RESP::Integer(2)]);
+
+        let mut decoder = Decoder::new();
+        decoder.feed(buf);
+        assert_eq!(
+            decoder.decode().unwrap().unwrap().1,
+            RESP::Array(vec![
+                RESP::Attribute(attrs.clone(), Box::new(RESP::Integer(1))),
+                RESP::Integer(2),
+            ])
+        );
+
+        decoder.set_attribute_policy(AttributePolicy::Strip);
+        decoder.feed(buf);
+        assert_eq!(decoder.decode().unwrap().unwrap().1, stripped);
+
+        let routed = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = routed.clone();
+        decoder.set_attribute_policy(AttributePolicy::route(move |attrs| {
+            sink.lock().unwrap().push(attrs)
+        }));
+        decoder.feed(buf);
+        assert_eq!(decoder.decode().unwrap().unwrap().1, stripped);
+        assert_eq!(*routed.lock().unwrap(), vec![attrs]);
+    }
+}
@@ -0,0 +1,198 @@
+//! Structural comparison of two values, reporting where they differ.
+use crate::{RESPOwned, RespType, RESP};
+use std::fmt;
+
+/// One difference between two values, at a path in the form `RESP::query` takes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffEntry {
+    /// Only the left value has an element at `path`.
+    OnlyLeft { path: String, value: RESPOwned },
+    /// Only the right value has an element at `path`.
+    OnlyRight { path: String, value: RESPOwned },
+    /// The values at `path` are of different types.
+    TypeMismatch {
+        path: String,
+        left: RespType,
+        right: RespType,
+    },
+    /// The values at `path` are of the same type but differ, and aren't aggregates to look
+    /// inside of.
+    Changed {
+        path: String,
+        left: RESPOwned,
+        right: RESPOwned,
+    },
+}
+
+impl DiffEntry {
+    pub fn path(&self) -> &str {
+        match self {
+            DiffEntry::OnlyLeft { path, .. }
+            | DiffEntry::OnlyRight { path, .. }
+            | DiffEntry::TypeMismatch { path, .. }
+            | DiffEntry::Changed { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for DiffEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = match self.path() {
+            "" => "<root>",
+            path => path,
+        };
+        match self {
+            DiffEntry::OnlyLeft { value, .. } => write!(f, "{}: only in left: {:?}", path, value),
+            DiffEntry::OnlyRight { value, .. } => {
+                write!(f, "{}: only in right: {:?}", path, value)
+            }
+            DiffEntry::TypeMismatch { left, right, .. } => {
+                write!(f, "{}: {:?} != {:?}", path, left, right)
+            }
+            DiffEntry::Changed { left, right, .. } => {
+                write!(f, "{}: {:?} != {:?}", path, left, right)
+            }
+        }
+    }
+}
+
+/// Returns every difference between `left` and `right`, in order.
+///
+/// Elements of arrays, sets and pushes are compared by index, and map entries by key, so a map
+/// with its keys reordered doesn't differ. Attributes are skipped over to the values they
+/// describe.
+pub fn diff(left: &RESP, right: &RESP) -> Vec<DiffEntry> {
+    let mut entries = Vec::new();
+    diff_at(String::new(), left, right, &mut entries);
+    entries
+}
+
+fn diff_at(path: String, left: &RESP, right: &RESP, entries: &mut Vec<DiffEntry>) {
+    let (left, right) = (skip_attributes(left), skip_attributes(right));
+    if left == right {
+        return;
+    }
+    match (left, right) {
+        (RESP::Array(l), RESP::Array(r))
+        | (RESP::Set(l), RESP::Set(r))
+        | (RESP::Push(l), RESP::Push(r)) => {
+            for i in 0..l.len().max(r.len()) {
+                let path = join(&path, &i.to_string());
+                match (l.get(i), r.get(i)) {
+                    (Some(l), Some(r)) => diff_at(path, l, r, entries),
+                    (Some(l), None) => entries.push(only_left(path, l)),
+                    (None, Some(r)) => entries.push(only_right(path, r)),
+                    (None, None) => unreachable!(),
+                }
+            }
+        }
+        (RESP::Map(l), RESP::Map(r)) => {
+            for (k, v) in l {
+                let path = join(&path, &segment(k));
+                match r.iter().find(|(rk, _)| rk == k) {
+                    Some((_, rv)) => diff_at(path, v, rv, entries),
+                    None => entries.push(only_left(path, v)),
+                }
+            }
+            for (k, v) in r {
+                if !l.iter().any(|(lk, _)| lk == k) {
+                    entries.push(only_right(join(&path, &segment(k)), v));
+                }
+            }
+        }
+        _ if left.kind() != right.kind() => entries.push(DiffEntry::TypeMismatch {
+            path,
+            left: left.kind(),
+            right: right.kind(),
+        }),
+        _ => entries.push(DiffEntry::Changed {
+            path,
+            left: left.clone().into_owned(),
+            right: right.clone().into_owned(),
+        }),
+    }
+}
+
+fn skip_attributes<'r, 'a>(mut resp: &'r RESP<'a>) -> &'r RESP<'a> {
+    while let RESP::Attribute(_, value) = resp {
+        resp = value;
+    }
+    resp
+}
+
+fn only_left(path: String, value: &RESP) -> DiffEntry {
+    let value = value.clone().into_owned();
+    DiffEntry::OnlyLeft { path, value }
+}
+
+fn only_right(path: String, value: &RESP) -> DiffEntry {
+    let value = value.clone().into_owned();
+    DiffEntry::OnlyRight { path, value }
+}
+
+/// Returns the path segment which `RESP::query` looks up a map key by.
+fn segment(key: &RESP) -> String {
+    match key {
+        RESP::SimpleString(s) | RESP::BulkString(s) | RESP::VerbatimString(_, s) => s.to_string(),
+        RESP::Integer(i) => i.to_string(),
+        key => format!("{:?}", key),
+    }
+}
+
+fn join(path: &str, segment: &str) -> String {
+    if path.is_empty() {
+        segment.to_string()
+    } else {
+        format!("{}.{}", path, segment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use std::borrow::Cow::Borrowed;
+
+    #[test]
+    fn test_diff() {
+        let (_, left) = parse(b"*3\r\n:1\r\n%2\r\n+a\r\n:1\r\n+b\r\n:2\r\n+x\r\n").unwrap();
+        let (_, right) = parse(b"*2\r\n:2\r\n%2\r\n+c\r\n:3\r\n+a\r\n$1\r\n1\r\n").unwrap();
+        let entries = diff(&left, &right);
+        assert_eq!(
+            entries,
+            vec![
+                DiffEntry::Changed {
+                    path: "0".to_string(),
+                    left: RESP::Integer(1),
+                    right: RESP::Integer(2),
+                },
+                DiffEntry::TypeMismatch {
+                    path: "1.a".to_string(),
+                    left: RespType::Integer,
+                    right: RespType::BulkString,
+                },
+                DiffEntry::OnlyLeft {
+                    path: "1.b".to_string(),
+                    value: RESP::Integer(2),
+                },
+                DiffEntry::OnlyRight {
+                    path: "1.c".to_string(),
+                    value: RESP::Integer(3),
+                },
+                DiffEntry::OnlyLeft {
+                    path: "2".to_string(),
+                    value: RESP::SimpleString(Borrowed("x")),
+                },
+            ]
+        );
+        for entry in &entries[1..4] {
+            assert!(left.query(entry.path()).is_some() || right.query(entry.path()).is_some());
+        }
+        assert_eq!(entries[0].to_string(), "0: Integer(1) != Integer(2)");
+
+        // Reordered maps and attributes don't differ.
+        let (_, left) = parse(b"%2\r\n+a\r\n:1\r\n+b\r\n:2\r\n").unwrap();
+        let (_, right) = parse(b"|1\r\n+ttl\r\n:1\r\n%2\r\n+b\r\n:2\r\n+a\r\n:1\r\n").unwrap();
+        assert_eq!(diff(&left, &right), vec![]);
+    }
+}
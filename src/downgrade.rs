@@ -0,0 +1,117 @@
+//! Conversion of RESP3 values into the RESP2 values a server sends in their place, so a proxy
+//! can serve RESP2 clients from a RESP3 backend.
+use crate::{format_double, RESP};
+use std::borrow::Cow::Owned;
+
+/// What `downgrade` does with a push frame, which RESP2 has no type for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PushPolicy {
+    /// Send it as an array, as RESP2 sends pub/sub messages.
+    Keep,
+    /// Drop it, such as for a client which never subscribes.
+    Drop,
+}
+
+/// Converts a value into the RESP2 value a server sends in its place, or `None` if it's a push
+/// which `pushes` drops.
+///
+/// Maps become flat arrays of their keys and values, sets become arrays, booleans become `1` or
+/// `0`, doubles and big numbers become bulk strings of their text, verbatim strings lose their
+/// format, blob errors become simple errors, nulls become null bulk strings, and attributes are
+/// dropped in favour of the values they describe.
+pub fn downgrade(resp: RESP<'_>, pushes: PushPolicy) -> Option<RESP<'_>> {
+    match resp {
+        RESP::Push(_) if pushes == PushPolicy::Drop => None,
+        resp => Some(to_resp2(resp)),
+    }
+}
+
+fn to_resp2(resp: RESP<'_>) -> RESP<'_> {
+    match resp {
+        RESP::Null => RESP::NullBulkString,
+        RESP::Boolean(b) => RESP::Integer(b as i64),
+        RESP::Double(d) => RESP::BulkString(Owned(format_double(d))),
+        RESP::BigNumber(s) => RESP::BulkString(s),
+        RESP::BlobError(s) if s.contains(['\r', '\n']) => {
+            RESP::Error(Owned(s.replace(['\r', '\n'], " ")))
+        }
+        RESP::BlobError(s) => RESP::Error(s),
+        RESP::VerbatimString(_, s) => RESP::BulkString(s),
+        RESP::Array(elements) | RESP::Set(elements) | RESP::Push(elements) => {
+            RESP::Array(elements.into_iter().map(to_resp2).collect())
+        }
+        RESP::Map(pairs) => RESP::Array(
+            pairs
+                .into_iter()
+                .flat_map(|(k, v)| vec![to_resp2(k), to_resp2(v)])
+                .collect(),
+        ),
+        RESP::Attribute(_, value) => to_resp2(*value),
+        resp => resp,
+    }
+}
+
+/// Returns whether a value is made up only of RESP2 types, such that `downgrade` would leave it
+/// as it is.
+pub fn is_resp2(resp: &RESP) -> bool {
+    match resp {
+        RESP::SimpleString(_)
+        | RESP::Error(_)
+        | RESP::Integer(_)
+        | RESP::BulkString(_)
+        | RESP::NullBulkString
+        | RESP::NullArray => true,
+        RESP::Array(elements) => elements.iter().all(is_resp2),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow::Borrowed;
+
+    #[test]
+    fn test_downgrade() {
+        let bulk = |s| RESP::BulkString(Borrowed(s));
+        let test_cases = vec![
+            (
+                RESP::Map(vec![(bulk("k"), RESP::Boolean(true))]),
+                RESP::Array(vec![bulk("k"), RESP::Integer(1)]),
+            ),
+            (
+                RESP::Set(vec![RESP::Double(1.5), RESP::Null]),
+                RESP::Array(vec![bulk("1.5"), RESP::NullBulkString]),
+            ),
+            (RESP::Double(f64::NEG_INFINITY), bulk("-inf")),
+            (RESP::BigNumber(Borrowed("123")), bulk("123")),
+            (
+                RESP::VerbatimString(Borrowed("txt"), Borrowed("hi")),
+                bulk("hi"),
+            ),
+            (
+                RESP::BlobError(Borrowed("ERR a\nb")),
+                RESP::Error(Borrowed("ERR a b")),
+            ),
+            (
+                RESP::Attribute(
+                    vec![(bulk("ttl"), RESP::Integer(3))],
+                    Box::new(RESP::Boolean(false)),
+                ),
+                RESP::Integer(0),
+            ),
+            (
+                RESP::Push(vec![bulk("message")]),
+                RESP::Array(vec![bulk("message")]),
+            ),
+            (RESP::NullArray, RESP::NullArray),
+        ];
+        for (resp3, resp2) in test_cases {
+            let downgraded = downgrade(resp3.clone(), PushPolicy::Keep).unwrap();
+            assert_eq!(downgraded, resp2, "{:?}", resp3);
+            assert!(is_resp2(&downgraded));
+        }
+        assert_eq!(downgrade(RESP::Push(vec![]), PushPolicy::Drop), None);
+        assert!(!is_resp2(&RESP::Array(vec![RESP::Null])));
+    }
+}
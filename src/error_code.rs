@@ -0,0 +1,153 @@
+//! The standard prefixes of error replies, which say what kind of error it is.
+use crate::RESP;
+use std::borrow::Cow::Owned;
+use std::fmt;
+
+/// The code at the start of an error reply, such as `WRONGTYPE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// A generic error.
+    Err,
+    WrongType,
+    /// The key's slot is served by another cluster node, for good.
+    Moved,
+    /// The key's slot is being migrated to another cluster node.
+    Ask,
+    NoScript,
+    BusyGroup,
+    Oom,
+    NoAuth,
+    WrongPass,
+    NoPerm,
+    /// The server is still loading its dataset.
+    Loading,
+    /// A script or function is running.
+    Busy,
+    ReadOnly,
+    MasterDown,
+    TryAgain,
+    ClusterDown,
+    CrossSlot,
+    ExecAbort,
+    NoReplicas,
+}
+
+const CODES: [(ErrorCode, &str); 19] = [
+    (ErrorCode::Err, "ERR"),
+    (ErrorCode::WrongType, "WRONGTYPE"),
+    (ErrorCode::Moved, "MOVED"),
+    (ErrorCode::Ask, "ASK"),
+    (ErrorCode::NoScript, "NOSCRIPT"),
+    (ErrorCode::BusyGroup, "BUSYGROUP"),
+    (ErrorCode::Oom, "OOM"),
+    (ErrorCode::NoAuth, "NOAUTH"),
+    (ErrorCode::WrongPass, "WRONGPASS"),
+    (ErrorCode::NoPerm, "NOPERM"),
+    (ErrorCode::Loading, "LOADING"),
+    (ErrorCode::Busy, "BUSY"),
+    (ErrorCode::ReadOnly, "READONLY"),
+    (ErrorCode::MasterDown, "MASTERDOWN"),
+    (ErrorCode::TryAgain, "TRYAGAIN"),
+    (ErrorCode::ClusterDown, "CLUSTERDOWN"),
+    (ErrorCode::CrossSlot, "CROSSSLOT"),
+    (ErrorCode::ExecAbort, "EXECABORT"),
+    (ErrorCode::NoReplicas, "NOREPLICAS"),
+];
+
+impl ErrorCode {
+    /// Returns the code as it's sent, such as `WRONGTYPE`.
+    pub fn as_str(self) -> &'static str {
+        CODES.iter().find(|(code, _)| *code == self).unwrap().1
+    }
+
+    /// Returns the code an error message starts with, or `None` if it's not a standard one.
+    pub fn from_message(message: &str) -> Option<ErrorCode> {
+        let prefix = message.split(' ').next()?;
+        CODES
+            .iter()
+            .find(|(_, s)| *s == prefix)
+            .map(|(code, _)| *code)
+    }
+
+    /// Returns whether the same command may succeed if sent again after a while, such as once
+    /// the server finishes loading or a cluster finishes failing over.
+    pub fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            ErrorCode::Loading
+                | ErrorCode::Busy
+                | ErrorCode::MasterDown
+                | ErrorCode::TryAgain
+                | ErrorCode::ClusterDown
+        )
+    }
+
+    /// Returns whether the command should be sent to another node, which the message names.
+    pub fn is_redirect(self) -> bool {
+        matches!(self, ErrorCode::Moved | ErrorCode::Ask)
+    }
+
+    /// Returns whether the connection isn't authenticated or allowed to run the command.
+    pub fn is_auth(self) -> bool {
+        matches!(
+            self,
+            ErrorCode::NoAuth | ErrorCode::WrongPass | ErrorCode::NoPerm
+        )
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<'a> RESP<'a> {
+    /// Returns an error reply of `code`, such as `-WRONGTYPE Operation against a key holding
+    /// the wrong kind of value`.
+    pub fn err_with_code(code: ErrorCode, message: &str) -> RESP<'a> {
+        RESP::Error(Owned(format!("{} {}", code, message)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow::Borrowed;
+
+    #[test]
+    fn test_error_code() {
+        for (code, s) in CODES.iter() {
+            assert_eq!(code.as_str(), *s);
+            assert_eq!(ErrorCode::from_message(s), Some(*code));
+        }
+        assert_eq!(
+            RESP::err_with_code(ErrorCode::Moved, "3999 127.0.0.1:6381"),
+            RESP::Error(Borrowed("MOVED 3999 127.0.0.1:6381"))
+        );
+        let test_cases = vec![
+            (
+                "WRONGTYPE Operation against a key",
+                Some(ErrorCode::WrongType),
+            ),
+            ("ERR", Some(ErrorCode::Err)),
+            ("ERROR something", None),
+            ("custom failure", None),
+            ("", None),
+        ];
+        for (message, expected) in test_cases {
+            assert_eq!(ErrorCode::from_message(message), expected, "{}", message);
+        }
+
+        let classes = |code: ErrorCode| (code.is_retryable(), code.is_redirect(), code.is_auth());
+        assert_eq!(classes(ErrorCode::Loading), (true, false, false));
+        assert_eq!(classes(ErrorCode::Ask), (false, true, false));
+        assert_eq!(classes(ErrorCode::NoPerm), (false, false, true));
+        assert_eq!(classes(ErrorCode::WrongType), (false, false, false));
+        for (code, _) in CODES.iter() {
+            let (retryable, redirect, auth) = classes(*code);
+            // No code is in more than one class.
+            assert!([retryable, redirect, auth].iter().filter(|c| **c).count() <= 1);
+        }
+    }
+}
@@ -0,0 +1,372 @@
+//! Seeded generation of reproducible frames, for load generation, fuzzing and benchmarks.
+use crate::rng::Rng;
+use crate::{dump_append, RESPOwned, RESP};
+use std::borrow::Cow::{self, Borrowed, Owned};
+
+/// The shape of the frames `gen` produces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Profile {
+    /// Mostly commands of a few short bulk strings, like typical client traffic, with the
+    /// occasional larger value.
+    SmallCommands,
+    /// A single bulk string of 64 KiB to 1 MiB.
+    HugeBulk,
+    /// Aggregates nested 16 to 64 deep.
+    DeepNesting,
+    /// Shallow values of every RESP2 and RESP3 type.
+    Mixed,
+}
+
+/// The commands used by `Profile::SmallCommands`, along with their number of arguments.
+const COMMANDS: [(&str, usize); 6] = [
+    ("GET", 1),
+    ("SET", 2),
+    ("INCR", 1),
+    ("HSET", 3),
+    ("LPUSH", 2),
+    ("PING", 0),
+];
+
+/// Generates a frame and its encoding. The same seed and profile always give the same frame.
+pub fn gen(seed: u64, profile: Profile) -> (Vec<u8>, RESPOwned) {
+    let resp = generate(&mut Rng::new(seed), profile);
+    let mut buf = Vec::with_capacity(resp.encoded_len());
+    dump_append(&resp, &mut buf).expect("generated frames are always valid");
+    (buf, resp)
+}
+
+pub(crate) fn generate(rng: &mut Rng, profile: Profile) -> RESPOwned {
+    match profile {
+        Profile::SmallCommands => {
+            let (name, arity) = COMMANDS[rng.below(COMMANDS.len() as u64) as usize];
+            let mut args = vec![Borrowed(name)];
+            for _ in 0..arity {
+                // One in a hundred arguments is a larger value.
+                let len = match rng.below(100) {
+                    0 => rng.between(256, 4096),
+                    _ => rng.between(1, 24),
+                };
+                args.push(Owned(text(rng, len)));
+            }
+            RESP::command(args)
+        }
+        Profile::HugeBulk => {
+            let len = rng.between(64 * 1024, 1024 * 1024);
+            RESP::BulkString(Owned(text(rng, len)))
+        }
+        Profile::DeepNesting => {
+            let depth = rng.between(16, 64);
+            nested(rng, depth)
+        }
+        Profile::Mixed => value(rng, 3),
+    }
+}
+
+/// Returns an aggregate nested `depth` deep, with a scalar beside each level.
+fn nested(rng: &mut Rng, depth: usize) -> RESPOwned {
+    if depth == 0 {
+        return scalar(rng);
+    }
+    let inner = nested(rng, depth - 1);
+    let beside = scalar(rng);
+    match rng.below(4) {
+        0 => RESP::Array(vec![beside, inner]),
+        1 => RESP::Set(vec![inner, beside]),
+        2 => RESP::Map(vec![(beside, inner)]),
+        _ => RESP::Push(vec![beside, inner]),
+    }
+}
+
+/// Returns a value of any type, with aggregates nested at most `depth` deep.
+fn value(rng: &mut Rng, depth: usize) -> RESPOwned {
+    if depth == 0 || rng.below(3) != 0 {
+        return scalar(rng);
+    }
+    let len = rng.between(0, 4);
+    let kind = rng.below(5);
+    let elements = |rng: &mut Rng| (0..len).map(|_| value(rng, depth - 1)).collect();
+    match kind {
+        0 => RESP::Array(elements(rng)),
+        1 => RESP::Set(elements(rng)),
+        2 => RESP::Push(elements(rng)),
+        3 => {
+            let pairs = (0..len).map(|_| (scalar(rng), value(rng, depth - 1)));
+            RESP::Map(pairs.collect())
+        }
+        _ => {
+            let attrs = vec![(RESP::SimpleString(Owned(text(rng, 3))), scalar(rng))];
+            RESP::Attribute(attrs, Box::new(value(rng, depth - 1)))
+        }
+    }
+}
+
+fn scalar(rng: &mut Rng) -> RESPOwned {
+    let len = rng.between(0, 16);
+    match rng.below(13) {
+        0 => RESP::SimpleString(Owned(text(rng, len))),
+        1 => RESP::Error(Owned(format!("ERR {}", text(rng, len)))),
+        2 => RESP::Integer(rng.next_u64() as i64),
+        3 => RESP::BulkString(Owned(text(rng, len))),
+        4 => RESP::NullBulkString,
+        5 => RESP::NullArray,
+        6 => RESP::Null,
+        7 => RESP::Boolean(rng.below(2) == 0),
+        // Eighths are exact in binary, so they encode and parse back to the same double.
+        8 => RESP::Double((rng.below(16_000) as f64 - 8_000.0) / 8.0),
+        9 => {
+            let digits: String = (0..rng.between(20, 40))
+                .map(|_| (b'0' + rng.below(10) as u8) as char)
+                .collect();
+            RESP::BigNumber(Owned(format!("1{}", digits)))
+        }
+        10 => RESP::BlobError(Owned(format!("ERR {}", text(rng, len)))),
+        11 => RESP::VerbatimString(Borrowed("txt"), Owned(text(rng, len))),
+        _ => RESP::Integer(rng.below(1000) as i64),
+    }
+}
+
+/// Returns `len` random letters and digits.
+fn text(rng: &mut Rng, len: usize) -> String {
+    const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+    (0..len)
+        .map(|_| ALPHABET[rng.below(ALPHABET.len() as u64) as usize] as char)
+        .collect()
+}
+
+/// Returns simpler variations of a frame, simplest first, for finding a minimal reproducer of a
+/// bug it triggers.
+///
+/// Aggregates are replaced by one of their elements, lose an element, or have an element
+/// shrunk. Strings are truncated, numbers move towards zero, and types are simplified, such as
+/// sets to arrays, and anything to `Null`. Every variation is smaller by some measure, so
+/// repeatedly shrinking always ends.
+pub fn shrink(resp: &RESPOwned) -> Vec<RESPOwned> {
+    let mut shrunk = Vec::new();
+    if *resp != RESP::Null {
+        shrunk.push(RESP::Null);
+    }
+    match resp {
+        RESP::Array(elements) | RESP::Set(elements) | RESP::Push(elements) => {
+            shrunk.extend(elements.iter().cloned());
+            let rebuild = |elements| match resp {
+                RESP::Set(_) => RESP::Set(elements),
+                RESP::Push(_) => RESP::Push(elements),
+                _ => RESP::Array(elements),
+            };
+            if !matches!(resp, RESP::Array(_)) {
+                shrunk.push(RESP::Array(elements.clone()));
+            }
+            for i in 0..elements.len() {
+                let mut fewer = elements.clone();
+                fewer.remove(i);
+                shrunk.push(rebuild(fewer));
+            }
+            for (i, element) in elements.iter().enumerate() {
+                for smaller in shrink(element) {
+                    let mut elements = elements.clone();
+                    elements[i] = smaller;
+                    shrunk.push(rebuild(elements));
+                }
+            }
+        }
+        RESP::Map(pairs) => shrink_pairs(pairs, &mut shrunk, RESP::Map),
+        RESP::Attribute(attrs, value) => {
+            shrunk.push(*value.clone());
+            let rebuild = |attrs| RESP::Attribute(attrs, value.clone());
+            shrink_pairs(attrs, &mut shrunk, rebuild);
+            for smaller in shrink(value) {
+                shrunk.push(RESP::Attribute(attrs.clone(), Box::new(smaller)));
+            }
+        }
+        RESP::SimpleString(s) => shrink_str(s, &mut shrunk, RESP::SimpleString),
+        RESP::Error(s) => shrink_str(s, &mut shrunk, RESP::Error),
+        RESP::BulkString(s) => shrink_str(s, &mut shrunk, RESP::BulkString),
+        RESP::BlobError(s) => {
+            if !s.contains(['\r', '\n']) {
+                shrunk.push(RESP::Error(s.clone()));
+            }
+            shrink_str(s, &mut shrunk, RESP::BlobError);
+        }
+        RESP::VerbatimString(format, s) => {
+            shrunk.push(RESP::BulkString(s.clone()));
+            let format = format.clone();
+            shrink_str(s, &mut shrunk, |s| RESP::VerbatimString(format.clone(), s));
+        }
+        RESP::BigNumber(_) => shrunk.push(RESP::Integer(0)),
+        RESP::Integer(i) if *i != 0 => {
+            shrunk.push(RESP::Integer(0));
+            if *i / 2 != 0 {
+                shrunk.push(RESP::Integer(*i / 2));
+            }
+        }
+        RESP::Double(d) if *d != 0.0 => shrunk.push(RESP::Double(0.0)),
+        RESP::Boolean(true) => shrunk.push(RESP::Boolean(false)),
+        _ => {}
+    }
+    shrunk
+}
+
+fn shrink_pairs<F>(pairs: &[(RESPOwned, RESPOwned)], shrunk: &mut Vec<RESPOwned>, rebuild: F)
+where
+    F: Fn(Vec<(RESPOwned, RESPOwned)>) -> RESPOwned,
+{
+    for (k, v) in pairs {
+        shrunk.push(k.clone());
+        shrunk.push(v.clone());
+    }
+    for i in 0..pairs.len() {
+        let mut fewer = pairs.to_vec();
+        fewer.remove(i);
+        shrunk.push(rebuild(fewer));
+    }
+    for (i, (k, v)) in pairs.iter().enumerate() {
+        for smaller in shrink(k) {
+            let mut pairs = pairs.to_vec();
+            pairs[i].0 = smaller;
+            shrunk.push(rebuild(pairs));
+        }
+        for smaller in shrink(v) {
+            let mut pairs = pairs.to_vec();
+            pairs[i].1 = smaller;
+            shrunk.push(rebuild(pairs));
+        }
+    }
+}
+
+fn shrink_str<F>(s: &str, shrunk: &mut Vec<RESPOwned>, rebuild: F)
+where
+    F: Fn(Cow<'static, str>) -> RESPOwned,
+{
+    if s.is_empty() {
+        return;
+    }
+    shrunk.push(rebuild(Borrowed("")));
+    // Halve the string, or drop its last or first character, cutting on character boundaries.
+    let boundary = |len: usize| (0..=len).rev().find(|&i| s.is_char_boundary(i)).unwrap();
+    let (half, last) = (boundary(s.len() / 2), boundary(s.len() - 1));
+    if half > 0 {
+        shrunk.push(rebuild(Owned(s[..half].to_string())));
+    }
+    if last > half {
+        shrunk.push(rebuild(Owned(s[..last].to_string())));
+    }
+    let first = s.chars().next().unwrap().len_utf8();
+    if first < s.len() {
+        shrunk.push(rebuild(Owned(s[first..].to_string())));
+    }
+}
+
+/// Repeatedly shrinks a frame for as long as `fails` still holds for a variation, returning
+/// the smallest failing variation found.
+pub fn minimize<F: FnMut(&RESPOwned) -> bool>(resp: RESPOwned, mut fails: F) -> RESPOwned {
+    let mut resp = resp;
+    while let Some(smaller) = shrink(&resp).into_iter().find(|smaller| fails(smaller)) {
+        resp = smaller;
+    }
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn depth(resp: &RESP) -> usize {
+        let deepest = |elements: &[RESP]| elements.iter().map(depth).max().unwrap_or(0);
+        match resp {
+            RESP::Array(elements) | RESP::Set(elements) | RESP::Push(elements) => {
+                1 + deepest(elements)
+            }
+            RESP::Map(pairs) => 1 + pairs.iter().map(|(_, v)| depth(v)).max().unwrap_or(0),
+            RESP::Attribute(_, resp) => 1 + depth(resp),
+            _ => 0,
+        }
+    }
+
+    #[test]
+    fn test_gen() {
+        let profiles = [
+            Profile::SmallCommands,
+            Profile::HugeBulk,
+            Profile::DeepNesting,
+            Profile::Mixed,
+        ];
+        for &profile in &profiles {
+            for seed in 0..20 {
+                let (buf, resp) = gen(seed, profile);
+                assert_eq!(gen(seed, profile), (buf.clone(), resp.clone()));
+                assert_eq!(parse(&buf), Ok((buf.len(), resp.clone())), "{:?}", profile);
+                match profile {
+                    Profile::SmallCommands => assert!(matches!(resp, RESP::Array(_))),
+                    Profile::HugeBulk => assert!(buf.len() > 64 * 1024),
+                    Profile::DeepNesting => assert!(depth(&resp) >= 16),
+                    Profile::Mixed => assert!(depth(&resp) <= 3),
+                }
+            }
+        }
+        for &profile in &profiles {
+            let mut frames: Vec<_> = (0..20).map(|seed| gen(seed, profile).0).collect();
+            frames.sort();
+            frames.dedup();
+            // Mixed frames are often a lone scalar such as a null, so some repeat.
+            if profile == Profile::Mixed {
+                assert!(frames.len() > 10);
+            } else {
+                assert_eq!(frames.len(), 20, "{:?}", profile);
+            }
+        }
+    }
+    #[test]
+    fn test_shrink() {
+        let resp = RESP::Set(vec![RESP::Integer(5), RESP::BulkString(Borrowed("ab"))]);
+        assert_eq!(
+            shrink(&resp),
+            vec![
+                RESP::Null,
+                RESP::Integer(5),
+                RESP::BulkString(Borrowed("ab")),
+                RESP::Array(vec![RESP::Integer(5), RESP::BulkString(Borrowed("ab"))]),
+                RESP::Set(vec![RESP::BulkString(Borrowed("ab"))]),
+                RESP::Set(vec![RESP::Integer(5)]),
+                RESP::Set(vec![RESP::Null, RESP::BulkString(Borrowed("ab"))]),
+                RESP::Set(vec![RESP::Integer(0), RESP::BulkString(Borrowed("ab"))]),
+                RESP::Set(vec![RESP::Integer(2), RESP::BulkString(Borrowed("ab"))]),
+                RESP::Set(vec![RESP::Integer(5), RESP::Null]),
+                RESP::Set(vec![RESP::Integer(5), RESP::BulkString(Borrowed(""))]),
+                RESP::Set(vec![RESP::Integer(5), RESP::BulkString(Borrowed("a"))]),
+                RESP::Set(vec![RESP::Integer(5), RESP::BulkString(Borrowed("b"))]),
+            ]
+        );
+        assert!(shrink(&RESP::Null).is_empty());
+
+        // A bug triggered by any bulk string containing a `z` inside a map shrinks to the
+        // smallest such frame.
+        fn has_z(resp: &RESP) -> bool {
+            match resp {
+                RESP::BulkString(s) => s.contains('z'),
+                RESP::Array(elements) | RESP::Set(elements) | RESP::Push(elements) => {
+                    elements.iter().any(has_z)
+                }
+                RESP::Map(pairs) => pairs.iter().any(|(k, v)| has_z(k) || has_z(v)),
+                RESP::Attribute(_, resp) => has_z(resp),
+                _ => false,
+            }
+        }
+        let fails = |resp: &RESPOwned| match resp {
+            RESP::Map(pairs) => pairs.iter().any(|(k, v)| has_z(k) || has_z(v)),
+            _ => false,
+        };
+        let (_, resp) = (0..)
+            .map(|seed| gen(seed, Profile::Mixed))
+            .find(|(_, resp)| fails(resp) && resp.encoded_len() > 40)
+            .unwrap();
+        let minimal = minimize(resp, fails);
+        assert!(matches!(minimal, RESP::Map(ref pairs) if pairs.len() == 1));
+        assert!(
+            minimal == RESP::Map(vec![(RESP::Null, RESP::BulkString(Borrowed("z")))])
+                || minimal == RESP::Map(vec![(RESP::BulkString(Borrowed("z")), RESP::Null)]),
+            "{:?}",
+            minimal
+        );
+    }
+}
@@ -0,0 +1,163 @@
+//! Writing the headers of frames by hand, for fast paths which write the rest of a frame
+//! themselves, such as copying a bulk string's contents straight from a file.
+use crate::{
+    dump_append, write_line, DumpError, ARRAY_BYTE, BULK_STRING_BYTE, MAP_BYTE, PUSH_BYTE, RESP,
+    SET_BYTE,
+};
+
+/// Writes the header of a frame of type `kind` declaring `len` to the start of `buf`, returning
+/// the number of bytes written.
+pub fn write_header(kind: u8, len: i64, buf: &mut [u8]) -> Result<usize, DumpError> {
+    write_line(buf, 0, kind, len.to_string().as_bytes())
+}
+
+/// Writes the header of an array of `n` elements, which should be followed by the elements.
+pub fn write_array_header(n: usize, buf: &mut [u8]) -> Result<usize, DumpError> {
+    write_header(ARRAY_BYTE, n as i64, buf)
+}
+
+/// Writes the header of a bulk string of `len` bytes, which should be followed by the bytes and
+/// `\r\n`.
+pub fn write_bulk_header(len: usize, buf: &mut [u8]) -> Result<usize, DumpError> {
+    write_header(BULK_STRING_BYTE, len as i64, buf)
+}
+
+/// Writes the header of a map of `n` pairs, which should be followed by `2 * n` elements.
+pub fn write_map_header(n: usize, buf: &mut [u8]) -> Result<usize, DumpError> {
+    write_header(MAP_BYTE, n as i64, buf)
+}
+
+/// Writes the header of a set of `n` elements.
+pub fn write_set_header(n: usize, buf: &mut [u8]) -> Result<usize, DumpError> {
+    write_header(SET_BYTE, n as i64, buf)
+}
+
+/// Writes the header of a push of `n` elements, such as a pub/sub message.
+pub fn write_push_header(n: usize, buf: &mut [u8]) -> Result<usize, DumpError> {
+    write_header(PUSH_BYTE, n as i64, buf)
+}
+
+/// Returns the number of bytes a header declaring `len` takes up.
+pub fn header_len(len: i64) -> usize {
+    len.to_string().len() + 3
+}
+
+/// The most bytes a header can take up: a type byte, an `i64` and `\r\n`.
+const MAX_HEADER_LEN: usize = 23;
+
+/// Encodes an array whose length isn't known up front, such as the results of a scan.
+///
+/// Each element is encoded as soon as it's pushed, after space left for the header, which is
+/// written by `finish` once the length is known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArrayBuilder {
+    buf: Vec<u8>,
+    len: usize,
+}
+
+impl Default for ArrayBuilder {
+    fn default() -> ArrayBuilder {
+        ArrayBuilder::new()
+    }
+}
+
+impl ArrayBuilder {
+    pub fn new() -> ArrayBuilder {
+        ArrayBuilder {
+            buf: vec![0; MAX_HEADER_LEN],
+            len: 0,
+        }
+    }
+
+    /// Returns the number of elements pushed so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Encodes an element onto the end of the array.
+    ///
+    /// On an error the array is left as it was.
+    pub fn push(&mut self, resp: &RESP) -> Result<(), DumpError> {
+        dump_append(resp, &mut self.buf)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Appends an element which is already encoded, which must be exactly one frame.
+    pub fn push_encoded(&mut self, frame: &[u8]) {
+        self.buf.extend_from_slice(frame);
+        self.len += 1;
+    }
+
+    /// Writes the header and returns the whole encoded array.
+    pub fn finish(mut self) -> Vec<u8> {
+        let header_len = header_len(self.len as i64);
+        let start = MAX_HEADER_LEN - header_len;
+        // The header always fits in the space left for it.
+        write_array_header(self.len, &mut self.buf[start..MAX_HEADER_LEN]).unwrap();
+        self.buf.drain(..start);
+        self.buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dump;
+    use std::borrow::Cow::Borrowed;
+
+    #[test]
+    fn test_write_header() {
+        let mut buf = [0; 64];
+        let mut n = write_array_header(2, &mut buf).unwrap();
+        for s in ["foo", "hello"].iter() {
+            n += write_bulk_header(s.len(), &mut buf[n..]).unwrap();
+            buf[n..n + s.len()].copy_from_slice(s.as_bytes());
+            buf[n + s.len()..n + s.len() + 2].copy_from_slice(b"\r\n");
+            n += s.len() + 2;
+        }
+        let mut expected = [0; 64];
+        let m = dump(&RESP::command(vec!["foo", "hello"]), &mut expected).unwrap();
+        assert_eq!(&buf[..n], &expected[..m]);
+
+        assert_eq!(header_len(-1), 5);
+        assert_eq!(
+            write_header(crate::BULK_STRING_BYTE, -1, &mut buf),
+            Ok(header_len(-1))
+        );
+        assert_eq!(&buf[..5], b"$-1\r\n");
+        assert_eq!(
+            write_map_header(10, &mut buf[..4]),
+            Err(DumpError::BufTooSmall)
+        );
+        assert_eq!(write_push_header(3, &mut buf), Ok(4));
+        assert_eq!(&buf[..4], b">3\r\n");
+    }
+
+    #[test]
+    fn test_array_builder() {
+        let mut builder = ArrayBuilder::new();
+        assert_eq!(builder.clone().finish(), b"*0\r\n");
+        let mut elements = Vec::new();
+        for i in 0..12 {
+            let element = RESP::Integer(i);
+            builder.push(&element).unwrap();
+            elements.push(element);
+        }
+        assert_eq!(
+            builder.push(&RESP::SimpleString(Borrowed("a\nb"))),
+            Err(DumpError::InvalidSimpleString)
+        );
+        builder.push_encoded(b"+OK\r\n");
+        elements.push(RESP::SimpleString(Borrowed("OK")));
+        assert_eq!(builder.len(), 13);
+
+        let mut expected = [0; 128];
+        let n = dump(&RESP::Array(elements), &mut expected).unwrap();
+        assert_eq!(builder.finish(), &expected[..n]);
+    }
+}
@@ -0,0 +1,172 @@
+//! Parsing of an aggregate's elements one at a time, without building the whole aggregate.
+use crate::{
+    parse_offset, read_len, to_usize, ParseError, ParseOptions, ARRAY_BYTE, PUSH_BYTE, RESP,
+    SET_BYTE,
+};
+use std::ops::Range;
+
+/// Parses the elements of an array, set or push one at a time.
+///
+/// Elements are parsed from whatever buffer is passed to `next_element`, so a proxy can forward
+/// and discard each element as it arrives rather than holding the whole reply.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArrayParser {
+    kind: u8,
+    len: usize,
+    remaining: usize,
+}
+
+impl ArrayParser {
+    /// Parses the header at the start of `buf`, returning the parser along with the length of
+    /// the header, or `None` for a null array.
+    pub fn new(buf: &[u8]) -> Result<Option<(usize, ArrayParser)>, ParseError> {
+        let kind = *buf.first().ok_or(ParseError::Incomplete)?;
+        if kind != ARRAY_BYTE && kind != SET_BYTE && kind != PUSH_BYTE {
+            return Err(ParseError::InvalidValue(kind));
+        }
+        let (n, len) = read_len(buf, 1, ParseOptions::default())?;
+        if len < 0 {
+            if kind != ARRAY_BYTE {
+                return Err(ParseError::InvalidValue(kind));
+            }
+            return Ok(None);
+        }
+        let len = to_usize(len)?;
+        let parser = ArrayParser {
+            kind,
+            len,
+            remaining: len,
+        };
+        Ok(Some((n + 1, parser)))
+    }
+
+    /// Returns the type byte of the aggregate, such as `*` for an array.
+    pub fn kind(&self) -> u8 {
+        self.kind
+    }
+
+    /// Returns the number of elements in the aggregate.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of elements which haven't been parsed yet.
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// Parses the element at the start of `buf`, returning it along with the number of bytes it
+    /// took up, or `None` once every element has been parsed.
+    ///
+    /// On an error, such as `Incomplete`, the parser stays where it was, so the element can be
+    /// parsed again once more bytes arrive.
+    pub fn next_element<'b>(
+        &mut self,
+        buf: &'b [u8],
+    ) -> Result<Option<(usize, RESP<'b>)>, ParseError> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        let (n, resp) = parse_offset(buf, 0, ParseOptions::default())?;
+        self.remaining -= 1;
+        Ok(Some((n, resp)))
+    }
+
+    /// Iterates over the rest of the elements in `buf`, which starts at the first of them,
+    /// yielding each along with its byte range in `buf`.
+    pub fn elements(self, buf: &[u8]) -> Elements<'_> {
+        Elements {
+            parser: self,
+            buf,
+            offset: 0,
+        }
+    }
+}
+
+/// The elements of an aggregate, from `ArrayParser::elements`.
+pub struct Elements<'b> {
+    parser: ArrayParser,
+    buf: &'b [u8],
+    offset: usize,
+}
+
+impl<'b> Elements<'b> {
+    /// Returns the number of bytes parsed so far.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl<'b> Iterator for Elements<'b> {
+    type Item = Result<(Range<usize>, RESP<'b>), ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.offset;
+        match self.parser.next_element(&self.buf[start..]) {
+            Ok(Some((n, resp))) => {
+                self.offset += n;
+                Some(Ok((start..self.offset, resp)))
+            }
+            Ok(None) => None,
+            Err(err) => {
+                // Stop rather than failing on the same element forever.
+                self.parser.remaining = 0;
+                Some(Err(err))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.parser.remaining))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow::Borrowed;
+
+    #[test]
+    fn test_array_parser() {
+        let buf = b"*3\r\n$3\r\nfoo\r\n:1\r\n*1\r\n+OK\r\n";
+        let (n, parser) = ArrayParser::new(buf).unwrap().unwrap();
+        assert_eq!((n, parser.len(), parser.kind()), (4, 3, b'*'));
+        let elements: Vec<_> = parser.elements(&buf[n..]).map(Result::unwrap).collect();
+        assert_eq!(
+            elements,
+            vec![
+                (0..9, RESP::BulkString(Borrowed("foo"))),
+                (9..13, RESP::Integer(1)),
+                (
+                    13..22,
+                    RESP::Array(vec![RESP::SimpleString(Borrowed("OK"))])
+                ),
+            ]
+        );
+
+        // Elements can be parsed from separate buffers as they arrive.
+        let (_, mut parser) = ArrayParser::new(b"~2\r\n").unwrap().unwrap();
+        assert_eq!(parser.next_element(b":1"), Err(ParseError::CLRFNotFound));
+        assert_eq!(parser.remaining(), 2);
+        assert_eq!(
+            parser.next_element(b":1\r\n:2"),
+            Ok(Some((4, RESP::Integer(1))))
+        );
+        assert_eq!(
+            parser.next_element(b":2\r\n"),
+            Ok(Some((4, RESP::Integer(2))))
+        );
+        assert_eq!(parser.next_element(b":3\r\n"), Ok(None));
+
+        assert_eq!(ArrayParser::new(b"*-1\r\n"), Ok(None));
+        assert_eq!(ArrayParser::new(b"*2"), Err(ParseError::CLRFNotFound));
+        assert_eq!(
+            ArrayParser::new(b"+OK\r\n"),
+            Err(ParseError::InvalidValue(b'+'))
+        );
+    }
+}
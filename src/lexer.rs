@@ -0,0 +1,189 @@
+//! A pull parser which splits a buffer into tokens rather than building `RESP` objects.
+//!
+//! Aggregates are reported by a start token holding their length, followed by the tokens of
+//! their elements, so consumers can build their own representations or transform frames as they
+//! go. Strings are left as bytes and only checked to be UTF-8 if a consumer wants them as text.
+use crate::{
+    check_big_number, check_int, read_blob_bytes, read_len, read_line_bytes, to_str, to_usize,
+    ParseError, ParseOptions, ARRAY_BYTE, ATTRIBUTE_BYTE, BIG_NUMBER_BYTE, BLOB_ERROR_BYTE,
+    BOOLEAN_BYTE, BULK_STRING_BYTE, DOUBLE_BYTE, ERROR_BYTE, INTEGER_BYTE, MAP_BYTE, NULL_BYTE,
+    PUSH_BYTE, SET_BYTE, SIMPLE_STRING_BYTE, VERBATIM_STRING_BYTE,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Token<'a> {
+    Simple(&'a [u8]),
+    Error(&'a [u8]),
+    Int(i64),
+    Bulk(&'a [u8]),
+    NullBulk,
+    /// The start of an array of this many elements.
+    ArrayStart(usize),
+    NullArray,
+    Null,
+    Boolean(bool),
+    Double(f64),
+    BigNumber(&'a [u8]),
+    BlobError(&'a [u8]),
+    /// A verbatim string's format and text.
+    Verbatim(&'a [u8], &'a [u8]),
+    /// The start of a map of this many pairs, which are followed by twice as many elements.
+    MapStart(usize),
+    SetStart(usize),
+    /// The start of this many attribute pairs, which are followed by the value they describe.
+    AttributeStart(usize),
+    PushStart(usize),
+}
+
+/// Produces the tokens of the frames in a buffer, in order.
+pub struct Lexer<'a> {
+    buf: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(buf: &'a [u8]) -> Lexer<'a> {
+        Lexer { buf, offset: 0 }
+    }
+
+    /// Returns the number of bytes tokenized so far.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the next token, or `None` at the end of the buffer.
+    ///
+    /// On an error, such as `Incomplete`, the lexer stays where it was.
+    pub fn next_token(&mut self) -> Result<Option<Token<'a>>, ParseError> {
+        if self.offset == self.buf.len() {
+            return Ok(None);
+        }
+        let (n, token) = self.read_token()?;
+        self.offset += n;
+        Ok(Some(token))
+    }
+
+    fn read_token(&self) -> Result<(usize, Token<'a>), ParseError> {
+        let options = ParseOptions::default();
+        let (buf, offset) = (self.buf, self.offset + 1);
+        let kind = buf[self.offset];
+        let line = || read_line_bytes(buf, offset, options);
+        let len = || -> Result<(usize, usize), ParseError> {
+            match read_len(buf, offset, options)? {
+                (n, len) if len >= 0 => Ok((n, to_usize(len)?)),
+                _ => Err(ParseError::InvalidValue(kind)),
+            }
+        };
+        let (n, token) = match kind {
+            SIMPLE_STRING_BYTE => line().map(|(n, l)| (n, Token::Simple(l)))?,
+            ERROR_BYTE => line().map(|(n, l)| (n, Token::Error(l)))?,
+            BIG_NUMBER_BYTE => {
+                let (n, l) = line()?;
+                check_big_number(l)?;
+                (n, Token::BigNumber(l))
+            }
+            INTEGER_BYTE => {
+                let (n, l) = line()?;
+                check_int(l, kind, options)?;
+                let int = to_str(l, options)?
+                    .parse()
+                    .map_err(ParseError::ParseIntError)?;
+                (n, Token::Int(int))
+            }
+            DOUBLE_BYTE => {
+                let (n, l) = line()?;
+                let d = to_str(l, options)?
+                    .parse()
+                    .map_err(ParseError::ParseFloatError)?;
+                (n, Token::Double(d))
+            }
+            NULL_BYTE => match line()? {
+                (n, b"") => (n, Token::Null),
+                _ => return Err(ParseError::InvalidValue(kind)),
+            },
+            BOOLEAN_BYTE => match line()? {
+                (n, b"t") => (n, Token::Boolean(true)),
+                (n, b"f") => (n, Token::Boolean(false)),
+                _ => return Err(ParseError::InvalidValue(kind)),
+            },
+            BULK_STRING_BYTE => match read_blob_bytes(buf, offset, options)? {
+                (n, Some(bytes)) => (n, Token::Bulk(bytes)),
+                (n, None) => (n, Token::NullBulk),
+            },
+            BLOB_ERROR_BYTE => match read_blob_bytes(buf, offset, options)? {
+                (n, Some(bytes)) => (n, Token::BlobError(bytes)),
+                (_, None) => return Err(ParseError::InvalidValue(kind)),
+            },
+            VERBATIM_STRING_BYTE => match read_blob_bytes(buf, offset, options)? {
+                (n, Some(bytes)) if bytes.len() >= 4 && bytes[3] == b':' => {
+                    (n, Token::Verbatim(&bytes[..3], &bytes[4..]))
+                }
+                _ => return Err(ParseError::InvalidValue(kind)),
+            },
+            ARRAY_BYTE => match read_len(buf, offset, options)? {
+                (n, len) if len < 0 => (n, Token::NullArray),
+                (n, len) => (n, Token::ArrayStart(to_usize(len)?)),
+            },
+            MAP_BYTE => len().map(|(n, len)| (n, Token::MapStart(len)))?,
+            SET_BYTE => len().map(|(n, len)| (n, Token::SetStart(len)))?,
+            ATTRIBUTE_BYTE => len().map(|(n, len)| (n, Token::AttributeStart(len)))?,
+            PUSH_BYTE => len().map(|(n, len)| (n, Token::PushStart(len)))?,
+            b => return Err(ParseError::UnknownByte(b)),
+        };
+        Ok((n + 1, token))
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token<'a>, ParseError>;
+
+    /// Returns the next token, ending at the end of the buffer or after the first error.
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_token() {
+            Ok(token) => token.map(Ok),
+            Err(err) => {
+                self.offset = self.buf.len();
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lexer() {
+        let buf =
+            b"*3\r\n$3\r\nfoo\r\n:-1\r\n%1\r\n+k\r\n_\r\n|1\r\n#t\r\n,1.5\r\n=7\r\ntxt:abc\r\n";
+        let tokens: Vec<_> = Lexer::new(buf).map(Result::unwrap).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::ArrayStart(3),
+                Token::Bulk(b"foo"),
+                Token::Int(-1),
+                Token::MapStart(1),
+                Token::Simple(b"k"),
+                Token::Null,
+                Token::AttributeStart(1),
+                Token::Boolean(true),
+                Token::Double(1.5),
+                Token::Verbatim(b"txt", b"abc"),
+            ]
+        );
+
+        let mut lexer = Lexer::new(b"$-1\r\n*-1\r\n$5\r\nab");
+        assert_eq!(lexer.next_token(), Ok(Some(Token::NullBulk)));
+        assert_eq!(lexer.next_token(), Ok(Some(Token::NullArray)));
+        assert_eq!(lexer.next_token(), Err(ParseError::Incomplete));
+        assert_eq!(lexer.offset(), 10);
+        assert_eq!(lexer.next(), Some(Err(ParseError::Incomplete)));
+        assert_eq!(lexer.next(), None);
+
+        // Strings aren't required to be UTF-8.
+        let tokens: Vec<_> = Lexer::new(b"+\xff\r\n").collect();
+        assert_eq!(tokens, vec![Ok(Token::Simple(b"\xff"))]);
+    }
+}
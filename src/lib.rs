@@ -1,17 +1,72 @@
 //! This is an implementation of Redis's RESP 2.0 protocol (as defined by [the
-//! spec](https://redis.io/topics/protocol)).
+//! spec](https://redis.io/topics/protocol)), along with the types added by
+//! [RESP3](https://github.com/redis/redis-specification/blob/master/protocol/RESP3.md).
 //!
 //! Benefits:
 //! - Parsing is fast by avoiding unnecessary copies.
 //! - All failures are returned as explicit errors.
 //!
 //! Issues:
-//! - Parser expects full RESP message and returns errors for incomplete messages.
-use std::borrow::Cow::{self, Borrowed};
+//! - Parser expects full RESP message and returns errors for incomplete messages. Use
+//!   `decoder::Decoder` to buffer messages which arrive in pieces.
+//!
+//! The crate contains no unsafe code unless the `unsafe-fast-paths` feature is enabled, which
+//! allows optimizations that rely on it, each of which must opt in with `allow(unsafe_code)`.
+#![cfg_attr(not(feature = "unsafe-fast-paths"), forbid(unsafe_code))]
+#![cfg_attr(feature = "unsafe-fast-paths", deny(unsafe_code))]
+pub use convert::ToResp;
+pub use diff::{diff, DiffEntry};
+use lexer::Token;
+use std::borrow::Cow::{self, Borrowed, Owned};
+use std::convert::TryFrom;
+use std::io;
+use std::mem;
 use std::num;
 use std::str;
 
-#[derive(Debug, PartialEq)]
+pub mod aof;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod cli;
+pub mod connection;
+pub mod consts;
+pub mod convert;
+pub mod decoder;
+pub mod diff;
+pub mod downgrade;
+pub mod error_code;
+pub mod generator;
+pub mod header;
+pub mod lazy;
+pub mod lexer;
+pub mod loadgen;
+pub mod mapped;
+pub mod metrics;
+pub mod multiplexer;
+pub mod pipelined;
+pub mod protocol;
+mod query;
+pub mod record;
+pub mod replication;
+pub mod replies;
+mod rng;
+mod semantic;
+pub mod sentinel;
+pub mod server;
+pub mod shared;
+pub mod snapshot;
+pub mod span;
+pub mod subscriber;
+pub mod testing;
+pub mod trace;
+pub mod tracking;
+pub mod transcode;
+#[cfg(any(test, feature = "test-vectors"))]
+pub mod vectors;
+pub mod visitor;
+pub mod writer;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum RESP<'a> {
     SimpleString(Cow<'a, str>),
     Error(Cow<'a, str>),
@@ -20,124 +75,1194 @@ pub enum RESP<'a> {
     NullBulkString,
     Array(Vec<RESP<'a>>),
     NullArray,
+    // The types below were added by RESP3.
+    Null,
+    Boolean(bool),
+    Double(f64),
+    /// An integer too large for an `i64`, kept in its decimal form.
+    BigNumber(Cow<'a, str>),
+    /// An error which, unlike `Error`, may contain any characters.
+    BlobError(Cow<'a, str>),
+    /// A string along with its three character format, such as `txt` or `mkd`.
+    VerbatimString(Cow<'a, str>, Cow<'a, str>),
+    /// Key value pairs, in the order they were sent.
+    Map(Vec<(RESP<'a>, RESP<'a>)>),
+    Set(Vec<RESP<'a>>),
+    /// Auxiliary key value pairs describing the value which follows them.
+    Attribute(Vec<(RESP<'a>, RESP<'a>)>, Box<RESP<'a>>),
+    /// Out of band data, such as pub/sub messages, which doesn't answer a request.
+    Push(Vec<RESP<'a>>),
+}
+
+/// A RESP object which owns all of its strings.
+pub type RESPOwned = RESP<'static>;
+
+/// The type of a RESP frame, as given by its first byte.
+///
+/// Null bulk strings and null arrays are the bulk string and array types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RespType {
+    SimpleString,
+    Error,
+    Integer,
+    BulkString,
+    Array,
+    Null,
+    Boolean,
+    Double,
+    BigNumber,
+    BlobError,
+    VerbatimString,
+    Map,
+    Set,
+    Attribute,
+    Push,
+}
+
+impl RespType {
+    /// Returns the type starting with `byte`, or `None` if no type does.
+    pub fn from_byte(byte: u8) -> Option<RespType> {
+        let kind = match byte {
+            SIMPLE_STRING_BYTE => RespType::SimpleString,
+            ERROR_BYTE => RespType::Error,
+            INTEGER_BYTE => RespType::Integer,
+            BULK_STRING_BYTE => RespType::BulkString,
+            ARRAY_BYTE => RespType::Array,
+            NULL_BYTE => RespType::Null,
+            BOOLEAN_BYTE => RespType::Boolean,
+            DOUBLE_BYTE => RespType::Double,
+            BIG_NUMBER_BYTE => RespType::BigNumber,
+            BLOB_ERROR_BYTE => RespType::BlobError,
+            VERBATIM_STRING_BYTE => RespType::VerbatimString,
+            MAP_BYTE => RespType::Map,
+            SET_BYTE => RespType::Set,
+            ATTRIBUTE_BYTE => RespType::Attribute,
+            PUSH_BYTE => RespType::Push,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    /// Returns whether the type was added by RESP3, so RESP2 peers don't understand it.
+    pub fn is_resp3(self) -> bool {
+        !matches!(
+            self,
+            RespType::SimpleString
+                | RespType::Error
+                | RespType::Integer
+                | RespType::BulkString
+                | RespType::Array
+        )
+    }
+
+    /// Returns the byte which frames of this type start with.
+    pub fn byte(self) -> u8 {
+        match self {
+            RespType::SimpleString => SIMPLE_STRING_BYTE,
+            RespType::Error => ERROR_BYTE,
+            RespType::Integer => INTEGER_BYTE,
+            RespType::BulkString => BULK_STRING_BYTE,
+            RespType::Array => ARRAY_BYTE,
+            RespType::Null => NULL_BYTE,
+            RespType::Boolean => BOOLEAN_BYTE,
+            RespType::Double => DOUBLE_BYTE,
+            RespType::BigNumber => BIG_NUMBER_BYTE,
+            RespType::BlobError => BLOB_ERROR_BYTE,
+            RespType::VerbatimString => VERBATIM_STRING_BYTE,
+            RespType::Map => MAP_BYTE,
+            RespType::Set => SET_BYTE,
+            RespType::Attribute => ATTRIBUTE_BYTE,
+            RespType::Push => PUSH_BYTE,
+        }
+    }
+}
+
+impl<'a> RESP<'a> {
+    /// Builds a command, an array of bulk strings, from its name and arguments.
+    pub fn command<I, S>(args: I) -> RESP<'a>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<Cow<'a, str>>,
+    {
+        RESP::Array(
+            args.into_iter()
+                .map(|arg| RESP::BulkString(arg.into()))
+                .collect(),
+        )
+    }
+
+    /// Converts into a RESP object which no longer borrows from the buffer it was parsed from.
+    pub fn into_owned(self) -> RESPOwned {
+        match self {
+            RESP::SimpleString(s) => RESP::SimpleString(Owned(s.into_owned())),
+            RESP::Error(s) => RESP::Error(Owned(s.into_owned())),
+            RESP::Integer(i) => RESP::Integer(i),
+            RESP::BulkString(s) => RESP::BulkString(Owned(s.into_owned())),
+            RESP::NullBulkString => RESP::NullBulkString,
+            RESP::Array(arr) => RESP::Array(arr.into_iter().map(RESP::into_owned).collect()),
+            RESP::NullArray => RESP::NullArray,
+            RESP::Null => RESP::Null,
+            RESP::Boolean(b) => RESP::Boolean(b),
+            RESP::Double(d) => RESP::Double(d),
+            RESP::BigNumber(s) => RESP::BigNumber(Owned(s.into_owned())),
+            RESP::BlobError(s) => RESP::BlobError(Owned(s.into_owned())),
+            RESP::VerbatimString(format, s) => {
+                RESP::VerbatimString(Owned(format.into_owned()), Owned(s.into_owned()))
+            }
+            RESP::Map(pairs) => RESP::Map(pairs_into_owned(pairs)),
+            RESP::Set(set) => RESP::Set(set.into_iter().map(RESP::into_owned).collect()),
+            RESP::Attribute(attrs, resp) => {
+                RESP::Attribute(pairs_into_owned(attrs), Box::new(resp.into_owned()))
+            }
+            RESP::Push(arr) => RESP::Push(arr.into_iter().map(RESP::into_owned).collect()),
+        }
+    }
+
+    /// Returns the type of the object's frame.
+    pub fn kind(&self) -> RespType {
+        match self {
+            RESP::SimpleString(_) => RespType::SimpleString,
+            RESP::Error(_) => RespType::Error,
+            RESP::Integer(_) => RespType::Integer,
+            RESP::BulkString(_) | RESP::NullBulkString => RespType::BulkString,
+            RESP::Array(_) | RESP::NullArray => RespType::Array,
+            RESP::Null => RespType::Null,
+            RESP::Boolean(_) => RespType::Boolean,
+            RESP::Double(_) => RespType::Double,
+            RESP::BigNumber(_) => RespType::BigNumber,
+            RESP::BlobError(_) => RespType::BlobError,
+            RESP::VerbatimString(_, _) => RespType::VerbatimString,
+            RESP::Map(_) => RespType::Map,
+            RESP::Set(_) => RespType::Set,
+            RESP::Attribute(_, _) => RespType::Attribute,
+            RESP::Push(_) => RespType::Push,
+        }
+    }
+
+    /// Returns the number of bytes `dump` writes when encoding this object.
+    pub fn encoded_len(&self) -> usize {
+        self.encoded_len_with(DumpOptions::default())
+    }
+
+    /// Returns the number of bytes `dump_with` writes when encoding this object with `options`.
+    pub fn encoded_len_with(&self, options: DumpOptions) -> usize {
+        let len = |resp: &RESP| resp.encoded_len_with(options);
+        match self {
+            RESP::SimpleString(s)
+                if options.promote_invalid_simple_strings && has_line_break(s) =>
+            {
+                blob_len(s.len())
+            }
+            RESP::SimpleString(s) | RESP::Error(s) | RESP::BigNumber(s) => s.len() + 3,
+            RESP::Integer(i) => i.to_string().len() + 3,
+            RESP::BulkString(s) | RESP::BlobError(s) => blob_len(s.len()),
+            RESP::NullBulkString | RESP::NullArray => 5,
+            RESP::Array(arr) | RESP::Set(arr) | RESP::Push(arr) => {
+                aggregate_len(arr.len(), arr.iter().map(len).sum())
+            }
+            RESP::Null => 3,
+            RESP::Boolean(_) => 4,
+            RESP::Double(d) => format_double(*d).len() + 3,
+            RESP::VerbatimString(format, s) => blob_len(format.len() + 1 + s.len()),
+            RESP::Map(pairs) => aggregate_len(pairs.len(), pairs_len(pairs, options)),
+            RESP::Attribute(attrs, resp) => {
+                aggregate_len(attrs.len(), pairs_len(attrs, options)) + len(resp)
+            }
+        }
+    }
+
+    /// Estimates the memory the object takes up in bytes, including what its strings and
+    /// aggregates own on the heap, so that caches can budget by size.
+    ///
+    /// Borrowed strings only count as the object they're in, since their buffer is owned
+    /// elsewhere.
+    pub fn approx_mem_usage(&self) -> usize {
+        mem::size_of::<RESP>() + self.heap_usage()
+    }
+
+    fn heap_usage(&self) -> usize {
+        let string = |s: &Cow<str>| match s {
+            Borrowed(_) => 0,
+            Owned(s) => s.capacity(),
+        };
+        let elements = |elements: &Vec<RESP>| {
+            elements.capacity() * mem::size_of::<RESP>()
+                + elements.iter().map(RESP::heap_usage).sum::<usize>()
+        };
+        let pairs = |pairs: &Vec<(RESP, RESP)>| {
+            pairs.capacity() * mem::size_of::<(RESP, RESP)>()
+                + pairs
+                    .iter()
+                    .map(|(k, v)| k.heap_usage() + v.heap_usage())
+                    .sum::<usize>()
+        };
+        match self {
+            RESP::SimpleString(s)
+            | RESP::Error(s)
+            | RESP::BulkString(s)
+            | RESP::BigNumber(s)
+            | RESP::BlobError(s) => string(s),
+            RESP::VerbatimString(format, s) => string(format) + string(s),
+            RESP::Array(arr) | RESP::Set(arr) | RESP::Push(arr) => elements(arr),
+            RESP::Map(map) => pairs(map),
+            RESP::Attribute(attrs, resp) => pairs(attrs) + resp.approx_mem_usage(),
+            RESP::Integer(_)
+            | RESP::NullBulkString
+            | RESP::NullArray
+            | RESP::Null
+            | RESP::Boolean(_)
+            | RESP::Double(_) => 0,
+        }
+    }
+}
+
+fn pairs_into_owned(pairs: Vec<(RESP, RESP)>) -> Vec<(RESPOwned, RESPOwned)> {
+    pairs
+        .into_iter()
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect()
+}
+
+fn blob_len(len: usize) -> usize {
+    len.to_string().len() + len + 5
+}
+
+fn aggregate_len(count: usize, elements_len: usize) -> usize {
+    count.to_string().len() + 3 + elements_len
+}
+
+fn pairs_len(pairs: &[(RESP, RESP)], options: DumpOptions) -> usize {
+    pairs
+        .iter()
+        .map(|(k, v)| k.encoded_len_with(options) + v.encoded_len_with(options))
+        .sum()
+}
+
+/// Formats a double the way RESP3 spells it, which only differs from Rust for NaN.
+pub(crate) fn format_double(d: f64) -> String {
+    if d.is_nan() {
+        "nan".to_string()
+    } else {
+        d.to_string()
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub enum ParseError {
     UnknownByte(u8),
     CLRFNotFound,
+    /// The buffer ends before the RESP object does.
+    Incomplete,
     Utf8Error(str::Utf8Error),
     ParseIntError(num::ParseIntError),
+    ParseFloatError(num::ParseFloatError),
+    /// The value doesn't fit the type given by this byte, such as a boolean other than `t` or `f`.
+    InvalidValue(u8),
+    /// A declared length doesn't fit in a `usize`, or the frame would end past `usize::MAX`.
+    LengthOverflow,
+}
+
+// The first byte of each type of frame.
+pub const SIMPLE_STRING_BYTE: u8 = b'+';
+pub const ERROR_BYTE: u8 = b'-';
+pub const INTEGER_BYTE: u8 = b':';
+pub const BULK_STRING_BYTE: u8 = b'$';
+pub const ARRAY_BYTE: u8 = b'*';
+pub const NULL_BYTE: u8 = b'_';
+pub const BOOLEAN_BYTE: u8 = b'#';
+pub const DOUBLE_BYTE: u8 = b',';
+pub const BIG_NUMBER_BYTE: u8 = b'(';
+pub const BLOB_ERROR_BYTE: u8 = b'!';
+pub const VERBATIM_STRING_BYTE: u8 = b'=';
+pub const MAP_BYTE: u8 = b'%';
+pub const SET_BYTE: u8 = b'~';
+pub const ATTRIBUTE_BYTE: u8 = b'|';
+pub const PUSH_BYTE: u8 = b'>';
+
+/// Options which relax how strictly `parse_with` accepts its input.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ParseOptions {
+    /// Replaces invalid UTF-8 in strings with U+FFFD rather than failing with `Utf8Error`.
+    pub lossy_utf8: bool,
+    /// Accepts a bare `\n` wherever a line should end with `\r\n`, as some hand written clients
+    /// send.
+    pub lenient_line_endings: bool,
+    /// Parses integers too large for an `i64`, as some modules send, into a `BigNumber` rather
+    /// than failing with `ParseIntError`.
+    pub big_integers: bool,
+    /// Accepts integers and lengths in any form Rust's integer parsing does, such as `+5` or
+    /// `007`, rather than only the `0` or `-?[1-9][0-9]*` which Redis sends.
+    pub lenient_integers: bool,
+    /// Skips checking that strings are UTF-8, which dominates parsing large values, when set
+    /// to the promise that they are.
+    pub trusted_utf8: Option<TrustedUtf8>,
 }
 
-const SIMPLE_STRING_BYTE: u8 = b'+';
-const ERROR_BYTE: u8 = b'-';
-const INTEGER_BYTE: u8 = b':';
-const BULK_STRING_BYTE: u8 = b'$';
-const ARRAY_BYTE: u8 = b'*';
+/// A promise that every string in every buffer parsed is valid UTF-8, such as when the peer is
+/// trusted to only send text. See `ParseOptions::trusted_utf8`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrustedUtf8(());
+
+#[cfg(feature = "unsafe-fast-paths")]
+impl TrustedUtf8 {
+    /// # Safety
+    ///
+    /// Parsing anything other than UTF-8 strings with options holding the promise is undefined
+    /// behavior.
+    #[allow(unsafe_code)]
+    pub unsafe fn new() -> TrustedUtf8 {
+        TrustedUtf8(())
+    }
+}
 
 /// Parses a RESP object from a buffer, returning the number of bytes read.
-pub fn parse(buf: &[u8]) -> Result<(usize, RESP), ParseError> {
-    parse_offset(&buf, 0)
+pub fn parse(buf: &[u8]) -> Result<(usize, RESP<'_>), ParseError> {
+    parse_with(buf, ParseOptions::default())
+}
+
+/// Parses a RESP object like `parse`, but as relaxed by `options`.
+pub fn parse_with(buf: &[u8], options: ParseOptions) -> Result<(usize, RESP<'_>), ParseError> {
+    parse_offset(buf, 0, options)
+}
+
+/// Parses a RESP object from a buffer made up of `head` followed by `tail`, such as the two
+/// halves of a ring buffer that has wrapped, returning the number of bytes read from both.
+///
+/// Objects wholly within `head` borrow from it. Only an object straddling the two slices is
+/// copied, so the ring buffer never has to be compacted, and its strings are owned.
+pub fn parse_split<'a>(head: &'a [u8], tail: &[u8]) -> Result<(usize, RESP<'a>), ParseError> {
+    match parse(head) {
+        Err(ParseError::Incomplete) | Err(ParseError::CLRFNotFound) if !tail.is_empty() => {}
+        result => return result,
+    }
+    // Copy more and more of `tail` until the frame fits, so straddling frames cost time in
+    // proportion to their own size rather than the buffer's.
+    let mut joined = head.to_vec();
+    let mut copied = 0;
+    loop {
+        let more = (2 * copied).max(64).min(tail.len() - copied);
+        joined.extend_from_slice(&tail[copied..copied + more]);
+        copied += more;
+        match parse(&joined) {
+            Ok((n, resp)) => return Ok((n, resp.into_owned())),
+            Err(ParseError::Incomplete) | Err(ParseError::CLRFNotFound) if copied < tail.len() => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Advances over one frame at the start of `buf` without building it, returning the number of
+/// bytes it takes up.
+///
+/// Nothing is allocated, which makes it cheap to skip elements when only a few are wanted.
+/// Strings aren't checked to be UTF-8.
+pub fn skip_value(buf: &[u8]) -> Result<usize, ParseError> {
+    let mut lexer = lexer::Lexer::new(buf);
+    // The number of values left to skip, including the elements of aggregates already started.
+    let mut pending: usize = 1;
+    while pending > 0 {
+        let token = lexer.next_token()?.ok_or(ParseError::Incomplete)?;
+        pending -= 1;
+        let children = match token {
+            Token::ArrayStart(n) | Token::SetStart(n) | Token::PushStart(n) => n,
+            Token::MapStart(n) => n.saturating_mul(2),
+            Token::AttributeStart(n) => n.saturating_mul(2).saturating_add(1),
+            _ => 0,
+        };
+        pending = pending.saturating_add(children);
+    }
+    Ok(lexer.offset())
+}
+
+/// Returns the type byte of the frame at the start of `buf`, such as `$` for a bulk string,
+/// without parsing the rest of it.
+pub fn peek_type(buf: &[u8]) -> Result<u8, ParseError> {
+    let kind = *buf.first().ok_or(ParseError::Incomplete)?;
+    match RespType::from_byte(kind) {
+        Some(_) => Ok(kind),
+        None => Err(ParseError::UnknownByte(kind)),
+    }
+}
+
+/// Returns the length declared by the header of the frame at the start of `buf` without parsing
+/// the rest of it, or `None` for types which don't declare one.
+///
+/// This is the number of bytes of a string, or the number of elements of an aggregate (pairs, for
+/// maps and attributes). Nulls declare `-1`.
+pub fn peek_len(buf: &[u8]) -> Result<Option<i64>, ParseError> {
+    match peek_type(buf)? {
+        BULK_STRING_BYTE | BLOB_ERROR_BYTE | VERBATIM_STRING_BYTE | ARRAY_BYTE | MAP_BYTE
+        | SET_BYTE | ATTRIBUTE_BYTE | PUSH_BYTE => {
+            let (_, len) = read_len(buf, 1, ParseOptions::default())?;
+            Ok(Some(len))
+        }
+        _ => Ok(None),
+    }
+}
+
+pub(crate) fn parse_offset(
+    buf: &[u8],
+    offset: usize,
+    options: ParseOptions,
+) -> Result<(usize, RESP<'_>), ParseError> {
+    parse_traced(buf, offset, options, &mut None)
+}
+
+/// Parses a RESP object like `parse_offset`, setting `failed` to the type byte of the innermost
+/// object which failed to parse, if any did.
+pub(crate) fn parse_traced<'a>(
+    buf: &'a [u8],
+    offset: usize,
+    options: ParseOptions,
+    failed: &mut Option<u8>,
+) -> Result<(usize, RESP<'a>), ParseError> {
+    let kind = *buf.get(offset).ok_or(ParseError::Incomplete)?;
+    let result = parse_kind(buf, offset, kind, options, failed);
+    if result.is_err() && failed.is_none() {
+        *failed = Some(kind);
+    }
+    result
 }
 
-fn parse_offset(buf: &[u8], offset: usize) -> Result<(usize, RESP), ParseError> {
-    match buf[offset] {
+fn parse_kind<'a>(
+    buf: &'a [u8],
+    offset: usize,
+    kind: u8,
+    options: ParseOptions,
+    failed: &mut Option<u8>,
+) -> Result<(usize, RESP<'a>), ParseError> {
+    match kind {
         SIMPLE_STRING_BYTE => {
-            let (n, line) = read_line(buf, offset + 1)?;
-            Ok((n + 1, RESP::SimpleString(Borrowed(line))))
+            let (n, line) = read_line(buf, offset + 1, options)?;
+            Ok((n + 1, RESP::SimpleString(line)))
         }
         ERROR_BYTE => {
-            let (n, line) = read_line(buf, offset + 1)?;
-            Ok((n + 1, RESP::Error(Borrowed(line))))
+            let (n, line) = read_line(buf, offset + 1, options)?;
+            Ok((n + 1, RESP::Error(line)))
         }
         INTEGER_BYTE => {
-            let (n, line) = read_line(buf, offset + 1)?;
-            let int: i64 = line.parse().map_err(ParseError::ParseIntError)?;
-            Ok((n + 1, RESP::Integer(int)))
-        }
-        BULK_STRING_BYTE => {
-            let (n, line) = read_line(buf, offset + 1)?;
-            let len: i64 = line.parse().map_err(ParseError::ParseIntError)?;
-            if len < 0 {
-                return Ok((n + 1, RESP::NullBulkString));
+            let (n, line) = read_line(buf, offset + 1, options)?;
+            check_int(line.as_bytes(), kind, options)?;
+            match line.parse() {
+                Ok(int) => Ok((n + 1, RESP::Integer(int))),
+                Err(err) if options.big_integers && is_overflow(&err) => {
+                    Ok((n + 1, RESP::BigNumber(line)))
+                }
+                Err(err) => Err(ParseError::ParseIntError(err)),
             }
-            let s = str::from_utf8(&buf[offset + n + 1..offset + n + 1 + len as usize])
-                .map_err(ParseError::Utf8Error)?;
-            Ok((n + 1 + len as usize + 2, RESP::BulkString(Borrowed(s))))
         }
+        BULK_STRING_BYTE => match read_blob(buf, offset + 1, options)? {
+            (n, Some(s)) => Ok((n + 1, RESP::BulkString(s))),
+            (n, None) => Ok((n + 1, RESP::NullBulkString)),
+        },
         ARRAY_BYTE => {
-            let (n, line) = read_line(buf, offset + 1)?;
-            let len: i64 = line.parse().map_err(ParseError::ParseIntError)?;
+            let (n, len) = read_len(buf, offset + 1, options)?;
             if len < 0 {
                 return Ok((n + 1, RESP::NullArray));
             }
-            let mut arr = Vec::with_capacity(len as usize);
-            let mut m = 0;
-            for _ in 0..len {
-                let (l, resp) = parse_offset(buf, offset + n + 1 + m)?;
-                arr.push(resp);
-                m += l;
-            }
+            let (m, arr) = parse_elements(buf, offset + n + 1, to_usize(len)?, options, failed)?;
             Ok((n + 1 + m, RESP::Array(arr)))
         }
+        NULL_BYTE => {
+            let (n, line) = read_line(buf, offset + 1, options)?;
+            if !line.is_empty() {
+                return Err(ParseError::InvalidValue(kind));
+            }
+            Ok((n + 1, RESP::Null))
+        }
+        BOOLEAN_BYTE => {
+            let (n, line) = read_line(buf, offset + 1, options)?;
+            let b = match &*line {
+                "t" => true,
+                "f" => false,
+                _ => return Err(ParseError::InvalidValue(kind)),
+            };
+            Ok((n + 1, RESP::Boolean(b)))
+        }
+        DOUBLE_BYTE => {
+            let (n, line) = read_line(buf, offset + 1, options)?;
+            let d: f64 = line.parse().map_err(ParseError::ParseFloatError)?;
+            Ok((n + 1, RESP::Double(d)))
+        }
+        BIG_NUMBER_BYTE => {
+            let (n, line) = read_line(buf, offset + 1, options)?;
+            check_big_number(line.as_bytes())?;
+            Ok((n + 1, RESP::BigNumber(line)))
+        }
+        BLOB_ERROR_BYTE => match read_blob(buf, offset + 1, options)? {
+            (n, Some(s)) => Ok((n + 1, RESP::BlobError(s))),
+            (_, None) => Err(ParseError::InvalidValue(kind)),
+        },
+        VERBATIM_STRING_BYTE => match read_blob(buf, offset + 1, options)? {
+            (n, Some(s)) if s.len() >= 4 && s.as_bytes()[3] == b':' => {
+                let (format, text) = match s {
+                    Borrowed(s) => (Borrowed(&s[..3]), Borrowed(&s[4..])),
+                    Owned(s) => (Owned(s[..3].to_string()), Owned(s[4..].to_string())),
+                };
+                Ok((n + 1, RESP::VerbatimString(format, text)))
+            }
+            _ => Err(ParseError::InvalidValue(kind)),
+        },
+        SET_BYTE | PUSH_BYTE => {
+            let (n, len) = read_len(buf, offset + 1, options)?;
+            if len < 0 {
+                return Err(ParseError::InvalidValue(kind));
+            }
+            let (m, arr) = parse_elements(buf, offset + n + 1, to_usize(len)?, options, failed)?;
+            let resp = if kind == SET_BYTE {
+                RESP::Set(arr)
+            } else {
+                RESP::Push(arr)
+            };
+            Ok((n + 1 + m, resp))
+        }
+        MAP_BYTE | ATTRIBUTE_BYTE => {
+            let (n, len) = read_len(buf, offset + 1, options)?;
+            if len < 0 {
+                return Err(ParseError::InvalidValue(kind));
+            }
+            let count = to_usize(len)?
+                .checked_mul(2)
+                .ok_or(ParseError::LengthOverflow)?;
+            let (mut m, elements) = parse_elements(buf, offset + n + 1, count, options, failed)?;
+            let mut pairs = Vec::with_capacity(elements.len() / 2);
+            let mut elements = elements.into_iter();
+            while let (Some(k), Some(v)) = (elements.next(), elements.next()) {
+                pairs.push((k, v));
+            }
+            if kind == MAP_BYTE {
+                return Ok((n + 1 + m, RESP::Map(pairs)));
+            }
+            let (l, resp) = parse_traced(buf, offset + n + 1 + m, options, failed)?;
+            m += l;
+            Ok((n + 1 + m, RESP::Attribute(pairs, Box::new(resp))))
+        }
         b => Err(ParseError::UnknownByte(b)),
     }
 }
 
-fn read_line(buf: &[u8], offset: usize) -> Result<(usize, &str), ParseError> {
+fn is_overflow(err: &num::ParseIntError) -> bool {
+    matches!(
+        err.kind(),
+        num::IntErrorKind::PosOverflow | num::IntErrorKind::NegOverflow
+    )
+}
+
+/// Parses `count` consecutive RESP objects, returning the number of bytes read.
+fn parse_elements<'a>(
+    buf: &'a [u8],
+    offset: usize,
+    count: usize,
+    options: ParseOptions,
+    failed: &mut Option<u8>,
+) -> Result<(usize, Vec<RESP<'a>>), ParseError> {
+    // Every element takes up at least one byte, so don't trust `count` beyond what's buffered.
+    let mut elements = Vec::with_capacity(count.min(buf.len().saturating_sub(offset)));
+    let mut n = 0;
+    for _ in 0..count {
+        let (m, resp) = parse_traced(buf, offset + n, options, failed)?;
+        elements.push(resp);
+        n += m;
+    }
+    Ok((n, elements))
+}
+
+/// Reads the length line which starts bulk strings and aggregates.
+pub(crate) fn read_len(
+    buf: &[u8],
+    offset: usize,
+    options: ParseOptions,
+) -> Result<(usize, i64), ParseError> {
+    let (n, line) = read_line(buf, offset, options)?;
+    // Lengths always follow their type byte.
+    check_int(line.as_bytes(), buf[offset - 1], options)?;
+    let len: i64 = line.parse().map_err(ParseError::ParseIntError)?;
+    Ok((n, len))
+}
+
+/// Reads a length prefixed string, which is `None` when the length is negative.
+fn read_blob(
+    buf: &[u8],
+    offset: usize,
+    options: ParseOptions,
+) -> Result<(usize, Option<Cow<'_, str>>), ParseError> {
+    match read_blob_bytes(buf, offset, options)? {
+        (n, Some(bytes)) => Ok((n, Some(to_str(bytes, options)?))),
+        (n, None) => Ok((n, None)),
+    }
+}
+
+/// Reads a length prefixed string like `read_blob`, without checking it's UTF-8.
+pub(crate) fn read_blob_bytes(
+    buf: &[u8],
+    offset: usize,
+    options: ParseOptions,
+) -> Result<(usize, Option<&[u8]>), ParseError> {
+    let (n, len) = read_len(buf, offset, options)?;
+    if len < 0 {
+        return Ok((n, None));
+    }
+    let start = offset + n;
+    let end = checked_end(start, len)?;
+    let terminator = if options.lenient_line_endings && buf.get(end) == Some(&b'\n') {
+        1
+    } else {
+        2
+    };
+    let frame_end = checked_end(end, terminator)?;
+    if frame_end > buf.len() {
+        return Err(ParseError::Incomplete);
+    }
+    if terminator == 2 && &buf[end..frame_end] != b"\r\n" {
+        // The type byte always comes just before the length.
+        return Err(ParseError::InvalidValue(buf[offset - 1]));
+    }
+    Ok((frame_end - offset, Some(&buf[start..end])))
+}
+
+/// Checks an integer or length of the frame of type `kind` is `0` or `-?[1-9][0-9]*`, unless
+/// `options` allows any integer.
+pub(crate) fn check_int(line: &[u8], kind: u8, options: ParseOptions) -> Result<(), ParseError> {
+    if options.lenient_integers {
+        return Ok(());
+    }
+    let digits = line.strip_prefix(b"-").unwrap_or(line);
+    let valid = match digits {
+        b"0" => digits.len() == line.len(),
+        [b'1'..=b'9', rest @ ..] => rest.iter().all(u8::is_ascii_digit),
+        _ => false,
+    };
+    if !valid {
+        return Err(ParseError::InvalidValue(kind));
+    }
+    Ok(())
+}
+
+/// Checks a big number is `-?[0-9]+`.
+pub(crate) fn check_big_number(line: &[u8]) -> Result<(), ParseError> {
+    let digits = line.strip_prefix(b"-").unwrap_or(line);
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return Err(ParseError::InvalidValue(BIG_NUMBER_BYTE));
+    }
+    Ok(())
+}
+
+/// Converts a non-negative declared length to a `usize`.
+pub(crate) fn to_usize(len: i64) -> Result<usize, ParseError> {
+    usize::try_from(len).map_err(|_| ParseError::LengthOverflow)
+}
+
+/// Returns `start + len`, failing rather than overflowing on hostile lengths.
+fn checked_end(start: usize, len: i64) -> Result<usize, ParseError> {
+    start
+        .checked_add(to_usize(len)?)
+        .ok_or(ParseError::LengthOverflow)
+}
+
+fn read_line(
+    buf: &[u8],
+    offset: usize,
+    options: ParseOptions,
+) -> Result<(usize, Cow<'_, str>), ParseError> {
+    let (n, line) = read_line_bytes(buf, offset, options)?;
+    Ok((n, to_str(line, options)?))
+}
+
+/// Reads a line like `read_line`, without checking it's UTF-8.
+pub(crate) fn read_line_bytes(
+    buf: &[u8],
+    offset: usize,
+    options: ParseOptions,
+) -> Result<(usize, &[u8]), ParseError> {
+    if options.lenient_line_endings {
+        let rest = buf.get(offset..).unwrap_or_default();
+        let end = rest
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or(ParseError::CLRFNotFound)?;
+        let line = rest[..end].strip_suffix(b"\r").unwrap_or(&rest[..end]);
+        return Ok((end + 1, line));
+    }
     let mut current = 0;
     loop {
-        if current + 1 >= buf.len() {
+        if offset + current + 1 >= buf.len() {
             return Err(ParseError::CLRFNotFound);
         }
         if buf[offset + current] == b'\r' && buf[offset + current + 1] == b'\n' {
-            let line =
-                str::from_utf8(&buf[offset..offset + current]).map_err(ParseError::Utf8Error)?;
-            return Ok((current + 2, line));
+            return Ok((current + 2, &buf[offset..offset + current]));
         }
         current += 1;
     }
 }
 
+pub(crate) fn to_str(bytes: &[u8], options: ParseOptions) -> Result<Cow<'_, str>, ParseError> {
+    #[cfg(feature = "unsafe-fast-paths")]
+    if options.trusted_utf8.is_some() {
+        // Safe as `TrustedUtf8` promises every string parsed is UTF-8.
+        #[allow(unsafe_code)]
+        return Ok(Borrowed(unsafe { str::from_utf8_unchecked(bytes) }));
+    }
+    if options.lossy_utf8 {
+        return Ok(String::from_utf8_lossy(bytes));
+    }
+    str::from_utf8(bytes)
+        .map(Borrowed)
+        .map_err(ParseError::Utf8Error)
+}
+
+/// Errors returned when reading or writing RESP objects over IO.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse(ParseError),
+    Dump(DumpError),
+    /// The server sent something other than what the request expects.
+    UnexpectedReply(RESPOwned),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Error {
+        Error::Parse(err)
+    }
+}
+
+impl From<DumpError> for Error {
+    fn from(err: DumpError) -> Error {
+        Error::Dump(err)
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum DumpError {
     BufTooSmall,
+    /// A simple string or error contains `\r` or `\n`, which would end it early.
+    InvalidSimpleString,
+}
+
+/// Options for how `dump_with` encodes values which can't be written as they are.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DumpOptions {
+    /// Writes simple strings containing `\r` or `\n` as bulk strings, and replaces those
+    /// characters in errors with spaces as Redis does, rather than failing with
+    /// `InvalidSimpleString`.
+    pub promote_invalid_simple_strings: bool,
 }
 
 /// Encodes a RESP object to a buffer, returning the numbers of bytes written.
 pub fn dump(resp: &RESP, buf: &mut [u8]) -> Result<usize, DumpError> {
-    dump_offset(resp, buf, 0)
+    dump_with(resp, buf, DumpOptions::default())
+}
+
+/// Encodes a RESP object like `dump`, but as configured by `options`.
+pub fn dump_with(resp: &RESP, buf: &mut [u8], options: DumpOptions) -> Result<usize, DumpError> {
+    dump_offset(resp, buf, 0, options)
+}
+
+/// Encodes a RESP object to a buffer like `dump`, but checks that it fits and can be encoded
+/// before writing anything, so that the buffer is never left holding part of an object.
+pub fn dump_exact(resp: &RESP, buf: &mut [u8]) -> Result<usize, DumpError> {
+    if resp.encoded_len() > buf.len() {
+        return Err(DumpError::BufTooSmall);
+    }
+    check_lines(resp)?;
+    dump(resp, buf)
+}
+
+/// Checks every simple string and error in an object can be written as a line.
+fn check_lines(resp: &RESP) -> Result<(), DumpError> {
+    match resp {
+        RESP::SimpleString(s) | RESP::Error(s) if has_line_break(s) => {
+            Err(DumpError::InvalidSimpleString)
+        }
+        RESP::Array(arr) | RESP::Set(arr) | RESP::Push(arr) => arr.iter().try_for_each(check_lines),
+        RESP::Map(pairs) => pairs
+            .iter()
+            .flat_map(|(k, v)| [k, v])
+            .try_for_each(check_lines),
+        RESP::Attribute(attrs, resp) => attrs
+            .iter()
+            .flat_map(|(k, v)| [k, v])
+            .chain(std::iter::once(&**resp))
+            .try_for_each(check_lines),
+        _ => Ok(()),
+    }
+}
+
+/// A piece of an encoded object, from `dump_slices`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EncodedPart<'a> {
+    /// Headers, line endings and small values, encoded into a buffer.
+    Owned(Vec<u8>),
+    /// The contents of a string, borrowed from the object.
+    Borrowed(&'a [u8]),
+}
+
+impl EncodedPart<'_> {
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            EncodedPart::Owned(bytes) => bytes,
+            EncodedPart::Borrowed(bytes) => bytes,
+        }
+    }
+}
+
+/// Encodes a RESP object as a list of parts which, written in order, are what `dump` writes.
+///
+/// The contents of bulk strings, blob errors and verbatim strings are borrowed rather than
+/// copied, so a vectored write of the parts never copies large values.
+pub fn dump_slices<'a>(resp: &'a RESP) -> Result<Vec<EncodedPart<'a>>, DumpError> {
+    let mut parts = Vec::new();
+    let mut pending = Vec::new();
+    slices(resp, &mut parts, &mut pending)?;
+    if !pending.is_empty() {
+        parts.push(EncodedPart::Owned(pending));
+    }
+    Ok(parts)
+}
+
+fn slices<'a>(
+    resp: &'a RESP,
+    parts: &mut Vec<EncodedPart<'a>>,
+    pending: &mut Vec<u8>,
+) -> Result<(), DumpError> {
+    let header = |pending: &mut Vec<u8>, kind: u8, len: usize| {
+        pending.push(kind);
+        pending.extend_from_slice(len.to_string().as_bytes());
+        pending.extend_from_slice(b"\r\n");
+    };
+    let mut borrow = |pending: &mut Vec<u8>, kind: u8, prefix: &[u8], s: &'a str| {
+        header(pending, kind, prefix.len() + s.len());
+        pending.extend_from_slice(prefix);
+        parts.push(EncodedPart::Owned(mem::take(pending)));
+        parts.push(EncodedPart::Borrowed(s.as_bytes()));
+        pending.extend_from_slice(b"\r\n");
+    };
+    match resp {
+        RESP::BulkString(s) => borrow(pending, BULK_STRING_BYTE, b"", s),
+        RESP::BlobError(s) => borrow(pending, BLOB_ERROR_BYTE, b"", s),
+        RESP::VerbatimString(format, s) => {
+            let prefix = [format.as_bytes(), b":"].concat();
+            borrow(pending, VERBATIM_STRING_BYTE, &prefix, s)
+        }
+        RESP::Array(elements) | RESP::Set(elements) | RESP::Push(elements) => {
+            header(pending, resp.kind().byte(), elements.len());
+            for element in elements {
+                slices(element, parts, pending)?;
+            }
+        }
+        RESP::Map(pairs) | RESP::Attribute(pairs, _) => {
+            header(pending, resp.kind().byte(), pairs.len());
+            for (k, v) in pairs {
+                slices(k, parts, pending)?;
+                slices(v, parts, pending)?;
+            }
+            if let RESP::Attribute(_, value) = resp {
+                slices(value, parts, pending)?;
+            }
+        }
+        scalar => {
+            dump_append(scalar, pending)?;
+        }
+    }
+    Ok(())
+}
+
+/// The most of a string's contents `DumpIter` yields at once.
+const DUMP_CHUNK: usize = 64 * 1024;
+
+/// Encodes a RESP object lazily, as an iterator of parts which, in order, are what `dump`
+/// writes. See `DumpIter`.
+pub fn dump_iter<'a>(resp: &'a RESP<'a>) -> DumpIter<'a> {
+    DumpIter {
+        steps: vec![Step::Value(resp)],
+    }
+}
+
+/// The parts of an encoded object, from `dump_iter`.
+///
+/// Each part is encoded only once it's asked for, and strings are borrowed from the object in
+/// pieces of at most 64 KiB, so a sink can pull a huge reply at the pace it drains.
+pub struct DumpIter<'a> {
+    /// What's left to encode, last first.
+    steps: Vec<Step<'a>>,
+}
+
+enum Step<'a> {
+    Value(&'a RESP<'a>),
+    Bytes(&'a [u8]),
+}
+
+impl<'a> Iterator for DumpIter<'a> {
+    type Item = Result<EncodedPart<'a>, DumpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let resp = match self.steps.pop()? {
+            Step::Bytes(bytes) if bytes.len() > DUMP_CHUNK => {
+                self.steps.push(Step::Bytes(&bytes[DUMP_CHUNK..]));
+                return Some(Ok(EncodedPart::Borrowed(&bytes[..DUMP_CHUNK])));
+            }
+            Step::Bytes(bytes) => return Some(Ok(EncodedPart::Borrowed(bytes))),
+            Step::Value(resp) => resp,
+        };
+        let mut header = Vec::new();
+        let mut push_header = |kind: u8, len: usize| {
+            header.push(kind);
+            header.extend_from_slice(len.to_string().as_bytes());
+            header.extend_from_slice(b"\r\n");
+        };
+        match resp {
+            RESP::BulkString(s) | RESP::BlobError(s) | RESP::VerbatimString(_, s) => {
+                let format = match resp {
+                    RESP::VerbatimString(format, _) => Some(format),
+                    _ => None,
+                };
+                let prefix = format.map_or(0, |f| f.len() + 1);
+                push_header(resp.kind().byte(), prefix + s.len());
+                if let Some(format) = format {
+                    header.extend_from_slice(format.as_bytes());
+                    header.push(b':');
+                }
+                self.steps.push(Step::Bytes(b"\r\n"));
+                self.steps.push(Step::Bytes(s.as_bytes()));
+            }
+            RESP::Array(elements) | RESP::Set(elements) | RESP::Push(elements) => {
+                push_header(resp.kind().byte(), elements.len());
+                self.steps.extend(elements.iter().rev().map(Step::Value));
+            }
+            RESP::Map(pairs) | RESP::Attribute(pairs, _) => {
+                push_header(resp.kind().byte(), pairs.len());
+                if let RESP::Attribute(_, value) = resp {
+                    self.steps.push(Step::Value(value));
+                }
+                for (k, v) in pairs.iter().rev() {
+                    self.steps.push(Step::Value(v));
+                    self.steps.push(Step::Value(k));
+                }
+            }
+            scalar => {
+                if let Err(err) = dump_append(scalar, &mut header) {
+                    self.steps.clear();
+                    return Some(Err(err));
+                }
+            }
+        }
+        Some(Ok(EncodedPart::Owned(header)))
+    }
+}
+
+/// Encodes a frame across a series of fixed size buffers, such as registered `io_uring` buffers,
+/// filling each before moving on to the next.
+pub struct EncodeState<'a> {
+    parts: DumpIter<'a>,
+    /// The part being written, along with how much of it has been written.
+    part: Option<(EncodedPart<'a>, usize)>,
+    /// An error found after some bytes were written, returned by the next write.
+    error: Option<DumpError>,
+    done: bool,
+}
+
+impl<'a> EncodeState<'a> {
+    pub fn new(resp: &'a RESP<'a>) -> EncodeState<'a> {
+        EncodeState {
+            parts: dump_iter(resp),
+            part: None,
+            error: None,
+            done: false,
+        }
+    }
+
+    /// Writes as much of the rest of the frame as fits in `buf`, returning the number of bytes
+    /// written, which is less than `buf.len()` only once the frame is done or has failed.
+    pub fn write_to(&mut self, buf: &mut [u8]) -> Result<usize, DumpError> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        let mut n = 0;
+        while n < buf.len() {
+            if self.part.is_none() {
+                self.advance();
+            }
+            let (part, written) = match &mut self.part {
+                Some(part) => part,
+                None => break,
+            };
+            let rest = &part.as_bytes()[*written..];
+            let len = rest.len().min(buf.len() - n);
+            buf[n..n + len].copy_from_slice(&rest[..len]);
+            n += len;
+            *written += len;
+            if *written == part.as_bytes().len() {
+                self.part = None;
+                // Look ahead so a frame which exactly fills `buf` is done without another write.
+                self.advance();
+            }
+        }
+        match self.error.take() {
+            Some(err) if n == 0 => Err(err),
+            err => {
+                self.error = err;
+                Ok(n)
+            }
+        }
+    }
+
+    /// Returns whether the whole frame has been written.
+    pub fn is_done(&self) -> bool {
+        self.done && self.part.is_none()
+    }
+
+    /// Moves on to the next part, if the current one has been written.
+    fn advance(&mut self) {
+        if self.part.is_some() || self.done || self.error.is_some() {
+            return;
+        }
+        match self.parts.next() {
+            Some(Ok(part)) => self.part = Some((part, 0)),
+            Some(Err(err)) => self.error = Some(err),
+            None => self.done = true,
+        }
+    }
 }
 
-fn dump_offset(resp: &RESP, buf: &mut [u8], offset: usize) -> Result<usize, DumpError> {
+/// Encodes a batch of objects, such as the replies to a pipeline, into one buffer sized for all
+/// of them up front, returning it along with the offset each frame starts at.
+pub fn dump_all(resps: &[RESP]) -> Result<(Vec<u8>, Vec<usize>), DumpError> {
+    let len = resps.iter().map(RESP::encoded_len).sum();
+    let mut buf = vec![0; len];
+    let mut offsets = Vec::with_capacity(resps.len());
+    let mut offset = 0;
+    for resp in resps {
+        offsets.push(offset);
+        offset += dump(resp, &mut buf[offset..])?;
+    }
+    Ok((buf, offsets))
+}
+
+/// Encodes a RESP object onto the end of a vector, growing it as needed.
+///
+/// On an error the vector is left as it was.
+pub(crate) fn dump_append(resp: &RESP, buf: &mut Vec<u8>) -> Result<usize, DumpError> {
+    let start = buf.len();
+    buf.resize(start + resp.encoded_len(), 0);
+    let result = dump(resp, &mut buf[start..]);
+    if result.is_err() {
+        buf.truncate(start);
+    }
+    result
+}
+
+fn dump_offset(
+    resp: &RESP,
+    buf: &mut [u8],
+    offset: usize,
+    options: DumpOptions,
+) -> Result<usize, DumpError> {
     match resp {
+        RESP::SimpleString(s) | RESP::Error(s)
+            if has_line_break(s) && !options.promote_invalid_simple_strings =>
+        {
+            Err(DumpError::InvalidSimpleString)
+        }
+        RESP::SimpleString(s) if has_line_break(s) => {
+            write_blob(buf, offset, BULK_STRING_BYTE, &[s.as_bytes()])
+        }
+        RESP::Error(s) if has_line_break(s) => {
+            let s = s.replace(['\r', '\n'], " ");
+            write_line(buf, offset, ERROR_BYTE, s.as_bytes())
+        }
         RESP::SimpleString(s) => write_line(buf, offset, SIMPLE_STRING_BYTE, s.as_bytes()),
         RESP::Error(s) => write_line(buf, offset, ERROR_BYTE, s.as_bytes()),
         RESP::Integer(i) => write_line(buf, offset, INTEGER_BYTE, i.to_string().as_bytes()),
-        RESP::BulkString(s) => {
-            let bytes = s.as_bytes();
-            let len = bytes.len().to_string();
-            let mut n = write_line(buf, offset, BULK_STRING_BYTE, len.as_bytes())?;
-            n += write_bytes(buf, offset + n, bytes)?;
-            n += write_bytes(buf, offset + n, b"\r\n")?;
-            Ok(n)
-        }
+        RESP::BulkString(s) => write_blob(buf, offset, BULK_STRING_BYTE, &[s.as_bytes()]),
         RESP::NullBulkString => write_bytes(buf, offset, b"$-1\r\n"),
         RESP::Array(arr) => {
-            let len = arr.len().to_string();
-            let mut n = write_line(buf, offset, ARRAY_BYTE, len.as_bytes())?;
-            for r in arr {
-                let m = dump_offset(r, buf, offset + n)?;
-                n += m;
-            }
-            Ok(n)
+            write_aggregate(buf, offset, ARRAY_BYTE, arr.len(), arr.iter(), options)
         }
         RESP::NullArray => write_bytes(buf, offset, b"*-1\r\n"),
+        RESP::Null => write_bytes(buf, offset, b"_\r\n"),
+        RESP::Boolean(true) => write_bytes(buf, offset, b"#t\r\n"),
+        RESP::Boolean(false) => write_bytes(buf, offset, b"#f\r\n"),
+        RESP::Double(d) => write_line(buf, offset, DOUBLE_BYTE, format_double(*d).as_bytes()),
+        RESP::BigNumber(s) => write_line(buf, offset, BIG_NUMBER_BYTE, s.as_bytes()),
+        RESP::BlobError(s) => write_blob(buf, offset, BLOB_ERROR_BYTE, &[s.as_bytes()]),
+        RESP::VerbatimString(format, s) => write_blob(
+            buf,
+            offset,
+            VERBATIM_STRING_BYTE,
+            &[format.as_bytes(), b":", s.as_bytes()],
+        ),
+        RESP::Map(pairs) => write_pairs(buf, offset, MAP_BYTE, pairs, options),
+        RESP::Set(set) => write_aggregate(buf, offset, SET_BYTE, set.len(), set.iter(), options),
+        RESP::Attribute(attrs, resp) => {
+            let n = write_pairs(buf, offset, ATTRIBUTE_BYTE, attrs, options)?;
+            Ok(n + dump_offset(resp, buf, offset + n, options)?)
+        }
+        RESP::Push(arr) => write_aggregate(buf, offset, PUSH_BYTE, arr.len(), arr.iter(), options),
     }
 }
 
-fn write_line(buf: &mut [u8], offset: usize, kind: u8, bytes: &[u8]) -> Result<usize, DumpError> {
+/// Writes a length prefixed string made up of `parts`.
+fn write_blob(
+    buf: &mut [u8],
+    offset: usize,
+    kind: u8,
+    parts: &[&[u8]],
+) -> Result<usize, DumpError> {
+    let len: usize = parts.iter().map(|p| p.len()).sum();
+    let mut n = write_line(buf, offset, kind, len.to_string().as_bytes())?;
+    for part in parts {
+        n += write_bytes(buf, offset + n, part)?;
+    }
+    n += write_bytes(buf, offset + n, b"\r\n")?;
+    Ok(n)
+}
+
+fn write_aggregate<'b, I>(
+    buf: &mut [u8],
+    offset: usize,
+    kind: u8,
+    count: usize,
+    elements: I,
+    options: DumpOptions,
+) -> Result<usize, DumpError>
+where
+    I: Iterator<Item = &'b RESP<'b>>,
+{
+    let mut n = write_line(buf, offset, kind, count.to_string().as_bytes())?;
+    for r in elements {
+        let m = dump_offset(r, buf, offset + n, options)?;
+        n += m;
+    }
+    Ok(n)
+}
+
+fn write_pairs(
+    buf: &mut [u8],
+    offset: usize,
+    kind: u8,
+    pairs: &[(RESP, RESP)],
+    options: DumpOptions,
+) -> Result<usize, DumpError> {
+    let elements = pairs.iter().flat_map(|(k, v)| [k, v]);
+    write_aggregate(buf, offset, kind, pairs.len(), elements, options)
+}
+
+fn has_line_break(s: &str) -> bool {
+    s.bytes().any(|b| b == b'\r' || b == b'\n')
+}
+
+pub(crate) fn write_line(
+    buf: &mut [u8],
+    offset: usize,
+    kind: u8,
+    bytes: &[u8],
+) -> Result<usize, DumpError> {
     let mut n = write_bytes(buf, offset, &[kind])?;
     n += write_bytes(buf, offset + n, bytes)?;
     n += write_bytes(buf, offset + n, b"\r\n")?;
@@ -185,12 +1310,502 @@ mod tests {
                     "nested",
                 ))])]),
             ),
+            (b"_\r\n", RESP::Null),
+            (b"#t\r\n", RESP::Boolean(true)),
+            (b"#f\r\n", RESP::Boolean(false)),
+            (b",1.5\r\n", RESP::Double(1.5)),
+            (b",-inf\r\n", RESP::Double(f64::NEG_INFINITY)),
+            (
+                b"(3492890328409238509324850943850943825024385\r\n",
+                RESP::BigNumber(Borrowed("3492890328409238509324850943850943825024385")),
+            ),
+            (
+                b"!21\r\nSYNTAX invalid syntax\r\n",
+                RESP::BlobError(Borrowed("SYNTAX invalid syntax")),
+            ),
+            (
+                b"=15\r\ntxt:Some string\r\n",
+                RESP::VerbatimString(Borrowed("txt"), Borrowed("Some string")),
+            ),
+            (
+                b"%2\r\n+first\r\n:1\r\n+second\r\n:2\r\n",
+                RESP::Map(vec![
+                    (RESP::SimpleString(Borrowed("first")), RESP::Integer(1)),
+                    (RESP::SimpleString(Borrowed("second")), RESP::Integer(2)),
+                ]),
+            ),
+            (
+                b"~2\r\n+a\r\n+b\r\n",
+                RESP::Set(vec![
+                    RESP::SimpleString(Borrowed("a")),
+                    RESP::SimpleString(Borrowed("b")),
+                ]),
+            ),
+            (
+                b"|1\r\n+ttl\r\n:3600\r\n*1\r\n:2039123\r\n",
+                RESP::Attribute(
+                    vec![(RESP::SimpleString(Borrowed("ttl")), RESP::Integer(3600))],
+                    Box::new(RESP::Array(vec![RESP::Integer(2039123)])),
+                ),
+            ),
+            (
+                b">2\r\n+message\r\n+hi\r\n",
+                RESP::Push(vec![
+                    RESP::SimpleString(Borrowed("message")),
+                    RESP::SimpleString(Borrowed("hi")),
+                ]),
+            ),
         ];
         let mut buf: Vec<u8> = vec![0; 4096];
         for (bytes, parsed) in test_cases {
+            assert_eq!(parsed.encoded_len(), bytes.len());
             assert_eq!(dump(&parsed, &mut buf), Ok(bytes.len()));
             assert_eq!(&buf[0..bytes.len()], bytes);
             assert_eq!(parse(bytes), Ok((bytes.len(), parsed)));
         }
     }
+
+    #[test]
+    fn test_skip_value() {
+        let test_cases: Vec<(&[u8], Result<usize, ParseError>)> = vec![
+            (b"+OK\r\n:1\r\n", Ok(5)),
+            (
+                b"*2\r\n*1\r\n$3\r\nfoo\r\n%1\r\n+k\r\n_\r\n+next\r\n",
+                Ok(28),
+            ),
+            (b"|1\r\n+k\r\n:1\r\n*0\r\n", Ok(16)),
+            (b"*-1\r\n", Ok(5)),
+            (b"*3\r\n:1\r\n", Err(ParseError::Incomplete)),
+            (b"*1\r\n?\r\n", Err(ParseError::UnknownByte(b'?'))),
+        ];
+        for (bytes, expected) in test_cases {
+            assert_eq!(skip_value(bytes), expected, "{:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn test_peek() {
+        let test_cases = vec![
+            (&b"$1000000\r\nabc"[..], Ok(b'$'), Ok(Some(1_000_000))),
+            (b"*-1\r\n", Ok(b'*'), Ok(Some(-1))),
+            (b"%2\r\n", Ok(b'%'), Ok(Some(2))),
+            (b"+OK", Ok(b'+'), Ok(None)),
+            (b"*12", Ok(b'*'), Err(ParseError::CLRFNotFound)),
+            (
+                b"",
+                Err(ParseError::Incomplete),
+                Err(ParseError::Incomplete),
+            ),
+            (
+                b"x",
+                Err(ParseError::UnknownByte(b'x')),
+                Err(ParseError::UnknownByte(b'x')),
+            ),
+        ];
+        for (bytes, kind, len) in test_cases {
+            assert_eq!(peek_type(bytes), kind);
+            assert_eq!(peek_len(bytes), len);
+        }
+    }
+
+    #[test]
+    fn test_approx_mem_usage() {
+        let size = mem::size_of::<RESP>();
+        let borrowed = RESP::BulkString(Borrowed("hello"));
+        assert_eq!(borrowed.approx_mem_usage(), size);
+        let owned = borrowed.clone().into_owned();
+        assert_eq!(owned.approx_mem_usage(), size + 5);
+
+        let array = RESP::Array(vec![owned.clone(), RESP::Integer(1)]);
+        assert_eq!(array.approx_mem_usage(), 3 * size + 5);
+        let attribute = RESP::Attribute(vec![], Box::new(owned));
+        assert_eq!(attribute.approx_mem_usage(), 2 * size + 5);
+    }
+
+    #[test]
+    fn test_parse_incomplete() {
+        let test_cases: Vec<(&[u8], ParseError)> = vec![
+            (b"", ParseError::Incomplete),
+            (b"+OK", ParseError::CLRFNotFound),
+            (b"$6\r\nfoo", ParseError::Incomplete),
+            (b"$6\r\nfoobar", ParseError::Incomplete),
+            (b"*2\r\n+OK\r\n", ParseError::Incomplete),
+            (b"*2\r\n+OK\r\n+O", ParseError::CLRFNotFound),
+        ];
+        for (bytes, err) in test_cases {
+            assert_eq!(parse(bytes), Err(err));
+        }
+    }
+
+    #[test]
+    fn test_dump_invalid_simple_string() {
+        let promote = DumpOptions {
+            promote_invalid_simple_strings: true,
+        };
+        let test_cases: Vec<(RESP, &[u8])> = vec![
+            (RESP::SimpleString(Borrowed("a\r\nb")), b"$4\r\na\r\nb\r\n"),
+            (RESP::Error(Borrowed("ERR a\nb")), b"-ERR a b\r\n"),
+            (
+                RESP::Array(vec![RESP::SimpleString(Borrowed("\r"))]),
+                b"*1\r\n$1\r\n\r\r\n",
+            ),
+        ];
+        for (resp, bytes) in test_cases {
+            let mut buf = vec![0; 64];
+            assert_eq!(dump(&resp, &mut buf), Err(DumpError::InvalidSimpleString));
+            assert_eq!(resp.encoded_len_with(promote), bytes.len());
+            assert_eq!(dump_with(&resp, &mut buf, promote), Ok(bytes.len()));
+            assert_eq!(&buf[..bytes.len()], bytes);
+        }
+    }
+
+    #[test]
+    fn test_dump_slices() {
+        for vector in vectors::vectors() {
+            let parts = dump_slices(&vector.expected).unwrap();
+            let bytes: Vec<u8> = parts.iter().flat_map(|p| p.as_bytes()).copied().collect();
+            assert_eq!(bytes, vector.wire, "{}", vector.name);
+        }
+
+        let value = "v".repeat(100);
+        let resp = RESP::command(vec!["SET", "k", &value]);
+        let parts = dump_slices(&resp).unwrap();
+        assert_eq!(parts.len(), 7);
+        assert_eq!(parts[0], EncodedPart::Owned(b"*3\r\n$3\r\n".to_vec()));
+        assert!(matches!(parts[5], EncodedPart::Borrowed(v) if v.as_ptr() == value.as_ptr()));
+        assert_eq!(parts[6], EncodedPart::Owned(b"\r\n".to_vec()));
+        assert_eq!(
+            dump_slices(&RESP::Error(Borrowed("a\nb"))),
+            Err(DumpError::InvalidSimpleString)
+        );
+    }
+
+    #[test]
+    fn test_dump_all() {
+        let resps = vec![
+            RESP::SimpleString(Borrowed("OK")),
+            RESP::Integer(12),
+            RESP::command(vec!["GET", "k"]),
+        ];
+        let (buf, offsets) = dump_all(&resps).unwrap();
+        assert_eq!(buf, b"+OK\r\n:12\r\n*2\r\n$3\r\nGET\r\n$1\r\nk\r\n");
+        assert_eq!(offsets, vec![0, 5, 10]);
+        assert_eq!(dump_all(&[]), Ok((vec![], vec![])));
+        assert_eq!(
+            dump_all(&[RESP::Null, RESP::Error(Borrowed("\n"))]),
+            Err(DumpError::InvalidSimpleString)
+        );
+    }
+
+    #[test]
+    fn test_encode_state() {
+        for vector in vectors::vectors() {
+            for size in [1, 3, 64].iter() {
+                let mut state = EncodeState::new(&vector.expected);
+                let mut bytes = Vec::new();
+                let mut buf = vec![0; *size];
+                while !state.is_done() {
+                    let n = state.write_to(&mut buf).unwrap();
+                    assert!(n == *size || state.is_done());
+                    assert!(n > 0);
+                    bytes.extend_from_slice(&buf[..n]);
+                }
+                assert_eq!(
+                    bytes, vector.wire,
+                    "{} in {} byte buffers",
+                    vector.name, size
+                );
+                assert_eq!(state.write_to(&mut buf), Ok(0));
+            }
+        }
+
+        let resp = RESP::Array(vec![RESP::Integer(1), RESP::SimpleString(Borrowed("\r"))]);
+        let mut state = EncodeState::new(&resp);
+        assert_eq!(state.write_to(&mut [0; 6]), Ok(6));
+        assert_eq!(state.write_to(&mut [0; 6]), Ok(2));
+        assert_eq!(
+            state.write_to(&mut [0; 6]),
+            Err(DumpError::InvalidSimpleString)
+        );
+        assert!(!state.is_done());
+    }
+
+    #[test]
+    fn test_dump_iter() {
+        for vector in vectors::vectors() {
+            let bytes: Vec<u8> = dump_iter(&vector.expected)
+                .flat_map(|part| part.unwrap().as_bytes().to_vec())
+                .collect();
+            assert_eq!(bytes, vector.wire, "{}", vector.name);
+        }
+
+        let value = "v".repeat(2 * DUMP_CHUNK + 1);
+        let resp = RESP::Array(vec![RESP::BulkString(Borrowed(&value))]);
+        let lens: Vec<_> = dump_iter(&resp)
+            .map(|part| part.unwrap().as_bytes().len())
+            .collect();
+        assert_eq!(lens, vec![4, 9, DUMP_CHUNK, DUMP_CHUNK, 1, 2]);
+
+        let resp = RESP::Array(vec![RESP::Error(Borrowed("a\nb")), RESP::Null]);
+        let parts: Vec<_> = dump_iter(&resp).collect();
+        assert_eq!(
+            parts,
+            vec![
+                Ok(EncodedPart::Owned(b"*2\r\n".to_vec())),
+                Err(DumpError::InvalidSimpleString),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dump_exact() {
+        let resp = RESP::Array(vec![
+            RESP::BulkString(Borrowed("foo")),
+            RESP::SimpleString(Borrowed("a\nb")),
+        ]);
+        let mut buf = [0; 64];
+        assert_eq!(dump(&resp, &mut buf), Err(DumpError::InvalidSimpleString));
+        assert_ne!(buf, [0; 64]);
+
+        let tests = vec![
+            (resp.clone(), 64, Err(DumpError::InvalidSimpleString)),
+            (
+                RESP::command(vec!["foo", "bar"]),
+                21,
+                Err(DumpError::BufTooSmall),
+            ),
+            (RESP::command(vec!["foo", "bar"]), 22, Ok(22)),
+        ];
+        for (resp, len, result) in tests {
+            let mut buf = vec![0; len];
+            assert_eq!(dump_exact(&resp, &mut buf), result);
+            if result.is_err() {
+                assert_eq!(buf, vec![0; len]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_lossy_utf8() {
+        let lossy = ParseOptions {
+            lossy_utf8: true,
+            ..ParseOptions::default()
+        };
+        let test_cases: Vec<(&[u8], RESP)> = vec![
+            (b"+a\xffb\r\n", RESP::SimpleString(Borrowed("a\u{fffd}b"))),
+            (
+                b"$3\r\n\xc3\x28!\r\n",
+                RESP::BulkString(Borrowed("\u{fffd}(!")),
+            ),
+            (
+                b"=6\r\ntxt:\xe2\x82\r\n",
+                RESP::VerbatimString(Borrowed("txt"), Borrowed("\u{fffd}")),
+            ),
+        ];
+        for (bytes, expected) in test_cases {
+            assert!(matches!(parse(bytes), Err(ParseError::Utf8Error(_))));
+            assert_eq!(parse_with(bytes, lossy), Ok((bytes.len(), expected)));
+        }
+    }
+
+    #[test]
+    fn test_parse_lenient_line_endings() {
+        let lenient = ParseOptions {
+            lenient_line_endings: true,
+            ..ParseOptions::default()
+        };
+        let test_cases: Vec<(&[u8], RESP)> = vec![
+            (b"+OK\n", RESP::SimpleString(Borrowed("OK"))),
+            (b":12\n", RESP::Integer(12)),
+            (
+                b"*2\n$3\nfoo\n$3\r\nbar\r\n",
+                RESP::command(vec!["foo", "bar"]),
+            ),
+            (b"$-1\n", RESP::NullBulkString),
+        ];
+        for (bytes, expected) in test_cases {
+            assert!(parse(bytes).is_err());
+            assert_eq!(parse_with(bytes, lenient), Ok((bytes.len(), expected)));
+        }
+        assert_eq!(parse_with(b"+OK", lenient), Err(ParseError::CLRFNotFound));
+        assert_eq!(
+            parse_with(b"$3\nfoo\r", lenient),
+            Err(ParseError::Incomplete)
+        );
+    }
+
+    #[test]
+    fn test_parse_big_integers() {
+        let big = ParseOptions {
+            big_integers: true,
+            ..ParseOptions::default()
+        };
+        let test_cases: Vec<(&[u8], RESP)> = vec![
+            (b":42\r\n", RESP::Integer(42)),
+            (
+                b":9223372036854775808\r\n",
+                RESP::BigNumber(Borrowed("9223372036854775808")),
+            ),
+            (
+                b":-99999999999999999999\r\n",
+                RESP::BigNumber(Borrowed("-99999999999999999999")),
+            ),
+        ];
+        for (bytes, expected) in test_cases {
+            assert_eq!(parse_with(bytes, big), Ok((bytes.len(), expected)));
+        }
+        assert!(matches!(
+            parse(b":9223372036854775808\r\n"),
+            Err(ParseError::ParseIntError(_))
+        ));
+        assert_eq!(
+            parse_with(b":12a\r\n", big),
+            Err(ParseError::InvalidValue(b':'))
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_values() {
+        let test_cases: Vec<&[u8]> = vec![
+            b"$2\r\nSYNT\r\n",
+            b"$3\r\nfoo\rx",
+            b"!2\r\nSYNT",
+            b"=5\r\ntxt:Soe",
+            b"*1\r\n$1\r\nab\r\n",
+            b"(\r\n",
+            b"(-\r\n",
+            b"(abc\r\n",
+            b"(12.5\r\n",
+        ];
+        for bytes in test_cases {
+            let kind = if bytes[0] == b'*' { b'$' } else { bytes[0] };
+            assert_eq!(
+                parse(bytes),
+                Err(ParseError::InvalidValue(kind)),
+                "{:?}",
+                bytes
+            );
+            let mut lexer = lexer::Lexer::new(bytes);
+            let result = loop {
+                match lexer.next_token() {
+                    Ok(Some(_)) => continue,
+                    result => break result,
+                }
+            };
+            assert_eq!(result, Err(ParseError::InvalidValue(kind)), "{:?}", bytes);
+        }
+        assert_eq!(
+            parse(b"(-12\r\n"),
+            Ok((6, RESP::BigNumber(Borrowed("-12"))))
+        );
+    }
+
+    #[test]
+    fn test_length_overflow() {
+        assert_eq!(checked_end(usize::MAX - 2, 2), Ok(usize::MAX));
+        assert_eq!(
+            checked_end(usize::MAX - 2, 3),
+            Err(ParseError::LengthOverflow)
+        );
+        assert_eq!(to_usize(-1), Err(ParseError::LengthOverflow));
+        #[cfg(target_pointer_width = "32")]
+        assert_eq!(to_usize(1 << 32), Err(ParseError::LengthOverflow));
+
+        // Hostile headers declaring the largest lengths must fail rather than panic or allocate.
+        let max = i64::MAX;
+        for header in ["$", "*", "%", "|", "~", ">", "!", "="].iter() {
+            let bytes = format!("{}{}\r\n:1\r\n", header, max);
+            match parse(bytes.as_bytes()) {
+                Err(ParseError::Incomplete) | Err(ParseError::LengthOverflow) => {}
+                result => panic!("{}: unexpected {:?}", header, result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_strict_integers() {
+        let lenient = ParseOptions {
+            lenient_integers: true,
+            ..ParseOptions::default()
+        };
+        let test_cases: Vec<(&[u8], RESP)> = vec![
+            (b":+5\r\n", RESP::Integer(5)),
+            (b":007\r\n", RESP::Integer(7)),
+            (b":-0\r\n", RESP::Integer(0)),
+            (b"$+3\r\nfoo\r\n", RESP::BulkString(Borrowed("foo"))),
+            (b"*01\r\n:1\r\n", RESP::Array(vec![RESP::Integer(1)])),
+        ];
+        for (bytes, expected) in test_cases {
+            assert_eq!(
+                parse(bytes),
+                Err(ParseError::InvalidValue(bytes[0])),
+                "{:?}",
+                bytes
+            );
+            assert_eq!(parse_with(bytes, lenient), Ok((bytes.len(), expected)));
+        }
+        for bytes in [&b":0\r\n"[..], b":-12\r\n", b"$-1\r\n", b"*10\r\n"].iter() {
+            assert_ne!(parse(bytes), Err(ParseError::InvalidValue(bytes[0])));
+        }
+        assert_eq!(
+            lexer::Lexer::new(b":+5\r\n").next_token(),
+            Err(ParseError::InvalidValue(b':'))
+        );
+    }
+
+    #[test]
+    fn test_kind() {
+        for vector in vectors::vectors() {
+            let kind = vector.expected.kind();
+            assert_eq!(
+                RespType::from_byte(vector.wire[0]),
+                Some(kind),
+                "{}",
+                vector.name
+            );
+            assert_eq!(kind.byte(), vector.wire[0]);
+        }
+        assert_eq!(RESP::NullArray.kind(), RespType::Array);
+        assert_eq!(RespType::from_byte(b'x'), None);
+    }
+
+    #[test]
+    fn test_parse_split() {
+        let mut frame = b"*2\r\n$3\r\nfoo\r\n$200\r\n".to_vec();
+        frame.extend_from_slice(&[b'x'; 200]);
+        frame.extend_from_slice(b"\r\n+next\r\n");
+        let expected = RESP::command(vec!["foo".to_string(), "x".repeat(200)]);
+        for split in [0, 1, 4, 10, 20, frame.len() - 8, frame.len()].iter() {
+            let (head, tail) = frame.split_at(*split);
+            assert_eq!(
+                parse_split(head, tail),
+                Ok((frame.len() - 7, expected.clone())),
+                "split at {}",
+                split
+            );
+        }
+
+        let (n, resp) = parse_split(b"+OK\r\n+", b"next\r\n").unwrap();
+        assert!(matches!(resp, RESP::SimpleString(Borrowed("OK"))));
+        assert_eq!(n, 5);
+        assert_eq!(parse_split(b"$3\r\nf", b"o"), Err(ParseError::Incomplete));
+        assert_eq!(
+            parse_split(b"*1\r\n", b"?"),
+            Err(ParseError::UnknownByte(b'?'))
+        );
+    }
+
+    #[cfg(feature = "unsafe-fast-paths")]
+    #[allow(unsafe_code)]
+    #[test]
+    fn test_parse_trusted_utf8() {
+        // Safe as every string parsed is UTF-8.
+        let options = ParseOptions {
+            trusted_utf8: Some(unsafe { TrustedUtf8::new() }),
+            ..ParseOptions::default()
+        };
+        let bytes = b"*2\r\n$3\r\nfoo\r\n+\xc3\xa9\r\n";
+        let (_, resp) = parse_with(bytes, options).unwrap();
+        assert_eq!(resp, parse(bytes).unwrap().1);
+    }
 }
@@ -0,0 +1,214 @@
+//! Generation of benchmark workloads and measurement of reply latencies, in the spirit of
+//! `redis-benchmark`.
+use crate::connection::Connection;
+use crate::rng::Rng;
+use crate::{Error, RESPOwned, RESP};
+use std::borrow::Cow::{Borrowed, Owned};
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+/// How a size or count is picked for each request or batch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Distribution {
+    Constant(usize),
+    /// Uniformly distributed between the two bounds, inclusive. The first must not be greater
+    /// than the second.
+    Uniform(usize, usize),
+}
+
+impl Distribution {
+    fn sample(self, rng: &mut Rng) -> usize {
+        match self {
+            Distribution::Constant(n) => n,
+            Distribution::Uniform(low, high) => rng.between(low, high),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandKind {
+    Ping,
+    Get,
+    Set,
+    Incr,
+}
+
+/// A mix of commands along with the shape of their keys, values and pipelines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Workload {
+    /// Commands picked with probability proportional to their weight.
+    pub commands: Vec<(CommandKind, u32)>,
+    /// The number of distinct keys.
+    pub keyspace: u64,
+    pub key_size: Distribution,
+    pub value_size: Distribution,
+    /// The number of requests sent before waiting on their replies.
+    pub pipeline: Distribution,
+}
+
+impl Default for Workload {
+    /// A `redis-benchmark` style mix of GETs and SETs of 3 byte values.
+    fn default() -> Workload {
+        Workload {
+            commands: vec![(CommandKind::Get, 1), (CommandKind::Set, 1)],
+            keyspace: 100_000,
+            key_size: Distribution::Constant(16),
+            value_size: Distribution::Constant(3),
+            pipeline: Distribution::Constant(1),
+        }
+    }
+}
+
+/// Generates reproducible batches of requests for a workload.
+pub struct Generator {
+    workload: Workload,
+    total_weight: u64,
+    rng: Rng,
+}
+
+impl Generator {
+    pub fn new(workload: Workload, seed: u64) -> Generator {
+        let total_weight = workload.commands.iter().map(|(_, w)| *w as u64).sum();
+        Generator {
+            workload,
+            total_weight,
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// Returns the next pipeline's worth of requests.
+    pub fn next_batch(&mut self) -> Vec<RESPOwned> {
+        let depth = self.workload.pipeline.sample(&mut self.rng).max(1);
+        (0..depth).map(|_| self.next_request()).collect()
+    }
+
+    pub fn next_request(&mut self) -> RESPOwned {
+        let mut pick = self.rng.below(self.total_weight);
+        let mut kind = CommandKind::Ping;
+        for (k, weight) in &self.workload.commands {
+            if pick < *weight as u64 {
+                kind = *k;
+                break;
+            }
+            pick -= *weight as u64;
+        }
+        match kind {
+            CommandKind::Ping => RESP::command(vec![Borrowed("PING")]),
+            CommandKind::Get => RESP::command(vec![Borrowed("GET"), Owned(self.key())]),
+            CommandKind::Incr => RESP::command(vec![Borrowed("INCR"), Owned(self.key())]),
+            CommandKind::Set => {
+                let key = self.key();
+                let value = "x".repeat(self.workload.value_size.sample(&mut self.rng));
+                RESP::command(vec![Borrowed("SET"), Owned(key), Owned(value)])
+            }
+        }
+    }
+
+    fn key(&mut self) -> String {
+        let n = self.rng.below(self.workload.keyspace);
+        let width = self
+            .workload
+            .key_size
+            .sample(&mut self.rng)
+            .saturating_sub(4);
+        format!("key:{:0width$}", n, width = width)
+    }
+}
+
+/// The outcome of a benchmark run.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// The latency of each request, from its batch being sent to its reply arriving, sorted.
+    pub latencies: Vec<Duration>,
+    /// The number of error replies.
+    pub errors: usize,
+    pub elapsed: Duration,
+}
+
+impl Report {
+    /// Returns the latency at `percentile`, such as 99.0 for the p99.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let rank = (percentile / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        Some(self.latencies[rank.clamp(1, self.latencies.len()) - 1])
+    }
+
+    /// Returns the number of requests completed per second.
+    pub fn throughput(&self) -> f64 {
+        self.latencies.len() as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Sends at least `requests` requests generated for `workload`, measuring their latencies.
+pub fn run<S: Read + Write>(
+    conn: &mut Connection<S>,
+    workload: Workload,
+    requests: usize,
+    seed: u64,
+) -> Result<Report, Error> {
+    let mut generator = Generator::new(workload, seed);
+    let mut report = Report::default();
+    let start = Instant::now();
+    while report.latencies.len() < requests {
+        let batch = generator.next_batch();
+        let sent = Instant::now();
+        for request in &batch {
+            conn.send(request)?;
+        }
+        for _ in &batch {
+            if let RESP::Error(_) = conn.recv()? {
+                report.errors += 1;
+            }
+            report.latencies.push(sent.elapsed());
+        }
+    }
+    report.elapsed = start.elapsed();
+    report.latencies.sort();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{serve, Request};
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_run() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            serve(listener, || {
+                |request: Request| match request.name() {
+                    "SET" => RESP::SimpleString(Borrowed("OK")),
+                    _ => RESP::Error(Borrowed("ERR unknown command")),
+                }
+            })
+        });
+
+        let workload = Workload {
+            commands: vec![(CommandKind::Get, 1), (CommandKind::Set, 3)],
+            pipeline: Distribution::Uniform(1, 8),
+            ..Workload::default()
+        };
+        let mut conn = Connection::connect(addr).unwrap();
+        let report = run(&mut conn, workload.clone(), 200, 7).unwrap();
+        assert!(report.latencies.len() >= 200);
+        assert!(report.errors > 0 && report.errors < report.latencies.len() / 2);
+        assert!(report.percentile(50.0) <= report.percentile(99.0));
+
+        let mut a = Generator::new(workload.clone(), 1);
+        let mut b = Generator::new(workload, 1);
+        assert_eq!(a.next_batch(), b.next_batch());
+        match a.next_request() {
+            RESP::Array(args) => match &args[1] {
+                RESP::BulkString(key) => assert_eq!(key.len(), 16),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+}
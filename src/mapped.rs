@@ -0,0 +1,171 @@
+//! Zero-copy parsing of the frames in a file mapped into memory, such as a capture or an AOF
+//! file mapped with `memmap2`.
+//!
+//! Every frame borrows its strings straight from the mapping, so a multi-gigabyte file can be
+//! scanned without copying it into the heap. Files which are still being written, or were cut
+//! off by a crash, usually end part way through a frame, so a truncated tail is reported apart
+//! from malformed frames rather than as an error.
+use crate::{parse_with, skip_value, ParseError, ParseOptions, RESP};
+use std::ops::Range;
+use std::thread;
+
+/// Iterates over the frames in `region`, which is typically a whole mapped file: `&mmap[..]`.
+pub fn parse_mmap(region: &[u8]) -> MappedFrames<'_> {
+    parse_mmap_with(region, ParseOptions::default())
+}
+
+/// Iterates over the frames in `region`, parsing as relaxed by `options`.
+pub fn parse_mmap_with(region: &[u8], options: ParseOptions) -> MappedFrames<'_> {
+    MappedFrames {
+        region,
+        options,
+        offset: 0,
+        truncated: false,
+        failed: false,
+    }
+}
+
+/// The frames in a region along with their byte ranges in it, from `parse_mmap`.
+///
+/// Ends at the end of the region, at a truncated frame, or after the first malformed one.
+pub struct MappedFrames<'a> {
+    region: &'a [u8],
+    options: ParseOptions,
+    offset: usize,
+    truncated: bool,
+    failed: bool,
+}
+
+impl<'a> MappedFrames<'a> {
+    /// Returns the number of bytes parsed so far, which is where the next frame starts.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the range of the incomplete frame the region ends with, once iteration has
+    /// reached it, such as to truncate the file there as `redis-check-aof --fix` does.
+    pub fn truncated(&self) -> Option<Range<usize>> {
+        if self.truncated {
+            Some(self.offset..self.region.len())
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> Iterator for MappedFrames<'a> {
+    type Item = Result<(Range<usize>, RESP<'a>), ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.truncated || self.failed || self.offset == self.region.len() {
+            return None;
+        }
+        let start = self.offset;
+        match parse_with(&self.region[start..], self.options) {
+            Ok((n, resp)) => {
+                self.offset += n;
+                Some(Ok((start..self.offset, resp)))
+            }
+            Err(ParseError::Incomplete) | Err(ParseError::CLRFNotFound) => {
+                self.truncated = true;
+                None
+            }
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Parses every frame in `buf` across a thread per core, returning them in order.
+///
+/// Frame boundaries are found first with `skip_value`, which is cheap, then the frames are
+/// split into a contiguous run for each thread to parse. Fails with the first frame's error,
+/// or `Incomplete` if `buf` ends part way through a frame.
+pub fn parse_all_parallel(buf: &[u8]) -> Result<Vec<RESP<'_>>, ParseError> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let mut ranges = Vec::new();
+    let mut offset = 0;
+    while offset < buf.len() {
+        let n = skip_value(&buf[offset..])?;
+        ranges.push(offset..offset + n);
+        offset += n;
+    }
+    let per_thread = ranges.len().div_ceil(threads).max(1);
+    let parsed: Vec<Result<Vec<RESP>, ParseError>> = thread::scope(|scope| {
+        let handles: Vec<_> = ranges
+            .chunks(per_thread)
+            .map(|run| {
+                scope.spawn(move || {
+                    run.iter()
+                        .map(|range| parse_with(&buf[range.clone()], ParseOptions::default()))
+                        .map(|parsed| parsed.map(|(_, resp)| resp))
+                        .collect()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    });
+    let mut frames = Vec::with_capacity(ranges.len());
+    for run in parsed {
+        frames.extend(run?);
+    }
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use std::borrow::Cow::Borrowed;
+
+    #[test]
+    fn test_parse_mmap() {
+        let region = b"+OK\r\n:1\r\n*2\r\n$3\r\nfoo\r\n$3\r\nba";
+        let mut frames = parse_mmap(region);
+        assert_eq!(
+            frames.by_ref().map(Result::unwrap).collect::<Vec<_>>(),
+            vec![
+                (0..5, RESP::SimpleString(Borrowed("OK"))),
+                (5..9, RESP::Integer(1)),
+            ]
+        );
+        assert_eq!(frames.truncated(), Some(9..region.len()));
+        assert_eq!(frames.next(), None);
+
+        let mut frames = parse_mmap(b":1\r\n?\r\n:2\r\n");
+        assert_eq!(frames.next(), Some(Ok((0..4, RESP::Integer(1)))));
+        assert_eq!(frames.next(), Some(Err(ParseError::UnknownByte(b'?'))));
+        assert_eq!(frames.next(), None);
+        assert_eq!((frames.offset(), frames.truncated()), (4, None));
+
+        let mut frames = parse_mmap(b"");
+        assert_eq!(frames.next(), None);
+        assert_eq!(frames.truncated(), None);
+    }
+
+    #[test]
+    fn test_parse_all_parallel() {
+        let mut buf = Vec::new();
+        let mut expected = Vec::new();
+        for i in 0..1000 {
+            let resp = RESP::Array(vec![RESP::Integer(i), RESP::SimpleString(Borrowed("x"))]);
+            crate::dump_append(&resp, &mut buf).unwrap();
+            expected.push(resp);
+        }
+        assert_eq!(parse_all_parallel(&buf), Ok(expected));
+        assert_eq!(parse_all_parallel(b""), Ok(vec![]));
+        assert_eq!(
+            parse_all_parallel(b":1\r\n+\xff\r\n:2\r\n").unwrap_err(),
+            parse(b"+\xff\r\n").unwrap_err()
+        );
+        assert_eq!(
+            parse_all_parallel(b":1\r\n$3\r\nab"),
+            Err(ParseError::Incomplete)
+        );
+    }
+}
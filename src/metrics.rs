@@ -0,0 +1,133 @@
+//! Counters of the frames a decoder or writer has handled, for exporting to monitoring.
+use crate::ParseError;
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+
+/// The names of the kinds of parse error `Metrics` counts, in the order of
+/// `MetricsSnapshot::errors`.
+pub const ERROR_KINDS: [&str; 6] = [
+    "unknown_byte",
+    "utf8",
+    "parse_int",
+    "parse_float",
+    "invalid_value",
+    "length_overflow",
+];
+
+/// Counters updated by each `Decoder`, `FrameWriter` or `Connection` given them, which can be
+/// shared through an `Arc` and read from another thread.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    frames_decoded: AtomicU64,
+    bytes_decoded: AtomicU64,
+    frames_encoded: AtomicU64,
+    bytes_encoded: AtomicU64,
+    max_frame_size: AtomicU64,
+    errors: [AtomicU64; ERROR_KINDS.len()],
+}
+
+/// The values of a `Metrics`' counters at one moment.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MetricsSnapshot {
+    pub frames_decoded: u64,
+    pub bytes_decoded: u64,
+    pub frames_encoded: u64,
+    pub bytes_encoded: u64,
+    /// The size of the largest frame decoded or encoded.
+    pub max_frame_size: u64,
+    /// The number of each kind of parse error, named by `ERROR_KINDS`.
+    pub errors: [u64; ERROR_KINDS.len()],
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let mut errors = [0; ERROR_KINDS.len()];
+        for (count, counter) in errors.iter_mut().zip(&self.errors) {
+            *count = counter.load(Relaxed);
+        }
+        MetricsSnapshot {
+            frames_decoded: self.frames_decoded.load(Relaxed),
+            bytes_decoded: self.bytes_decoded.load(Relaxed),
+            frames_encoded: self.frames_encoded.load(Relaxed),
+            bytes_encoded: self.bytes_encoded.load(Relaxed),
+            max_frame_size: self.max_frame_size.load(Relaxed),
+            errors,
+        }
+    }
+
+    pub(crate) fn record_decoded(&self, len: usize) {
+        self.frames_decoded.fetch_add(1, Relaxed);
+        self.bytes_decoded.fetch_add(len as u64, Relaxed);
+        self.max_frame_size.fetch_max(len as u64, Relaxed);
+    }
+
+    pub(crate) fn record_encoded(&self, len: usize) {
+        self.frames_encoded.fetch_add(1, Relaxed);
+        self.bytes_encoded.fetch_add(len as u64, Relaxed);
+        self.max_frame_size.fetch_max(len as u64, Relaxed);
+    }
+
+    /// Counts a parse error, ignoring those which only mean more bytes are needed.
+    pub(crate) fn record_error(&self, err: &ParseError) {
+        if let Some(kind) = error_kind(err) {
+            self.errors[kind].fetch_add(1, Relaxed);
+        }
+    }
+}
+
+/// Returns the index of the kind of `err` in `ERROR_KINDS`, or `None` for errors which only mean
+/// more bytes are needed.
+pub(crate) fn error_kind(err: &ParseError) -> Option<usize> {
+    match err {
+        ParseError::UnknownByte(_) => Some(0),
+        ParseError::Utf8Error(_) => Some(1),
+        ParseError::ParseIntError(_) => Some(2),
+        ParseError::ParseFloatError(_) => Some(3),
+        ParseError::InvalidValue(_) => Some(4),
+        ParseError::LengthOverflow => Some(5),
+        ParseError::CLRFNotFound | ParseError::Incomplete => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+    use crate::writer::{FlushPolicy, FrameWriter};
+    use crate::RESP;
+    use std::borrow::Cow::Borrowed;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_metrics() {
+        let metrics = Arc::new(Metrics::new());
+        let mut decoder = Decoder::new();
+        decoder.set_metrics(metrics.clone());
+        decoder.feed(b"+OK\r\n$3\r\nfoo\r\n:1");
+        while decoder.decode().unwrap().is_some() {}
+        decoder.feed(b"x\r\n");
+        assert!(decoder.decode().is_err());
+
+        let mut writer = FrameWriter::new(Vec::new(), FlushPolicy::explicit());
+        writer.set_metrics(metrics.clone());
+        writer
+            .write_frame(&RESP::SimpleString(Borrowed("PONG")))
+            .unwrap();
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(
+            snapshot,
+            MetricsSnapshot {
+                frames_decoded: 2,
+                bytes_decoded: 14,
+                frames_encoded: 1,
+                bytes_encoded: 7,
+                max_frame_size: 9,
+                errors: [0, 0, 0, 0, 1, 0],
+            }
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Pairing of replies with the requests they answer, for clients which pipeline requests from
+//! many callers over one connection.
+//!
+//! A RESP server replies to requests in the order it received them, other than RESP3 push
+//! frames, which don't answer any request unless they confirm a subscription. This holds no IO of
+//! its own, so it fits whatever threads or event loop a client uses.
+use crate::{RESPOwned, RESP};
+use std::collections::vec_deque;
+use std::collections::VecDeque;
+
+/// The kinds of push frame a RESP3 server replies to subscribe and unsubscribe commands with.
+const SUBSCRIPTION_KINDS: [&str; 6] = [
+    "subscribe",
+    "unsubscribe",
+    "psubscribe",
+    "punsubscribe",
+    "ssubscribe",
+    "sunsubscribe",
+];
+
+/// Returns whether `frame` is a push confirming a subscribe or unsubscribe command, which is the
+/// reply to that command rather than an out of band message.
+pub(crate) fn is_subscription_reply(frame: &RESP) -> bool {
+    match frame {
+        RESP::Push(elements) => match elements.first() {
+            Some(RESP::BulkString(kind)) | Some(RESP::SimpleString(kind)) => SUBSCRIPTION_KINDS
+                .iter()
+                .any(|k| k.eq_ignore_ascii_case(kind)),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Tracks the waiters of requests which have been sent, in the order they were sent.
+#[derive(Debug)]
+pub struct Multiplexer<W> {
+    next_seq: u64,
+    waiting: VecDeque<(u64, W)>,
+}
+
+/// Where a frame from the server should go, from `Multiplexer::dispatch`.
+#[derive(Debug, PartialEq)]
+pub enum Routed<W> {
+    /// The reply to the oldest request, along with its sequence number and waiter.
+    Reply {
+        seq: u64,
+        waiter: W,
+        reply: RESPOwned,
+    },
+    /// A push frame, such as a pub/sub message, or a subscription confirmation when no request is
+    /// waiting, such as for the second channel of a `SUBSCRIBE`.
+    Push(RESPOwned),
+    /// A reply when no request is waiting, after which the connection can't be trusted.
+    Unexpected(RESPOwned),
+}
+
+impl<W> Default for Multiplexer<W> {
+    fn default() -> Multiplexer<W> {
+        Multiplexer::new()
+    }
+}
+
+impl<W> Multiplexer<W> {
+    pub fn new() -> Multiplexer<W> {
+        Multiplexer {
+            next_seq: 0,
+            waiting: VecDeque::new(),
+        }
+    }
+
+    /// Records the waiter of the request just sent, returning the request's sequence number.
+    ///
+    /// Sequence numbers count up from 0 in the order requests are registered.
+    pub fn register(&mut self, waiter: W) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.waiting.push_back((seq, waiter));
+        seq
+    }
+
+    /// Returns the number of requests waiting on replies.
+    pub fn len(&self) -> usize {
+        self.waiting.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiting.is_empty()
+    }
+
+    /// Routes a frame from the server to the waiter it answers, if it answers one.
+    ///
+    /// A push confirming a subscription answers the oldest request. A command naming several
+    /// channels gets a confirmation for each, so it should be the last request in flight.
+    pub fn dispatch(&mut self, frame: RESPOwned) -> Routed<W> {
+        let subscription = is_subscription_reply(&frame);
+        if let RESP::Push(_) = frame {
+            if !subscription {
+                return Routed::Push(frame);
+            }
+        }
+        match self.waiting.pop_front() {
+            Some((seq, waiter)) => Routed::Reply {
+                seq,
+                waiter,
+                reply: frame,
+            },
+            None if subscription => Routed::Push(frame),
+            None => Routed::Unexpected(frame),
+        }
+    }
+
+    /// Removes every waiter, oldest first, such as to fail them once the connection is lost.
+    pub fn drain(&mut self) -> vec_deque::Drain<'_, (u64, W)> {
+        self.waiting.drain(..)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow::Borrowed;
+
+    #[test]
+    fn test_multiplexer() {
+        let mut mux = Multiplexer::new();
+        assert_eq!(mux.register("a"), 0);
+        assert_eq!(mux.register("b"), 1);
+        let push = RESP::Push(vec![RESP::BulkString(Borrowed("message"))]);
+        assert_eq!(mux.dispatch(push.clone()), Routed::Push(push));
+        assert_eq!(
+            mux.dispatch(RESP::Integer(1)),
+            Routed::Reply {
+                seq: 0,
+                waiter: "a",
+                reply: RESP::Integer(1),
+            }
+        );
+        assert_eq!(mux.register("c"), 2);
+        assert_eq!(mux.len(), 2);
+        assert_eq!(mux.drain().collect::<Vec<_>>(), vec![(1, "b"), (2, "c")]);
+        assert_eq!(mux.dispatch(RESP::Null), Routed::Unexpected(RESP::Null));
+    }
+
+    #[test]
+    fn test_subscription_replies() {
+        let mut mux = Multiplexer::new();
+        mux.register("subscribe");
+        mux.register("get");
+        let confirm = RESP::Push(vec![
+            RESP::BulkString(Borrowed("subscribe")),
+            RESP::BulkString(Borrowed("news")),
+            RESP::Integer(1),
+        ]);
+        let message = RESP::Push(vec![
+            RESP::BulkString(Borrowed("message")),
+            RESP::BulkString(Borrowed("news")),
+            RESP::BulkString(Borrowed("hello")),
+        ]);
+        assert_eq!(
+            mux.dispatch(confirm.clone()),
+            Routed::Reply {
+                seq: 0,
+                waiter: "subscribe",
+                reply: confirm.clone(),
+            }
+        );
+        assert_eq!(mux.dispatch(message.clone()), Routed::Push(message));
+        assert_eq!(
+            mux.dispatch(RESP::BulkString(Borrowed("bar"))),
+            Routed::Reply {
+                seq: 1,
+                waiter: "get",
+                reply: RESP::BulkString(Borrowed("bar")),
+            }
+        );
+        assert_eq!(mux.dispatch(confirm.clone()), Routed::Push(confirm));
+    }
+}
@@ -0,0 +1,362 @@
+//! A connection shared by concurrent callers, which pipelines their requests.
+//!
+//! Callers encode requests into a shared outgoing buffer, which a writer thread flushes in as few
+//! writes as possible. A reader thread decodes replies and hands each one to the oldest waiting
+//! caller, since a RESP server replies to requests in the order it received them. RESP3 push
+//! frames don't answer any request, other than those confirming a subscription, so they're routed
+//! to a separate channel instead.
+use crate::connection::{Endpoint, Stream};
+use crate::decoder::Decoder;
+use crate::multiplexer::{Multiplexer, Routed};
+use crate::{dump_append, Error, RESPOwned, RESP};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+type Reply = Result<RESPOwned, Error>;
+
+#[derive(Default)]
+struct Outgoing {
+    buf: Vec<u8>,
+    /// Callers waiting on replies, in the order their requests were buffered.
+    waiting: Multiplexer<Sender<Reply>>,
+    /// Set once the connection has failed or been dropped.
+    closed: Option<io::ErrorKind>,
+}
+
+#[derive(Default)]
+struct Shared {
+    outgoing: Mutex<Outgoing>,
+    writable: Condvar,
+    pushes: Mutex<Option<Sender<RESPOwned>>>,
+}
+
+impl Shared {
+    /// Fails every waiting caller along with all future requests.
+    fn close(&self, kind: io::ErrorKind) {
+        let mut outgoing = self.outgoing.lock().unwrap();
+        if outgoing.closed.is_none() {
+            outgoing.closed = Some(kind);
+        }
+        for (_, waiter) in outgoing.waiting.drain() {
+            let _ = waiter.send(Err(Error::Io(kind.into())));
+        }
+        self.writable.notify_all();
+        drop(outgoing);
+        self.pushes.lock().unwrap().take();
+    }
+}
+
+struct Handle {
+    shared: Arc<Shared>,
+    shutdown: Option<Box<dyn Fn() + Send + Sync>>,
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        self.shared.close(io::ErrorKind::NotConnected);
+        if let Some(shutdown) = &self.shutdown {
+            shutdown();
+        }
+    }
+}
+
+/// A cloneable handle to a pipelined connection.
+///
+/// The connection is closed once every clone has been dropped.
+#[derive(Clone)]
+pub struct PipelinedConnection {
+    handle: Arc<Handle>,
+}
+
+/// The reply to a request sent with `PipelinedConnection::send`.
+pub struct Pending {
+    reply: Receiver<Reply>,
+}
+
+impl Pending {
+    /// Blocks until the reply has arrived.
+    pub fn wait(self) -> Result<RESPOwned, Error> {
+        self.reply
+            .recv()
+            .unwrap_or_else(|_| Err(Error::Io(io::ErrorKind::NotConnected.into())))
+    }
+}
+
+impl PipelinedConnection {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<PipelinedConnection> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        PipelinedConnection::from_stream(Stream::Tcp(stream))
+    }
+
+    pub fn open(endpoint: &Endpoint) -> io::Result<PipelinedConnection> {
+        PipelinedConnection::from_stream(endpoint.connect()?)
+    }
+
+    fn from_stream(stream: Stream) -> io::Result<PipelinedConnection> {
+        let reader = stream.try_clone()?;
+        let writer = stream.try_clone()?;
+        Ok(PipelinedConnection::spawn(
+            reader,
+            writer,
+            Some(Box::new(move || {
+                let _ = stream.shutdown(Shutdown::Both);
+            })),
+        ))
+    }
+
+    /// Creates a connection from the two halves of a stream.
+    ///
+    /// The reader thread only exits once `reader` reaches the end of the stream or fails.
+    pub fn from_halves<R, W>(reader: R, writer: W) -> PipelinedConnection
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        PipelinedConnection::spawn(reader, writer, None)
+    }
+
+    fn spawn<R, W>(
+        reader: R,
+        writer: W,
+        shutdown: Option<Box<dyn Fn() + Send + Sync>>,
+    ) -> PipelinedConnection
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        let shared = Arc::new(Shared::default());
+        let read_shared = shared.clone();
+        thread::spawn(move || read_loop(reader, &read_shared));
+        let write_shared = shared.clone();
+        thread::spawn(move || write_loop(writer, &write_shared));
+        PipelinedConnection {
+            handle: Arc::new(Handle { shared, shutdown }),
+        }
+    }
+
+    /// Buffers a request to be sent, returning a handle to wait on its reply.
+    pub fn send(&self, request: &RESP) -> Result<Pending, Error> {
+        let (tx, rx) = mpsc::channel();
+        let shared = &self.handle.shared;
+        let mut outgoing = shared.outgoing.lock().unwrap();
+        if let Some(kind) = outgoing.closed {
+            return Err(Error::Io(kind.into()));
+        }
+        dump_append(request, &mut outgoing.buf)?;
+        outgoing.waiting.register(tx);
+        shared.writable.notify_one();
+        Ok(Pending { reply: rx })
+    }
+
+    /// Returns a receiver of RESP3 push frames, such as pub/sub messages, replacing any previous
+    /// receiver. Pushes which arrive while there's no receiver are dropped.
+    pub fn pushes(&self) -> Receiver<RESPOwned> {
+        let (tx, rx) = mpsc::channel();
+        let shared = &self.handle.shared;
+        if shared.outgoing.lock().unwrap().closed.is_none() {
+            *shared.pushes.lock().unwrap() = Some(tx);
+        }
+        rx
+    }
+
+    /// Sends a request and waits for its reply.
+    pub fn call(&self, request: &RESP) -> Result<RESPOwned, Error> {
+        self.send(request)?.wait()
+    }
+}
+
+fn write_loop<W: Write>(mut writer: W, shared: &Shared) {
+    let mut buf = Vec::new();
+    loop {
+        {
+            let mut outgoing = shared.outgoing.lock().unwrap();
+            while outgoing.buf.is_empty() && outgoing.closed.is_none() {
+                outgoing = shared.writable.wait(outgoing).unwrap();
+            }
+            if outgoing.closed.is_some() {
+                return;
+            }
+            buf.clear();
+            mem::swap(&mut buf, &mut outgoing.buf);
+        }
+        if let Err(err) = writer.write_all(&buf).and_then(|_| writer.flush()) {
+            shared.close(err.kind());
+            return;
+        }
+    }
+}
+
+fn read_loop<R: Read>(mut reader: R, shared: &Shared) {
+    let mut decoder = Decoder::new();
+    loop {
+        let reply = match decoder.decode() {
+            Ok(Some((_, reply))) => reply,
+            Ok(None) => match decoder.read_from(&mut reader) {
+                Ok(0) => return shared.close(io::ErrorKind::UnexpectedEof),
+                Ok(_) => continue,
+                Err(err) => return shared.close(err.kind()),
+            },
+            // The stream can't be resynchronized, so nothing after the bad reply can be paired.
+            Err(_) => return shared.close(io::ErrorKind::InvalidData),
+        };
+        let routed = shared.outgoing.lock().unwrap().waiting.dispatch(reply);
+        match routed {
+            Routed::Reply { waiter, reply, .. } => {
+                let _ = waiter.send(Ok(reply));
+            }
+            Routed::Push(push) => {
+                if let Some(pushes) = &*shared.pushes.lock().unwrap() {
+                    let _ = pushes.send(push);
+                }
+            }
+            Routed::Unexpected(_) => return shared.close(io::ErrorKind::InvalidData),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::Connection;
+    use std::borrow::Cow::Borrowed;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_concurrent_callers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Echoes each request's argument back as a simple string.
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut conn = Connection::new(stream);
+            while let Ok(RESP::Array(args)) = conn.recv() {
+                let reply = match &args[1] {
+                    RESP::BulkString(s) => RESP::SimpleString(s.clone()),
+                    _ => unreachable!(),
+                };
+                conn.send(&reply).unwrap();
+            }
+        });
+
+        let conn = PipelinedConnection::connect(addr).unwrap();
+        let callers: Vec<_> = (0..8)
+            .map(|i| {
+                let conn = conn.clone();
+                thread::spawn(move || {
+                    for j in 0..50 {
+                        let arg = format!("{}-{}", i, j);
+                        let reply = conn.call(&RESP::command(vec!["ECHO", &arg])).unwrap();
+                        assert_eq!(reply, RESP::SimpleString(Borrowed(&arg)));
+                    }
+                })
+            })
+            .collect();
+        for caller in callers {
+            caller.join().unwrap();
+        }
+
+        let pending: Vec<_> = (0..10)
+            .map(|i| conn.send(&RESP::command(vec!["ECHO".to_string(), i.to_string()])))
+            .collect();
+        for (i, p) in pending.into_iter().enumerate() {
+            let reply = p.unwrap().wait().unwrap();
+            assert_eq!(reply, RESP::SimpleString(i.to_string().into()));
+        }
+    }
+
+    #[test]
+    fn test_pushes() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Replies to each request with a push before the actual reply.
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut conn = Connection::new(stream);
+            while conn.recv().is_ok() {
+                let push = RESP::Push(vec![RESP::BulkString(Borrowed("message"))]);
+                conn.send(&push).unwrap();
+                conn.send(&RESP::SimpleString(Borrowed("OK"))).unwrap();
+            }
+        });
+
+        let conn = PipelinedConnection::connect(addr).unwrap();
+        let pushes = conn.pushes();
+        for _ in 0..3 {
+            let reply = conn.call(&RESP::command(vec!["PING"])).unwrap();
+            assert_eq!(reply, RESP::SimpleString(Borrowed("OK")));
+        }
+        assert_eq!(pushes.iter().take(3).count(), 3);
+        drop(conn);
+        assert!(pushes.recv().is_err());
+    }
+
+    #[test]
+    fn test_subscription_replies() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Confirms a subscription and publishes to it, then replies to the next request.
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut conn = Connection::new(stream);
+            conn.recv().unwrap();
+            let push = |kind| {
+                RESP::Push(vec![
+                    RESP::BulkString(Borrowed(kind)),
+                    RESP::BulkString(Borrowed("news")),
+                    RESP::Integer(1),
+                ])
+            };
+            conn.send(&push("subscribe")).unwrap();
+            conn.send(&push("message")).unwrap();
+            conn.recv().unwrap();
+            conn.send(&RESP::BulkString(Borrowed("bar"))).unwrap();
+        });
+
+        let conn = PipelinedConnection::connect(addr).unwrap();
+        let pushes = conn.pushes();
+        let subscribe = conn
+            .send(&RESP::command(vec!["SUBSCRIBE", "news"]))
+            .unwrap();
+        let get = conn.send(&RESP::command(vec!["GET", "foo"])).unwrap();
+        match subscribe.wait().unwrap() {
+            RESP::Push(elements) => {
+                assert_eq!(elements[0], RESP::BulkString(Borrowed("subscribe")))
+            }
+            reply => panic!("unexpected {:?}", reply),
+        }
+        assert_eq!(get.wait().unwrap(), RESP::BulkString(Borrowed("bar")));
+        match pushes.recv().unwrap() {
+            RESP::Push(elements) => assert_eq!(elements[0], RESP::BulkString(Borrowed("message"))),
+            push => panic!("unexpected {:?}", push),
+        }
+    }
+
+    #[test]
+    fn test_send_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Echoes each request back, so anything left in the buffer shows up in the replies.
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut conn = Connection::new(stream);
+            while let Ok(request) = conn.recv() {
+                conn.send(&request).unwrap();
+            }
+        });
+
+        let conn = PipelinedConnection::connect(addr).unwrap();
+        let invalid = RESP::Array(vec![
+            RESP::BulkString(Borrowed("ECHO")),
+            RESP::SimpleString(Borrowed("a\r\nb")),
+        ]);
+        assert!(conn.send(&invalid).is_err());
+        assert!(conn.handle.shared.outgoing.lock().unwrap().buf.is_empty());
+        let ping = RESP::command(vec!["PING"]);
+        assert_eq!(conn.call(&ping).unwrap(), ping);
+    }
+}
@@ -0,0 +1,244 @@
+//! A client protocol state machine which doesn't own a socket.
+//!
+//! `Protocol` frames requests into an output buffer and pairs replies fed to it with the requests
+//! they answer, leaving all IO to the caller. This makes it usable from any event loop: write out
+//! whatever `take_output` returns and pass whatever is read to `handle_input`.
+use crate::decoder::Decoder;
+use crate::multiplexer::is_subscription_reply;
+use crate::{dump_append, DumpError, ParseError, RESPOwned, RESP};
+use std::collections::VecDeque;
+use std::mem;
+
+/// Identifies a request sent with `Protocol::request`.
+pub type RequestId = u64;
+
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    /// Every handshake command succeeded. Not reported when there was no handshake.
+    Ready,
+    /// A handshake command failed with this error reply.
+    HandshakeFailed(RESPOwned),
+    /// The reply to a request.
+    Reply { id: RequestId, reply: RESPOwned },
+    /// A RESP3 push frame, which is kept apart from replies so it doesn't break their pairing.
+    /// Pushes confirming a subscription are the reply to the request which asked for it instead.
+    Push(RESPOwned),
+    /// An object which doesn't answer any request.
+    Unsolicited(RESPOwned),
+}
+
+#[derive(Debug)]
+enum Pending {
+    Handshake,
+    Request(RequestId),
+}
+
+#[derive(Debug)]
+pub struct Protocol {
+    decoder: Decoder,
+    out: Vec<u8>,
+    taken: Vec<u8>,
+    pending: VecDeque<Pending>,
+    handshake_remaining: usize,
+    handshake_failed: bool,
+    next_id: RequestId,
+}
+
+impl Protocol {
+    /// Creates a protocol which starts by sending the `handshake` commands, such as `AUTH`,
+    /// `SELECT` or `CLIENT SETNAME`.
+    ///
+    /// Requests may be sent straight away, in which case they're pipelined behind the handshake.
+    pub fn new(handshake: &[RESP]) -> Result<Protocol, DumpError> {
+        let mut protocol = Protocol {
+            decoder: Decoder::new(),
+            out: Vec::new(),
+            taken: Vec::new(),
+            pending: VecDeque::new(),
+            handshake_remaining: handshake.len(),
+            handshake_failed: false,
+            next_id: 0,
+        };
+        for command in handshake {
+            dump_append(command, &mut protocol.out)?;
+            protocol.pending.push_back(Pending::Handshake);
+        }
+        Ok(protocol)
+    }
+
+    /// Returns whether every handshake reply has arrived and none of them failed.
+    pub fn is_ready(&self) -> bool {
+        self.handshake_remaining == 0 && !self.handshake_failed
+    }
+
+    /// Returns whether a handshake command has failed.
+    pub fn handshake_failed(&self) -> bool {
+        self.handshake_failed
+    }
+
+    /// Returns the number of requests, including handshake commands, awaiting replies.
+    pub fn in_flight(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Queues a request to be sent, returning the id its reply will be reported with.
+    pub fn request(&mut self, request: &RESP) -> Result<RequestId, DumpError> {
+        dump_append(request, &mut self.out)?;
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.push_back(Pending::Request(id));
+        Ok(id)
+    }
+
+    /// Returns the bytes which need writing, which stay valid until the next call.
+    pub fn take_output(&mut self) -> &[u8] {
+        self.taken.clear();
+        mem::swap(&mut self.taken, &mut self.out);
+        &self.taken
+    }
+
+    /// Accepts bytes read from the server, returning the events they complete.
+    pub fn handle_input(&mut self, bytes: &[u8]) -> Result<Vec<Event>, ParseError> {
+        self.decoder.feed(bytes);
+        let mut events = Vec::new();
+        while let Some((_, resp)) = self.decoder.decode()? {
+            if let RESP::Push(_) = resp {
+                // Subscription confirmations answer the command which asked for them, unless
+                // it's already been answered, such as by the confirmation of its first channel.
+                if !is_subscription_reply(&resp) || self.pending.is_empty() {
+                    events.push(Event::Push(resp));
+                    continue;
+                }
+            }
+            let event = match self.pending.pop_front() {
+                Some(Pending::Handshake) => {
+                    self.handshake_remaining -= 1;
+                    if let RESP::Error(_) | RESP::BlobError(_) = resp {
+                        self.handshake_failed = true;
+                        Event::HandshakeFailed(resp)
+                    } else if self.handshake_remaining == 0 && !self.handshake_failed {
+                        Event::Ready
+                    } else {
+                        continue;
+                    }
+                }
+                Some(Pending::Request(id)) => Event::Reply { id, reply: resp },
+                None => Event::Unsolicited(resp),
+            };
+            events.push(event);
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow::Borrowed;
+
+    #[test]
+    fn test_protocol() {
+        let handshake = vec![
+            RESP::command(vec!["AUTH", "secret"]),
+            RESP::command(vec!["SELECT", "1"]),
+        ];
+        let mut protocol = Protocol::new(&handshake).unwrap();
+        let get = protocol
+            .request(&RESP::command(vec!["GET", "foo"]))
+            .unwrap();
+        assert_eq!(
+            protocol.take_output(),
+            &b"*2\r\n$4\r\nAUTH\r\n$6\r\nsecret\r\n*2\r\n$6\r\nSELECT\r\n$1\r\n1\r\n\
+               *2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n"[..]
+        );
+        assert_eq!(protocol.take_output(), b"");
+        assert_eq!(protocol.in_flight(), 3);
+
+        assert_eq!(protocol.handle_input(b"+OK\r\n+O"), Ok(vec![]));
+        assert!(!protocol.is_ready());
+        assert_eq!(
+            protocol.handle_input(b"K\r\n>1\r\n+hi\r\n$3\r\nbar\r\n:1\r\n"),
+            Ok(vec![
+                Event::Ready,
+                Event::Push(RESP::Push(vec![RESP::SimpleString(Borrowed("hi"))])),
+                Event::Reply {
+                    id: get,
+                    reply: RESP::BulkString(Borrowed("bar")),
+                },
+                Event::Unsolicited(RESP::Integer(1)),
+            ])
+        );
+        assert!(protocol.is_ready());
+    }
+
+    #[test]
+    fn test_subscription_replies() {
+        let mut protocol = Protocol::new(&[]).unwrap();
+        let subscribe = protocol
+            .request(&RESP::command(vec!["SUBSCRIBE", "news"]))
+            .unwrap();
+        let get = protocol
+            .request(&RESP::command(vec!["GET", "foo"]))
+            .unwrap();
+        let confirm = RESP::Push(vec![
+            RESP::BulkString(Borrowed("subscribe")),
+            RESP::BulkString(Borrowed("news")),
+            RESP::Integer(1),
+        ]);
+        let message = RESP::Push(vec![
+            RESP::BulkString(Borrowed("message")),
+            RESP::BulkString(Borrowed("news")),
+            RESP::BulkString(Borrowed("hi")),
+        ]);
+        assert_eq!(
+            protocol.handle_input(
+                b">3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n\
+                  >3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$2\r\nhi\r\n$3\r\nbar\r\n"
+            ),
+            Ok(vec![
+                Event::Reply {
+                    id: subscribe,
+                    reply: confirm,
+                },
+                Event::Push(message),
+                Event::Reply {
+                    id: get,
+                    reply: RESP::BulkString(Borrowed("bar")),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_handshake_failed() {
+        let handshake = vec![
+            RESP::command(vec!["HELLO", "3"]),
+            RESP::command(vec!["AUTH", "secret"]),
+        ];
+        let mut protocol = Protocol::new(&handshake).unwrap();
+        assert_eq!(
+            protocol.handle_input(b"!7\r\nNOPROTO\r\n-WRONGPASS\r\n"),
+            Ok(vec![
+                Event::HandshakeFailed(RESP::BlobError(Borrowed("NOPROTO"))),
+                Event::HandshakeFailed(RESP::Error(Borrowed("WRONGPASS"))),
+            ])
+        );
+        assert!(!protocol.is_ready());
+        assert!(protocol.handshake_failed());
+    }
+
+    #[test]
+    fn test_request_error() {
+        let invalid = RESP::Array(vec![
+            RESP::BulkString(Borrowed("SET")),
+            RESP::SimpleString(Borrowed("a\r\nb")),
+        ]);
+        assert!(Protocol::new(&[RESP::command(vec!["PING"]), invalid.clone()]).is_err());
+
+        let mut protocol = Protocol::new(&[]).unwrap();
+        assert!(protocol.request(&invalid).is_err());
+        protocol.request(&RESP::command(vec!["PING"])).unwrap();
+        assert_eq!(protocol.take_output(), b"*1\r\n$4\r\nPING\r\n");
+        assert_eq!(protocol.in_flight(), 1);
+    }
+}
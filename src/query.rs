@@ -0,0 +1,92 @@
+//! Navigation into nested values by a dotted path, such as `1.2.name`.
+use crate::RESP;
+
+impl<'a> RESP<'a> {
+    /// Returns the value at `path`, or `None` if there isn't one.
+    ///
+    /// Each `.` separated segment is an index into an array, set or push, or a key to look up in
+    /// a map, which matches string keys by their text and integer keys by their decimal form.
+    /// Attributes are skipped over to the value they describe. An empty path is the value
+    /// itself.
+    pub fn query(&self, path: &str) -> Option<&RESP<'a>> {
+        let mut resp = self;
+        for segment in path.split('.').filter(|s| !s.is_empty()) {
+            while let RESP::Attribute(_, value) = resp {
+                resp = value;
+            }
+            resp = match resp {
+                RESP::Array(elements) | RESP::Set(elements) | RESP::Push(elements) => {
+                    elements.get(segment.parse::<usize>().ok()?)?
+                }
+                RESP::Map(pairs) => {
+                    let (_, value) = pairs.iter().find(|(k, _)| is_key(k, segment))?;
+                    value
+                }
+                _ => return None,
+            };
+        }
+        while let RESP::Attribute(_, value) = resp {
+            resp = value;
+        }
+        Some(resp)
+    }
+
+    /// Returns the text of the string at `path`.
+    pub fn query_str(&self, path: &str) -> Option<&str> {
+        match self.query(path)? {
+            RESP::SimpleString(s) | RESP::BulkString(s) | RESP::VerbatimString(_, s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer at `path`, parsing it from a string if need be, as many replies
+    /// send numbers as bulk strings.
+    pub fn query_int(&self, path: &str) -> Option<i64> {
+        match self.query(path)? {
+            RESP::Integer(i) => Some(*i),
+            RESP::SimpleString(s) | RESP::BulkString(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+fn is_key(key: &RESP, segment: &str) -> bool {
+    match key {
+        RESP::SimpleString(s) | RESP::BulkString(s) | RESP::VerbatimString(_, s) => s == segment,
+        RESP::Integer(i) => segment.parse() == Ok(*i),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use std::borrow::Cow::Borrowed;
+
+    #[test]
+    fn test_query() {
+        let buf =
+            b"*2\r\n:7\r\n*3\r\n+a\r\n+b\r\n%2\r\n$4\r\nname\r\n$3\r\nbob\r\n+age\r\n$2\r\n42\r\n";
+        let (_, resp) = parse(buf).unwrap();
+        let test_cases = vec![
+            ("0", Some(RESP::Integer(7))),
+            ("1.2.name", Some(RESP::BulkString(Borrowed("bob")))),
+            ("1.0", Some(RESP::SimpleString(Borrowed("a")))),
+            ("1.3", None),
+            ("1.2.missing", None),
+            ("0.0", None),
+            ("x", None),
+        ];
+        for (path, expected) in test_cases {
+            assert_eq!(resp.query(path), expected.as_ref(), "{}", path);
+        }
+        assert_eq!(resp.query(""), Some(&resp));
+        assert_eq!(resp.query_str("1.2.name"), Some("bob"));
+        assert_eq!(resp.query_int("1.2.age"), Some(42));
+        assert_eq!(resp.query_int("1.2.name"), None);
+
+        let (_, resp) = parse(b"|1\r\n+ttl\r\n:1\r\n%1\r\n:5\r\n+five\r\n").unwrap();
+        assert_eq!(resp.query_str("5"), Some("five"));
+    }
+}
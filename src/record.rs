@@ -0,0 +1,275 @@
+//! Recording of connection traffic, and replaying it through the decoder.
+//!
+//! A recording is a sequence of records, each holding the bytes of one read or write:
+//!
+//! ```text
+//! <direction> <microseconds since the recording started> <length>\n<bytes>\n
+//! ```
+//!
+//! where the direction is `>` for bytes sent and `<` for bytes received. Keeping the original
+//! segmentation means replaying reproduces partial frames exactly as they arrived.
+use crate::decoder::Decoder;
+use crate::{Error, RESPOwned};
+use std::convert::TryFrom;
+use std::io::{self, BufRead, Read, Write};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+impl Direction {
+    pub(crate) fn byte(self) -> u8 {
+        match self {
+            Direction::Sent => b'>',
+            Direction::Received => b'<',
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Record {
+    pub direction: Direction,
+    /// Time since the recording started.
+    pub elapsed: Duration,
+    pub bytes: Vec<u8>,
+}
+
+/// Wraps a stream, recording everything read from and written to it into `log`.
+///
+/// Pass it to `Connection::new` to record a connection.
+pub struct Recorder<S, W: Write> {
+    inner: S,
+    log: W,
+    start: Instant,
+}
+
+impl<S, W: Write> Recorder<S, W> {
+    pub fn new(inner: S, log: W) -> Recorder<S, W> {
+        Recorder {
+            inner,
+            log,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn into_inner(self) -> (S, W) {
+        (self.inner, self.log)
+    }
+
+    fn record(&mut self, direction: Direction, bytes: &[u8]) -> io::Result<()> {
+        let micros = self.start.elapsed().as_micros();
+        writeln!(
+            self.log,
+            "{} {} {}",
+            direction.byte() as char,
+            micros,
+            bytes.len()
+        )?;
+        self.log.write_all(bytes)?;
+        self.log.write_all(b"\n")
+    }
+}
+
+impl<S: Read, W: Write> Read for Recorder<S, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.record(Direction::Received, &buf[..n])?;
+        }
+        Ok(n)
+    }
+}
+
+impl<S: Write, W: Write> Write for Recorder<S, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.record(Direction::Sent, &buf[..n])?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        self.log.flush()
+    }
+}
+
+/// Reads the records of a recording.
+pub struct Replayer<R> {
+    reader: R,
+}
+
+impl<R: BufRead> Replayer<R> {
+    pub fn new(reader: R) -> Replayer<R> {
+        Replayer { reader }
+    }
+
+    /// Decodes the recorded bytes into frames, reporting each with the time its last byte was
+    /// recorded at.
+    pub fn frames(self) -> Frames<R> {
+        Frames {
+            records: self,
+            sent: Decoder::new(),
+            received: Decoder::new(),
+            current: None,
+        }
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<Record>> {
+        let mut header = Vec::new();
+        if self.reader.read_until(b'\n', &mut header)? == 0 {
+            return Ok(None);
+        }
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid record header");
+        let header = std::str::from_utf8(&header).map_err(|_| invalid())?;
+        let mut fields = header.trim_end().split(' ');
+        let direction = match fields.next() {
+            Some(">") => Direction::Sent,
+            Some("<") => Direction::Received,
+            _ => return Err(invalid()),
+        };
+        let mut number = || -> io::Result<u64> {
+            fields
+                .next()
+                .and_then(|f| f.parse().ok())
+                .ok_or_else(invalid)
+        };
+        let elapsed = Duration::from_micros(number()?);
+        let len = usize::try_from(number()?)
+            .ok()
+            .and_then(|len| len.checked_add(1))
+            .ok_or_else(invalid)?;
+        let mut bytes = vec![0; len];
+        self.reader.read_exact(&mut bytes)?;
+        if bytes.pop() != Some(b'\n') {
+            return Err(invalid());
+        }
+        Ok(Some(Record {
+            direction,
+            elapsed,
+            bytes,
+        }))
+    }
+}
+
+impl<R: BufRead> Iterator for Replayer<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// The frames of a recording, from `Replayer::frames`.
+pub struct Frames<R> {
+    records: Replayer<R>,
+    sent: Decoder,
+    received: Decoder,
+    /// The direction and time of the record currently being decoded.
+    current: Option<(Direction, Duration)>,
+}
+
+impl<R: BufRead> Iterator for Frames<R> {
+    type Item = Result<(Direction, Duration, RESPOwned), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((direction, elapsed)) = self.current {
+                let decoder = match direction {
+                    Direction::Sent => &mut self.sent,
+                    Direction::Received => &mut self.received,
+                };
+                match decoder.decode() {
+                    Ok(Some((_, resp))) => return Some(Ok((direction, elapsed, resp))),
+                    Ok(None) => self.current = None,
+                    Err(err) => {
+                        self.current = None;
+                        return Some(Err(Error::Parse(err)));
+                    }
+                }
+            }
+            let record = match self.records.read_record() {
+                Ok(Some(record)) => record,
+                Ok(None) => return None,
+                Err(err) => return Some(Err(Error::Io(err))),
+            };
+            match record.direction {
+                Direction::Sent => self.sent.feed(&record.bytes),
+                Direction::Received => self.received.feed(&record.bytes),
+            }
+            self.current = Some((record.direction, record.elapsed));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::Connection;
+    use crate::RESP;
+    use std::borrow::Cow::Borrowed;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_record_and_replay() {
+        // A stream which has already received a reply split across two reads.
+        struct Stream(Vec<&'static [u8]>, Vec<u8>);
+        impl Read for Stream {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                match self.0.pop() {
+                    Some(bytes) => {
+                        buf[..bytes.len()].copy_from_slice(bytes);
+                        Ok(bytes.len())
+                    }
+                    None => Ok(0),
+                }
+            }
+        }
+        impl Write for Stream {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.1.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let stream = Stream(vec![b"NG\r\n", b"+PO"], Vec::new());
+        let mut conn = Connection::new(Recorder::new(stream, Vec::new()));
+        conn.call(&RESP::command(vec!["PING"])).unwrap();
+        let (_, log) = conn.into_inner().into_inner();
+
+        let records: Vec<_> = Replayer::new(Cursor::new(&log))
+            .map(|r| {
+                let r = r.unwrap();
+                (r.direction, r.bytes)
+            })
+            .collect();
+        assert_eq!(
+            records,
+            vec![
+                (Direction::Sent, b"*1\r\n$4\r\nPING\r\n".to_vec()),
+                (Direction::Received, b"+PO".to_vec()),
+                (Direction::Received, b"NG\r\n".to_vec()),
+            ]
+        );
+
+        let frames: Vec<_> = Replayer::new(Cursor::new(&log))
+            .frames()
+            .map(|f| {
+                let (direction, _, resp) = f.unwrap();
+                (direction, resp)
+            })
+            .collect();
+        assert_eq!(
+            frames,
+            vec![
+                (Direction::Sent, RESP::command(vec!["PING"])),
+                (Direction::Received, RESP::SimpleString(Borrowed("PONG"))),
+            ]
+        );
+    }
+}
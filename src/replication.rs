@@ -0,0 +1,423 @@
+//! Decoding of the stream a master sends to a replica after `PSYNC`.
+//!
+//! The stream starts with a `+FULLRESYNC <replid> <offset>` (or `+CONTINUE`) reply. A full resync
+//! is followed by the RDB snapshot, framed either as `$<len>\r\n<payload>` or, for diskless
+//! replication, as `$EOF:<mark>\r\n<payload><mark>`. Neither framing has a trailing CRLF, which
+//! is why the snapshot can't be parsed as a bulk string. What follows is the stream of commands
+//! the master propagates, each of which advances the replication offset by its length.
+//!
+//! The replica reports the offset it has processed back with `REPLCONF ACK <offset>`, about once
+//! a second and whenever the master sends `REPLCONF GETACK`. `AckTracker` keeps that bookkeeping.
+use crate::{parse, ParseError, RESPOwned, BULK_STRING_BYTE, RESP};
+use std::borrow::Cow::Borrowed;
+use std::str;
+use std::time::{Duration, Instant};
+
+const EOF_MARK_PREFIX: &[u8] = b"$EOF:";
+const EOF_MARK_LEN: usize = 40;
+
+/// Builds the `PSYNC` command, asking for a full resync when `replid` and `offset` are unknown.
+pub fn psync_command(replid: Option<&str>, offset: Option<u64>) -> RESP<'_> {
+    let offset = match offset {
+        Some(offset) => offset.to_string(),
+        None => "-1".to_string(),
+    };
+    RESP::command(vec![
+        Borrowed("PSYNC"),
+        Borrowed(replid.unwrap_or("?")),
+        offset.into(),
+    ])
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    /// The master is going to send a snapshot, after which the commands start at `offset`.
+    FullResync { replid: String, offset: u64 },
+    /// The master is continuing from the offset the replica asked for.
+    Continue { replid: Option<String> },
+    /// A chunk of the RDB snapshot.
+    Rdb(Vec<u8>),
+    /// The end of the RDB snapshot.
+    RdbEnd,
+    /// A propagated command, the replication offset it starts at, and its length in bytes.
+    Command {
+        offset: u64,
+        len: usize,
+        command: RESPOwned,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ReplicationError {
+    Parse(ParseError),
+    /// The master replied with something other than the expected handshake or snapshot header.
+    UnexpectedReply(RESPOwned),
+}
+
+impl From<ParseError> for ReplicationError {
+    fn from(err: ParseError) -> ReplicationError {
+        ReplicationError::Parse(err)
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    Handshake,
+    RdbHeader,
+    Rdb { remaining: usize },
+    RdbEofMark { mark: Vec<u8> },
+    RdbEnd,
+    Commands,
+}
+
+/// Decodes the replication stream from bytes fed to it as they arrive from the master.
+#[derive(Debug)]
+pub struct Decoder {
+    buf: Vec<u8>,
+    state: State,
+    offset: u64,
+}
+
+impl Default for Decoder {
+    fn default() -> Decoder {
+        Decoder::new()
+    }
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder::with_offset(0)
+    }
+
+    /// Creates a decoder for a partial resync, where the master continues from `offset`.
+    pub fn with_offset(offset: u64) -> Decoder {
+        Decoder {
+            buf: Vec::new(),
+            state: State::Handshake,
+            offset,
+        }
+    }
+
+    /// Returns the replication offset of the next command.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Returns the next event, or `None` if more bytes need to be fed first.
+    pub fn next_event(&mut self) -> Result<Option<Event>, ReplicationError> {
+        match &mut self.state {
+            State::Handshake => {
+                self.skip_keepalives();
+                let (n, event) = match parse(&self.buf) {
+                    Ok((n, RESP::SimpleString(line))) => (n, handshake_event(&line)),
+                    Ok((_, resp)) => {
+                        return Err(ReplicationError::UnexpectedReply(resp.into_owned()))
+                    }
+                    Err(ParseError::Incomplete) | Err(ParseError::CLRFNotFound) => return Ok(None),
+                    Err(err) => return Err(err.into()),
+                };
+                let event = event?;
+                self.buf.drain(..n);
+                if let Event::FullResync { offset, .. } = event {
+                    self.offset = offset;
+                    self.state = State::RdbHeader;
+                } else {
+                    self.state = State::Commands;
+                }
+                Ok(Some(event))
+            }
+            State::RdbHeader => {
+                self.skip_keepalives();
+                let end = match self.buf.windows(2).position(|w| w == b"\r\n") {
+                    Some(end) => end,
+                    None => return Ok(None),
+                };
+                let header = &self.buf[..end];
+                let mark = header
+                    .strip_prefix(EOF_MARK_PREFIX)
+                    .filter(|mark| mark.len() == EOF_MARK_LEN);
+                self.state = if let Some(mark) = mark {
+                    State::RdbEofMark {
+                        mark: mark.to_vec(),
+                    }
+                } else if header.first() == Some(&BULK_STRING_BYTE) {
+                    let len = str::from_utf8(&header[1..])
+                        .map_err(ParseError::Utf8Error)?
+                        .parse()
+                        .map_err(ParseError::ParseIntError)?;
+                    State::Rdb { remaining: len }
+                } else {
+                    let (_, resp) = parse(&self.buf[..end + 2])?;
+                    return Err(ReplicationError::UnexpectedReply(resp.into_owned()));
+                };
+                self.buf.drain(..end + 2);
+                self.next_event()
+            }
+            State::Rdb { remaining } => {
+                if *remaining == 0 {
+                    self.state = State::RdbEnd;
+                    return self.next_event();
+                }
+                if self.buf.is_empty() {
+                    return Ok(None);
+                }
+                let n = self.buf.len().min(*remaining);
+                *remaining -= n;
+                Ok(Some(Event::Rdb(self.buf.drain(..n).collect())))
+            }
+            State::RdbEofMark { mark } => {
+                if let Some(i) = self.buf.windows(EOF_MARK_LEN).position(|w| w == &mark[..]) {
+                    let chunk: Vec<u8> = self.buf.drain(..i).collect();
+                    self.buf.drain(..EOF_MARK_LEN);
+                    self.state = State::RdbEnd;
+                    if chunk.is_empty() {
+                        return self.next_event();
+                    }
+                    return Ok(Some(Event::Rdb(chunk)));
+                }
+                // The tail might be the start of the mark, so hold it back.
+                let n = self.buf.len().saturating_sub(EOF_MARK_LEN - 1);
+                if n == 0 {
+                    return Ok(None);
+                }
+                Ok(Some(Event::Rdb(self.buf.drain(..n).collect())))
+            }
+            State::RdbEnd => {
+                self.state = State::Commands;
+                Ok(Some(Event::RdbEnd))
+            }
+            State::Commands => {
+                let (n, command) = match parse(&self.buf) {
+                    Ok((n, command)) => (n, command.into_owned()),
+                    Err(ParseError::Incomplete) | Err(ParseError::CLRFNotFound) => return Ok(None),
+                    Err(err) => return Err(err.into()),
+                };
+                self.buf.drain(..n);
+                let offset = self.offset;
+                self.offset += n as u64;
+                Ok(Some(Event::Command {
+                    offset,
+                    len: n,
+                    command,
+                }))
+            }
+        }
+    }
+
+    /// Skips the newlines a master sends to keep the connection alive while preparing a snapshot.
+    fn skip_keepalives(&mut self) {
+        let n = self.buf.iter().take_while(|&&b| b == b'\n').count();
+        self.buf.drain(..n);
+    }
+}
+
+/// Builds the `REPLCONF ACK` command acknowledging everything before `offset`.
+pub fn replconf_ack_command(offset: u64) -> RESPOwned {
+    RESP::command(vec![
+        Borrowed("REPLCONF"),
+        Borrowed("ACK"),
+        offset.to_string().into(),
+    ])
+}
+
+/// Tracks the offset a replica has processed and when it needs to be acknowledged.
+///
+/// Events are passed to `consume` once they have been applied, not when they are decoded, so that
+/// acknowledgements never cover commands the replica could still lose.
+#[derive(Debug)]
+pub struct AckTracker {
+    offset: u64,
+    interval: Duration,
+    last_ack: Option<Instant>,
+}
+
+impl AckTracker {
+    /// Creates a tracker acknowledging at most every `interval`, starting from `offset`.
+    pub fn new(offset: u64, interval: Duration) -> AckTracker {
+        AckTracker {
+            offset,
+            interval,
+            last_ack: None,
+        }
+    }
+
+    /// Returns the offset processed so far.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Records an event as processed, returning the acknowledgement to send immediately if the
+    /// master asked for one.
+    ///
+    /// Like Redis, the acknowledgement of a `REPLCONF GETACK` doesn't cover the command itself.
+    pub fn consume(&mut self, event: &Event) -> Option<RESPOwned> {
+        match event {
+            Event::FullResync { offset, .. } => {
+                self.offset = *offset;
+                None
+            }
+            Event::Command {
+                offset,
+                len,
+                command,
+            } => {
+                self.offset = offset + *len as u64;
+                if is_getack(command) {
+                    self.last_ack = Some(Instant::now());
+                    return Some(replconf_ack_command(*offset));
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the periodic acknowledgement to send, if one is due at `now`.
+    pub fn poll(&mut self, now: Instant) -> Option<RESPOwned> {
+        match self.last_ack {
+            Some(last) if now.duration_since(last) < self.interval => None,
+            _ => {
+                self.last_ack = Some(now);
+                Some(replconf_ack_command(self.offset))
+            }
+        }
+    }
+}
+
+fn is_getack(command: &RESP) -> bool {
+    match command {
+        RESP::Array(args) => match args.as_slice() {
+            [RESP::BulkString(name), RESP::BulkString(sub), ..] => {
+                name.eq_ignore_ascii_case("REPLCONF") && sub.eq_ignore_ascii_case("GETACK")
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn handshake_event(line: &str) -> Result<Event, ReplicationError> {
+    let unexpected =
+        || ReplicationError::UnexpectedReply(RESP::SimpleString(line.to_string().into()));
+    let mut parts = line.split(' ');
+    match parts.next() {
+        Some("FULLRESYNC") => {
+            let replid = parts.next().ok_or_else(unexpected)?.to_string();
+            let offset = parts
+                .next()
+                .ok_or_else(unexpected)?
+                .parse()
+                .map_err(ParseError::ParseIntError)?;
+            Ok(Event::FullResync { replid, offset })
+        }
+        Some("CONTINUE") => Ok(Event::Continue {
+            replid: parts.next().map(str::to_string),
+        }),
+        _ => Err(unexpected()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPLID: &str = "8de1787ba490483314a4d30f1c628bc5025eb761";
+
+    fn events(stream: &[u8], chunk: usize) -> Vec<Event> {
+        let mut decoder = Decoder::new();
+        let mut events = Vec::new();
+        for bytes in stream.chunks(chunk) {
+            decoder.feed(bytes);
+            while let Some(event) = decoder.next_event().unwrap() {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Merges consecutive RDB chunks so that streams fed in different chunk sizes compare equal.
+    fn merge_rdb(events: Vec<Event>) -> Vec<Event> {
+        let mut merged: Vec<Event> = Vec::new();
+        for event in events {
+            match (merged.last_mut(), event) {
+                (Some(Event::Rdb(rdb)), Event::Rdb(chunk)) => rdb.extend(chunk),
+                (_, event) => merged.push(event),
+            }
+        }
+        merged
+    }
+
+    #[test]
+    fn test_full_resync() {
+        let ping = b"*1\r\n$4\r\nPING\r\n";
+        let mut sized = format!("+FULLRESYNC {} 100\r\n\n\n$5\r\nREDIS", REPLID).into_bytes();
+        sized.extend_from_slice(ping);
+        let mut diskless = format!(
+            "+FULLRESYNC {} 100\r\n$EOF:{}\r\nREDIS{}",
+            REPLID, REPLID, REPLID
+        )
+        .into_bytes();
+        diskless.extend_from_slice(ping);
+
+        let expected = vec![
+            Event::FullResync {
+                replid: REPLID.to_string(),
+                offset: 100,
+            },
+            Event::Rdb(b"REDIS".to_vec()),
+            Event::RdbEnd,
+            Event::Command {
+                offset: 100,
+                len: 14,
+                command: RESP::command(vec!["PING"]),
+            },
+        ];
+        for stream in &[sized, diskless] {
+            for chunk in &[1, 7, stream.len()] {
+                assert_eq!(merge_rdb(events(stream, *chunk)), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_continue() {
+        let mut decoder = Decoder::with_offset(42);
+        decoder.feed(b"+CONTINUE\r\n*1\r\n$4\r\nPING\r\n*1\r\n$4\r\nPING\r\n");
+        assert_eq!(
+            decoder.next_event(),
+            Ok(Some(Event::Continue { replid: None }))
+        );
+        assert!(matches!(
+            decoder.next_event(),
+            Ok(Some(Event::Command { offset: 42, .. }))
+        ));
+        assert!(matches!(
+            decoder.next_event(),
+            Ok(Some(Event::Command { offset: 56, .. }))
+        ));
+        assert_eq!(decoder.offset(), 70);
+        assert_eq!(decoder.next_event(), Ok(None));
+    }
+
+    #[test]
+    fn test_ack_tracker() {
+        let mut decoder = Decoder::new();
+        decoder.feed(format!("+FULLRESYNC {} 100\r\n$0\r\n", REPLID).as_bytes());
+        decoder.feed(b"*1\r\n$4\r\nPING\r\n*3\r\n$8\r\nREPLCONF\r\n$6\r\nGETACK\r\n$1\r\n*\r\n");
+        let mut tracker = AckTracker::new(0, Duration::from_secs(1));
+        let mut acks = Vec::new();
+        while let Some(event) = decoder.next_event().unwrap() {
+            acks.extend(tracker.consume(&event));
+        }
+        assert_eq!(acks, vec![replconf_ack_command(114)]);
+        assert_eq!(tracker.offset(), 151);
+
+        let now = Instant::now() + Duration::from_secs(2);
+        assert_eq!(tracker.poll(now), Some(replconf_ack_command(151)));
+        assert_eq!(tracker.poll(now), None);
+    }
+}
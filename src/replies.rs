@@ -0,0 +1,786 @@
+//! Decoding of the replies most commands share the shapes of, with errors which say what shape
+//! was expected.
+use crate::error_code::ErrorCode;
+use crate::{RESPOwned, RESP};
+use std::borrow::Cow::Owned;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplyError {
+    /// The server replied with an error.
+    Server(String),
+    /// The reply, or one of its elements, isn't of the expected shape.
+    UnexpectedShape {
+        expected: &'static str,
+        actual: RESPOwned,
+    },
+}
+
+impl ReplyError {
+    /// Returns the code of the server's error, if it replied with one that has a standard code.
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            ReplyError::Server(message) => ErrorCode::from_message(message),
+            ReplyError::UnexpectedShape { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for ReplyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplyError::Server(message) => write!(f, "server error: {}", message),
+            ReplyError::UnexpectedShape { expected, actual } => {
+                write!(f, "expected {}, got {:?}", expected, actual.kind())
+            }
+        }
+    }
+}
+
+/// Decodes `+OK`, as `SET` and most other writes reply.
+pub fn parse_ok(reply: &RESP) -> Result<(), ReplyError> {
+    match reply {
+        RESP::SimpleString(s) if s == "OK" => Ok(()),
+        _ => Err(unexpected("OK", reply)),
+    }
+}
+
+/// Decodes an integer, as `INCR` or `DEL` reply.
+pub fn parse_int(reply: &RESP) -> Result<i64, ReplyError> {
+    match reply {
+        RESP::Integer(i) => Ok(*i),
+        _ => Err(unexpected("integer", reply)),
+    }
+}
+
+/// Decodes a string, or `None` for a null, as `GET` replies.
+pub fn parse_string<'r>(reply: &'r RESP) -> Result<Option<&'r str>, ReplyError> {
+    match reply {
+        RESP::NullBulkString | RESP::Null => Ok(None),
+        _ => string(reply).map(Some),
+    }
+}
+
+/// Decodes an array or set of strings, as `KEYS` or `SMEMBERS` reply.
+pub fn parse_string_array<'r>(reply: &'r RESP) -> Result<Vec<&'r str>, ReplyError> {
+    match reply {
+        RESP::Array(elements) | RESP::Set(elements) => elements.iter().map(string).collect(),
+        _ => Err(unexpected("array of strings", reply)),
+    }
+}
+
+/// Decodes string pairs, from either a map or the flat array of keys and values RESP2 replies
+/// with, as `HGETALL` or `CONFIG GET` reply.
+pub fn parse_kv_pairs<'r>(reply: &'r RESP) -> Result<Vec<(&'r str, &'r str)>, ReplyError> {
+    match reply {
+        RESP::Map(pairs) => pairs
+            .iter()
+            .map(|(k, v)| Ok((string(k)?, string(v)?)))
+            .collect(),
+        RESP::Array(elements) if elements.len() % 2 == 0 => elements
+            .chunks(2)
+            .map(|pair| Ok((string(&pair[0])?, string(&pair[1])?)))
+            .collect(),
+        _ => Err(unexpected("map or array of key value pairs", reply)),
+    }
+}
+
+/// Lookups into string-keyed pairs, from either a map or the flat array of keys and values RESP2
+/// replies with, without copying them out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MapView<'r, 'a> {
+    pairs: Pairs<'r, 'a>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pairs<'r, 'a> {
+    Map(&'r [(RESP<'a>, RESP<'a>)]),
+    Flat(&'r [RESP<'a>]),
+}
+
+impl<'r, 'a> MapView<'r, 'a> {
+    pub fn new(reply: &'r RESP<'a>) -> Result<MapView<'r, 'a>, ReplyError> {
+        let pairs = match reply {
+            RESP::Map(pairs) => Pairs::Map(pairs),
+            RESP::Array(elements) if elements.len() % 2 == 0 => Pairs::Flat(elements),
+            _ => return Err(unexpected("map or array of key value pairs", reply)),
+        };
+        Ok(MapView { pairs })
+    }
+
+    /// Returns the number of pairs.
+    pub fn len(&self) -> usize {
+        match self.pairs {
+            Pairs::Map(pairs) => pairs.len(),
+            Pairs::Flat(elements) => elements.len() / 2,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over the pairs in the order they were sent.
+    pub fn iter(&self) -> impl Iterator<Item = (&'r RESP<'a>, &'r RESP<'a>)> {
+        let pairs = self.pairs;
+        (0..self.len()).map(move |i| match pairs {
+            Pairs::Map(pairs) => (&pairs[i].0, &pairs[i].1),
+            Pairs::Flat(elements) => (&elements[2 * i], &elements[2 * i + 1]),
+        })
+    }
+
+    /// Returns the value of the first pair whose key is a string equal to `key`.
+    pub fn get(&self, key: &str) -> Option<&'r RESP<'a>> {
+        self.iter()
+            .find(|(k, _)| string(k).ok() == Some(key))
+            .map(|(_, v)| v)
+    }
+
+    /// Returns the value of `key` if it's a string.
+    pub fn get_str(&self, key: &str) -> Option<&'r str> {
+        self.get(key).and_then(|v| string(v).ok())
+    }
+
+    /// Returns the value of `key` if it's an integer, or a string of one, as `CONFIG GET`
+    /// replies with.
+    pub fn get_int(&self, key: &str) -> Option<i64> {
+        match self.get(key)? {
+            RESP::Integer(i) => Some(*i),
+            v => string(v).ok()?.parse().ok(),
+        }
+    }
+
+    /// Returns the value of `key` if it's a double, an integer or a string of either.
+    pub fn get_f64(&self, key: &str) -> Option<f64> {
+        match self.get(key)? {
+            RESP::Double(d) => Some(*d),
+            RESP::Integer(i) => Some(*i as f64),
+            v => string(v).ok()?.parse().ok(),
+        }
+    }
+}
+
+/// A position on the globe, as the `GEO` commands reply with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coordinates {
+    pub longitude: f64,
+    pub latitude: f64,
+}
+
+/// A member matched by `GEOSEARCH` or `GEORADIUS`, along with whichever of `WITHDIST`,
+/// `WITHHASH` and `WITHCOORD` were asked for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoMember<'r> {
+    pub member: &'r str,
+    pub distance: Option<f64>,
+    pub hash: Option<i64>,
+    pub coordinates: Option<Coordinates>,
+}
+
+/// Decodes the position of each member, or `None` for a missing one, as `GEOPOS` replies.
+pub fn parse_geopos(reply: &RESP) -> Result<Vec<Option<Coordinates>>, ReplyError> {
+    match reply {
+        RESP::Array(elements) => elements
+            .iter()
+            .map(|element| match element {
+                RESP::NullArray | RESP::Null => Ok(None),
+                _ => coordinates(element).map(Some),
+            })
+            .collect(),
+        _ => Err(unexpected("array of coordinates", reply)),
+    }
+}
+
+/// Decodes a distance, or `None` if either member is missing, as `GEODIST` replies.
+pub fn parse_geodist(reply: &RESP) -> Result<Option<f64>, ReplyError> {
+    match reply {
+        RESP::NullBulkString | RESP::Null => Ok(None),
+        _ => double(reply).map(Some),
+    }
+}
+
+/// Decodes the members matched by a search, as `GEOSEARCH` replies.
+///
+/// Without any `WITH` options each member is just its name. Otherwise it's an array of the name
+/// followed by the distance, hash and coordinates which were asked for, in that order, which
+/// are told apart by their types.
+pub fn parse_geosearch<'r>(reply: &'r RESP) -> Result<Vec<GeoMember<'r>>, ReplyError> {
+    let member = |element: &'r RESP| {
+        let (name, fields) = match element {
+            RESP::Array(fields) if !fields.is_empty() => (&fields[0], &fields[1..]),
+            _ => (element, &[][..]),
+        };
+        let mut member = GeoMember {
+            member: string(name)?,
+            distance: None,
+            hash: None,
+            coordinates: None,
+        };
+        for field in fields {
+            match field {
+                RESP::Integer(i) => member.hash = Some(*i),
+                RESP::Array(_) => member.coordinates = Some(coordinates(field)?),
+                _ => member.distance = Some(double(field)?),
+            }
+        }
+        Ok(member)
+    };
+    match reply {
+        RESP::Array(elements) => elements.iter().map(member).collect(),
+        _ => Err(unexpected("array of members", reply)),
+    }
+}
+
+/// Decodes members along with their scores, from either the flat array of members and scores
+/// RESP2 replies with or the array of pairs of RESP3, as `ZRANGE ... WITHSCORES` or `ZPOPMIN`
+/// with a count reply.
+///
+/// Scores may be `inf` or `-inf`.
+pub fn parse_withscores<'r>(reply: &'r RESP) -> Result<Vec<(&'r str, f64)>, ReplyError> {
+    let pair = |member: &'r RESP, score: &RESP| Ok((string(member)?, double(score)?));
+    match reply {
+        RESP::Array(elements) if elements.iter().all(|e| matches!(e, RESP::Array(_))) => elements
+            .iter()
+            .map(|element| match element {
+                RESP::Array(p) if p.len() == 2 => pair(&p[0], &p[1]),
+                _ => Err(unexpected("member and score", element)),
+            })
+            .collect(),
+        RESP::Array(elements) if elements.len() % 2 == 0 => {
+            elements.chunks(2).map(|p| pair(&p[0], &p[1])).collect()
+        }
+        _ => Err(unexpected("array of members and scores", reply)),
+    }
+}
+
+/// Decodes the seconds and microseconds since the epoch which `TIME` replies with.
+pub fn parse_time(reply: &RESP) -> Result<SystemTime, ReplyError> {
+    let (secs, micros) = match reply {
+        RESP::Array(parts) if parts.len() == 2 => (integer(&parts[0])?, integer(&parts[1])?),
+        _ => return Err(unexpected("seconds and microseconds", reply)),
+    };
+    if secs < 0 || !(0..1_000_000).contains(&micros) {
+        return Err(unexpected("seconds and microseconds", reply));
+    }
+    Ok(UNIX_EPOCH + Duration::new(secs as u64, micros as u32 * 1000))
+}
+
+/// Encodes a time as `TIME` replies with it, such as for a mock server.
+///
+/// Times before the epoch are sent as the epoch.
+pub fn time_reply(time: SystemTime) -> RESPOwned {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    RESP::Array(vec![
+        RESP::BulkString(Owned(since.as_secs().to_string())),
+        RESP::BulkString(Owned(since.subsec_micros().to_string())),
+    ])
+}
+
+/// The latest spike of a latency event, from `LATENCY LATEST`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyEvent<'r> {
+    pub event: &'r str,
+    /// When the latest spike happened.
+    pub time: SystemTime,
+    pub latest: Duration,
+    /// The longest spike since the server started.
+    pub max: Duration,
+}
+
+/// A spike of a latency event, from `LATENCY HISTORY`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencySample {
+    pub time: SystemTime,
+    pub latency: Duration,
+}
+
+/// Decodes the latest spike of each event, as `LATENCY LATEST` replies.
+pub fn parse_latency_latest<'r>(reply: &'r RESP) -> Result<Vec<LatencyEvent<'r>>, ReplyError> {
+    let event = |element: &'r RESP| match element {
+        RESP::Array(fields) if fields.len() >= 4 => Ok(LatencyEvent {
+            event: string(&fields[0])?,
+            time: timestamp(&fields[1])?,
+            latest: millis(&fields[2])?,
+            max: millis(&fields[3])?,
+        }),
+        _ => Err(unexpected("latency event", element)),
+    };
+    match reply {
+        RESP::Array(elements) => elements.iter().map(event).collect(),
+        _ => Err(unexpected("array of latency events", reply)),
+    }
+}
+
+/// Decodes the spikes of an event, oldest first, as `LATENCY HISTORY` replies.
+pub fn parse_latency_history(reply: &RESP) -> Result<Vec<LatencySample>, ReplyError> {
+    let sample = |element: &RESP| match element {
+        RESP::Array(fields) if fields.len() == 2 => Ok(LatencySample {
+            time: timestamp(&fields[0])?,
+            latency: millis(&fields[1])?,
+        }),
+        _ => Err(unexpected("latency sample", element)),
+    };
+    match reply {
+        RESP::Array(elements) => elements.iter().map(sample).collect(),
+        _ => Err(unexpected("array of latency samples", reply)),
+    }
+}
+
+/// Decodes a Unix timestamp in seconds.
+fn timestamp(resp: &RESP) -> Result<SystemTime, ReplyError> {
+    match integer(resp)? {
+        secs if secs >= 0 => Ok(UNIX_EPOCH + Duration::from_secs(secs as u64)),
+        _ => Err(unexpected("timestamp", resp)),
+    }
+}
+
+/// Decodes a number of milliseconds.
+fn millis(resp: &RESP) -> Result<Duration, ReplyError> {
+    match integer(resp)? {
+        ms if ms >= 0 => Ok(Duration::from_millis(ms as u64)),
+        _ => Err(unexpected("milliseconds", resp)),
+    }
+}
+
+/// The fields of a `MEMORY STATS` reply, each `None` if the server didn't send it.
+///
+/// Sizes are in bytes. Fields this doesn't know of, such as the overheads of each database, are
+/// kept in `unknown` so newer servers' fields can still be read.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemoryStats<'r, 'a> {
+    pub peak_allocated: Option<i64>,
+    pub total_allocated: Option<i64>,
+    pub startup_allocated: Option<i64>,
+    pub replication_backlog: Option<i64>,
+    pub clients_replicas: Option<i64>,
+    pub clients_normal: Option<i64>,
+    pub aof_buffer: Option<i64>,
+    pub overhead_total: Option<i64>,
+    pub keys_count: Option<i64>,
+    pub keys_bytes_per_key: Option<i64>,
+    pub dataset_bytes: Option<i64>,
+    pub dataset_percentage: Option<f64>,
+    pub peak_percentage: Option<f64>,
+    pub fragmentation: Option<f64>,
+    pub unknown: Vec<(&'r str, &'r RESP<'a>)>,
+}
+
+/// Decodes the map of statistics `MEMORY STATS` replies with.
+pub fn parse_memory_stats<'r, 'a>(reply: &'r RESP<'a>) -> Result<MemoryStats<'r, 'a>, ReplyError> {
+    let mut stats = MemoryStats::default();
+    for (key, value) in MapView::new(reply)?.iter() {
+        let key = string(key)?;
+        let int = || integer(value).map(Some);
+        let double = || double(value).map(Some);
+        match key {
+            "peak.allocated" => stats.peak_allocated = int()?,
+            "total.allocated" => stats.total_allocated = int()?,
+            "startup.allocated" => stats.startup_allocated = int()?,
+            "replication.backlog" => stats.replication_backlog = int()?,
+            "clients.slaves" => stats.clients_replicas = int()?,
+            "clients.normal" => stats.clients_normal = int()?,
+            "aof.buffer" => stats.aof_buffer = int()?,
+            "overhead.total" => stats.overhead_total = int()?,
+            "keys.count" => stats.keys_count = int()?,
+            "keys.bytes-per-key" => stats.keys_bytes_per_key = int()?,
+            "dataset.bytes" => stats.dataset_bytes = int()?,
+            "dataset.percentage" => stats.dataset_percentage = double()?,
+            "peak.percentage" => stats.peak_percentage = double()?,
+            "fragmentation" => stats.fragmentation = double()?,
+            _ => stats.unknown.push((key, value)),
+        }
+    }
+    Ok(stats)
+}
+
+/// Decodes the number of bytes a key takes up, or `None` if it doesn't exist, as `MEMORY USAGE`
+/// replies.
+pub fn parse_memory_usage(reply: &RESP) -> Result<Option<i64>, ReplyError> {
+    match reply {
+        RESP::NullBulkString | RESP::Null => Ok(None),
+        _ => parse_int(reply).map(Some),
+    }
+}
+
+/// A user's permissions, from `ACL GETUSER`.
+///
+/// Servers since Redis 7 send key patterns and channels as rules, such as `~cache:*` or
+/// `%R~logs:*`, while older ones send the bare patterns.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AclUser<'r> {
+    pub flags: Vec<&'r str>,
+    /// The hashes of the user's passwords.
+    pub passwords: Vec<&'r str>,
+    pub key_patterns: Vec<&'r str>,
+    pub channels: Vec<&'r str>,
+    /// The command rules, such as `+@all` or `-debug`.
+    pub commands: Vec<&'r str>,
+}
+
+/// A user and their rules, from `ACL LIST`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AclListEntry<'r> {
+    pub user: &'r str,
+    pub rules: Vec<&'r str>,
+}
+
+/// Decodes a user's permissions, or `None` if there's no such user, as `ACL GETUSER` replies.
+pub fn parse_acl_getuser<'r>(reply: &'r RESP) -> Result<Option<AclUser<'r>>, ReplyError> {
+    if let RESP::NullArray | RESP::Null = reply {
+        return Ok(None);
+    }
+    let mut user = AclUser::default();
+    for (key, value) in MapView::new(reply)?.iter() {
+        match string(key)? {
+            "flags" => user.flags = words(value)?,
+            "passwords" => user.passwords = words(value)?,
+            "keys" => user.key_patterns = words(value)?,
+            "channels" => user.channels = words(value)?,
+            "commands" => user.commands = words(value)?,
+            _ => {}
+        }
+    }
+    Ok(Some(user))
+}
+
+/// Decodes the rules of each user, as `ACL LIST` replies.
+pub fn parse_acl_list<'r>(reply: &'r RESP) -> Result<Vec<AclListEntry<'r>>, ReplyError> {
+    let entry = |element: &'r RESP| {
+        let mut words = string(element)?.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("user"), Some(user)) => Ok(AclListEntry {
+                user,
+                rules: words.collect(),
+            }),
+            _ => Err(unexpected("user and rules", element)),
+        }
+    };
+    match reply {
+        RESP::Array(elements) => elements.iter().map(entry).collect(),
+        _ => Err(unexpected("array of users", reply)),
+    }
+}
+
+/// Decodes an array of strings, or a string of space separated words.
+pub(crate) fn words<'r>(resp: &'r RESP) -> Result<Vec<&'r str>, ReplyError> {
+    match resp {
+        RESP::Array(elements) | RESP::Set(elements) => elements.iter().map(string).collect(),
+        _ => Ok(string(resp)?.split_whitespace().collect()),
+    }
+}
+
+fn coordinates(resp: &RESP) -> Result<Coordinates, ReplyError> {
+    match resp {
+        RESP::Array(pair) if pair.len() == 2 => Ok(Coordinates {
+            longitude: double(&pair[0])?,
+            latitude: double(&pair[1])?,
+        }),
+        _ => Err(unexpected("longitude and latitude", resp)),
+    }
+}
+
+/// Decodes a double, or a string of one, as RESP2 sends them.
+fn double(resp: &RESP) -> Result<f64, ReplyError> {
+    match resp {
+        RESP::Double(d) => Ok(*d),
+        RESP::SimpleString(s) | RESP::BulkString(s) => {
+            s.parse().map_err(|_| unexpected("double", resp))
+        }
+        _ => Err(unexpected("double", resp)),
+    }
+}
+
+/// Decodes an integer, or a string of one, as RESP2 often sends them.
+pub(crate) fn integer(resp: &RESP) -> Result<i64, ReplyError> {
+    match resp {
+        RESP::Integer(i) => Ok(*i),
+        RESP::SimpleString(s) | RESP::BulkString(s) => {
+            s.parse().map_err(|_| unexpected("integer", resp))
+        }
+        _ => Err(unexpected("integer", resp)),
+    }
+}
+
+fn string<'r>(resp: &'r RESP) -> Result<&'r str, ReplyError> {
+    match resp {
+        RESP::SimpleString(s) | RESP::BulkString(s) | RESP::VerbatimString(_, s) => Ok(s),
+        _ => Err(unexpected("string", resp)),
+    }
+}
+
+/// Returns the server's error if it replied with one, or else that the reply isn't `expected`.
+fn unexpected(expected: &'static str, reply: &RESP) -> ReplyError {
+    match reply {
+        RESP::Error(message) | RESP::BlobError(message) => ReplyError::Server(message.to_string()),
+        _ => ReplyError::UnexpectedShape {
+            expected,
+            actual: reply.clone().into_owned(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn test_replies() {
+        let reply = |bytes: &[u8]| parse(bytes).unwrap().1.into_owned();
+        assert_eq!(parse_ok(&reply(b"+OK\r\n")), Ok(()));
+        assert_eq!(parse_int(&reply(b":3\r\n")), Ok(3));
+        assert_eq!(parse_string(&reply(b"$3\r\nfoo\r\n")), Ok(Some("foo")));
+        assert_eq!(parse_string(&reply(b"$-1\r\n")), Ok(None));
+        assert_eq!(
+            parse_string_array(&reply(b"~2\r\n+a\r\n$1\r\nb\r\n")),
+            Ok(vec!["a", "b"])
+        );
+        let expected = Ok(vec![("maxmemory", "0")]);
+        let flat = reply(b"*2\r\n$9\r\nmaxmemory\r\n$1\r\n0\r\n");
+        assert_eq!(parse_kv_pairs(&flat), expected);
+        let map = reply(b"%1\r\n$9\r\nmaxmemory\r\n$1\r\n0\r\n");
+        assert_eq!(parse_kv_pairs(&map), expected);
+
+        let err = parse_string_array(&reply(b"*2\r\n+a\r\n:1\r\n")).unwrap_err();
+        assert_eq!(err.to_string(), "expected string, got Integer");
+        assert_eq!(
+            parse_ok(&reply(b"-ERR wrong type\r\n")),
+            Err(ReplyError::Server("ERR wrong type".to_string()))
+        );
+        let err = parse_int(&reply(b"-LOADING still loading\r\n")).unwrap_err();
+        assert!(err.code().unwrap().is_retryable());
+        assert_eq!(err.code(), Some(ErrorCode::Loading));
+        assert_eq!(
+            parse_kv_pairs(&reply(b"*1\r\n+a\r\n"))
+                .unwrap_err()
+                .to_string(),
+            "expected map or array of key value pairs, got Array"
+        );
+    }
+
+    #[test]
+    fn test_geo() {
+        let reply = |bytes: &[u8]| parse(bytes).unwrap().1.into_owned();
+        let coordinates = Coordinates {
+            longitude: 13.5,
+            latitude: 38.25,
+        };
+        assert_eq!(
+            parse_geopos(&reply(b"*2\r\n*2\r\n$4\r\n13.5\r\n$5\r\n38.25\r\n*-1\r\n")),
+            Ok(vec![Some(coordinates), None])
+        );
+        assert_eq!(parse_geodist(&reply(b"$6\r\n166.27\r\n")), Ok(Some(166.27)));
+        assert_eq!(parse_geodist(&reply(b"_\r\n")), Ok(None));
+
+        let members = reply(
+            b"*2\r\n*4\r\n$7\r\nPalermo\r\n$3\r\n1.5\r\n:42\r\n*2\r\n,13.5\r\n,38.25\r\n\
+              *2\r\n$7\r\nCatania\r\n$1\r\n2\r\n",
+        );
+        assert_eq!(
+            parse_geosearch(&members),
+            Ok(vec![
+                GeoMember {
+                    member: "Palermo",
+                    distance: Some(1.5),
+                    hash: Some(42),
+                    coordinates: Some(coordinates),
+                },
+                GeoMember {
+                    member: "Catania",
+                    distance: Some(2.0),
+                    hash: None,
+                    coordinates: None,
+                },
+            ])
+        );
+        let names = reply(b"*1\r\n$7\r\nPalermo\r\n");
+        assert_eq!(parse_geosearch(&names).unwrap()[0].member, "Palermo");
+        assert_eq!(
+            parse_geopos(&reply(b"*1\r\n*1\r\n$1\r\n1\r\n"))
+                .unwrap_err()
+                .to_string(),
+            "expected longitude and latitude, got Array"
+        );
+    }
+
+    #[test]
+    fn test_withscores() {
+        let reply = |bytes: &[u8]| parse(bytes).unwrap().1.into_owned();
+        let expected = Ok(vec![
+            ("a", 1.5),
+            ("b", f64::INFINITY),
+            ("c", f64::NEG_INFINITY),
+        ]);
+        let flat = reply(
+            b"*6\r\n$1\r\na\r\n$3\r\n1.5\r\n$1\r\nb\r\n$3\r\ninf\r\n$1\r\nc\r\n$4\r\n-inf\r\n",
+        );
+        assert_eq!(parse_withscores(&flat), expected);
+        let pairs = reply(
+            b"*3\r\n*2\r\n$1\r\na\r\n,1.5\r\n*2\r\n$1\r\nb\r\n,inf\r\n*2\r\n$1\r\nc\r\n,-inf\r\n",
+        );
+        assert_eq!(parse_withscores(&pairs), expected);
+        assert_eq!(parse_withscores(&reply(b"*0\r\n")), Ok(vec![]));
+        assert_eq!(
+            parse_withscores(&reply(b"*1\r\n$1\r\na\r\n"))
+                .unwrap_err()
+                .to_string(),
+            "expected array of members and scores, got Array"
+        );
+    }
+
+    #[test]
+    fn test_time() {
+        let reply = |bytes: &[u8]| parse(bytes).unwrap().1.into_owned();
+        let time = UNIX_EPOCH + Duration::from_micros(1_700_000_000_123_456);
+        let wire = reply(b"*2\r\n$10\r\n1700000000\r\n$6\r\n123456\r\n");
+        assert_eq!(parse_time(&wire), Ok(time));
+        assert_eq!(time_reply(time), wire);
+        assert_eq!(
+            parse_time(&reply(b"*2\r\n$1\r\n1\r\n$7\r\n1000000\r\n"))
+                .unwrap_err()
+                .to_string(),
+            "expected seconds and microseconds, got Array"
+        );
+        assert_eq!(
+            parse_time(&reply(b"*2\r\n$1\r\n1\r\n+x\r\n"))
+                .unwrap_err()
+                .to_string(),
+            "expected integer, got SimpleString"
+        );
+    }
+
+    #[test]
+    fn test_latency() {
+        let reply = |bytes: &[u8]| parse(bytes).unwrap().1.into_owned();
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        let latest = reply(b"*1\r\n*4\r\n$7\r\ncommand\r\n:1700000000\r\n:12\r\n:250\r\n");
+        assert_eq!(
+            parse_latency_latest(&latest),
+            Ok(vec![LatencyEvent {
+                event: "command",
+                time: at(1_700_000_000),
+                latest: Duration::from_millis(12),
+                max: Duration::from_millis(250),
+            }])
+        );
+        let history = reply(b"*2\r\n*2\r\n:100\r\n:5\r\n*2\r\n:160\r\n:7\r\n");
+        assert_eq!(
+            parse_latency_history(&history),
+            Ok(vec![
+                LatencySample {
+                    time: at(100),
+                    latency: Duration::from_millis(5),
+                },
+                LatencySample {
+                    time: at(160),
+                    latency: Duration::from_millis(7),
+                },
+            ])
+        );
+        assert_eq!(
+            parse_latency_history(&reply(b"*1\r\n*2\r\n:100\r\n:-5\r\n"))
+                .unwrap_err()
+                .to_string(),
+            "expected milliseconds, got Integer"
+        );
+    }
+
+    #[test]
+    fn test_memory() {
+        let reply = |bytes: &[u8]| parse(bytes).unwrap().1.into_owned();
+        let wire = reply(
+            b"*8\r\n$14\r\npeak.allocated\r\n:1024\r\n$13\r\nfragmentation\r\n$4\r\n1.25\r\n\
+              $4\r\ndb.0\r\n*2\r\n$18\r\noverhead.hashtable\r\n:96\r\n\
+              $10\r\nkeys.count\r\n:3\r\n",
+        );
+        let stats = parse_memory_stats(&wire).unwrap();
+        assert_eq!(stats.peak_allocated, Some(1024));
+        assert_eq!(stats.fragmentation, Some(1.25));
+        assert_eq!(stats.keys_count, Some(3));
+        assert_eq!(stats.total_allocated, None);
+        assert_eq!(stats.unknown.len(), 1);
+        let (key, db) = stats.unknown[0];
+        assert_eq!(key, "db.0");
+        assert_eq!(
+            MapView::new(db).unwrap().get_int("overhead.hashtable"),
+            Some(96)
+        );
+
+        assert_eq!(
+            parse_memory_stats(&reply(b"*2\r\n$10\r\nkeys.count\r\n+x\r\n"))
+                .unwrap_err()
+                .to_string(),
+            "expected integer, got SimpleString"
+        );
+        assert_eq!(parse_memory_usage(&reply(b":56\r\n")), Ok(Some(56)));
+        assert_eq!(parse_memory_usage(&reply(b"$-1\r\n")), Ok(None));
+    }
+
+    #[test]
+    fn test_acl() {
+        let reply = |bytes: &[u8]| parse(bytes).unwrap().1.into_owned();
+        let getuser = reply(
+            b"%6\r\n+flags\r\n*2\r\n+on\r\n+nopass\r\n+passwords\r\n*0\r\n\
+              +commands\r\n$15\r\n+@all -flushall\r\n+keys\r\n$10\r\n~* %R~logs\r\n\
+              +channels\r\n$2\r\n&*\r\n+selectors\r\n*0\r\n",
+        );
+        assert_eq!(
+            parse_acl_getuser(&getuser),
+            Ok(Some(AclUser {
+                flags: vec!["on", "nopass"],
+                passwords: vec![],
+                key_patterns: vec!["~*", "%R~logs"],
+                channels: vec!["&*"],
+                commands: vec!["+@all", "-flushall"],
+            }))
+        );
+        // Older servers send patterns as arrays.
+        let old = reply(b"*2\r\n+keys\r\n*1\r\n$1\r\n*\r\n");
+        assert_eq!(
+            parse_acl_getuser(&old).unwrap().unwrap().key_patterns,
+            vec!["*"]
+        );
+        assert_eq!(parse_acl_getuser(&reply(b"*-1\r\n")), Ok(None));
+
+        let list = reply(b"*1\r\n$31\r\nuser default on nopass ~* +@all\r\n");
+        assert_eq!(
+            parse_acl_list(&list),
+            Ok(vec![AclListEntry {
+                user: "default",
+                rules: vec!["on", "nopass", "~*", "+@all"],
+            }])
+        );
+        assert_eq!(
+            parse_acl_list(&reply(b"*1\r\n+default\r\n"))
+                .unwrap_err()
+                .to_string(),
+            "expected user and rules, got SimpleString"
+        );
+    }
+
+    #[test]
+    fn test_map_view() {
+        let flat = parse(b"*6\r\n+port\r\n$4\r\n6379\r\n+ratio\r\n,0.5\r\n:1\r\n+one\r\n")
+            .unwrap()
+            .1;
+        let map = parse(b"%3\r\n+port\r\n:6379\r\n+ratio\r\n$3\r\n0.5\r\n:1\r\n+one\r\n")
+            .unwrap()
+            .1;
+        for reply in [&flat, &map].iter() {
+            let view = MapView::new(reply).unwrap();
+            assert_eq!(view.len(), 3);
+            assert_eq!(view.get_int("port"), Some(6379));
+            assert_eq!(view.get_f64("ratio"), Some(0.5));
+            assert_eq!(view.get_str("missing"), None);
+            // Only string keys are looked up.
+            assert_eq!(view.get("1"), None);
+            assert_eq!(view.iter().last().unwrap().1.query_str(""), Some("one"));
+        }
+        assert_eq!(MapView::new(&flat).unwrap().get_str("port"), Some("6379"));
+        assert_eq!(
+            MapView::new(&RESP::Integer(1)).unwrap_err().to_string(),
+            "expected map or array of key value pairs, got Integer"
+        );
+    }
+}
@@ -0,0 +1,57 @@
+//! A small seeded pseudo-random number generator, so that generated data is reproducible.
+
+/// An xorshift64* generator, which is plenty for generating test and benchmark data.
+#[derive(Debug, Clone)]
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Rng {
+        // Mix the seed with splitmix64, so nearby seeds start far apart. The state must never be
+        // zero, and splitmix64 is a bijection, so exactly one seed needs substituting.
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        Rng {
+            state: if z == 0 { 0x9E37_79B9_7F4A_7C15 } else { z },
+        }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Returns a number in `0..n`, or `0` when `n` is zero.
+    pub(crate) fn below(&mut self, n: u64) -> u64 {
+        if n == 0 {
+            return 0;
+        }
+        self.next_u64() % n
+    }
+
+    /// Returns a number in `low..=high`.
+    pub(crate) fn between(&mut self, low: usize, high: usize) -> usize {
+        debug_assert!(low <= high, "{} > {}", low, high);
+        low + self.below((high - low) as u64 + 1) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeds() {
+        let mut firsts: Vec<_> = (0..1000).map(|seed| Rng::new(seed).next_u64()).collect();
+        firsts.sort();
+        firsts.dedup();
+        assert_eq!(firsts.len(), 1000);
+
+        assert_eq!(Rng::new(0).between(5, 5), 5);
+    }
+}
@@ -0,0 +1,209 @@
+//! Comparison of values by what they mean rather than how they were encoded.
+use crate::RESP;
+use std::hash::{Hash, Hasher};
+
+/// A value with the differences between equivalent encodings erased.
+enum Shape<'r, 'a> {
+    String(&'r str),
+    Error(&'r str),
+    Null,
+    Integer(i64),
+    Boolean(bool),
+    Double(f64),
+    BigNumber(&'r str),
+    Elements(Vec<&'r RESP<'a>>),
+}
+
+impl<'a> RESP<'a> {
+    /// Compares two values, treating encodings which a server might use interchangeably as equal.
+    ///
+    /// Simple, bulk and verbatim strings are equal if their text is, as are both kinds of error,
+    /// and every kind of null. Arrays, sets and pushes are equal if their elements are, in
+    /// order, and a map is equal to the flat array of its keys and values that RESP2 replies
+    /// with. Attributes are ignored.
+    pub fn semantically_eq(&self, other: &RESP) -> bool {
+        match (self.shape(), other.shape()) {
+            (Shape::String(a), Shape::String(b)) => a == b,
+            (Shape::Error(a), Shape::Error(b)) => a == b,
+            (Shape::Null, Shape::Null) => true,
+            (Shape::Integer(a), Shape::Integer(b)) => a == b,
+            (Shape::Boolean(a), Shape::Boolean(b)) => a == b,
+            (Shape::Double(a), Shape::Double(b)) => a == b,
+            (Shape::BigNumber(a), Shape::BigNumber(b)) => a == b,
+            (Shape::Elements(a), Shape::Elements(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.semantically_eq(b))
+            }
+            _ => false,
+        }
+    }
+
+    /// Hashes the value such that semantically equal values hash the same.
+    pub fn semantic_hash<H: Hasher>(&self, state: &mut H) {
+        match self.shape() {
+            Shape::String(s) => (0u8, s).hash(state),
+            Shape::Error(s) => (1u8, s).hash(state),
+            Shape::Null => 2u8.hash(state),
+            Shape::Integer(i) => (3u8, i).hash(state),
+            Shape::Boolean(b) => (4u8, b).hash(state),
+            // Zero and negative zero are equal, so must hash the same.
+            Shape::Double(d) => (5u8, if d == 0.0 { 0 } else { d.to_bits() }).hash(state),
+            Shape::BigNumber(s) => (6u8, s).hash(state),
+            Shape::Elements(elements) => {
+                (7u8, elements.len()).hash(state);
+                for element in elements {
+                    element.semantic_hash(state);
+                }
+            }
+        }
+    }
+
+    /// Rewrites the value into a canonical form, so that equivalent values compare equal with
+    /// `==`.
+    ///
+    /// Every string becomes a bulk string, every error a blob error and every null `Null`, and
+    /// attributes are stripped. Aggregates keep their kind and order, including maps.
+    pub fn canonicalize(self) -> RESP<'a> {
+        let all = |elements: Vec<RESP<'a>>| elements.into_iter().map(RESP::canonicalize).collect();
+        match self {
+            RESP::SimpleString(s) | RESP::BulkString(s) => RESP::BulkString(s),
+            RESP::VerbatimString(_, s) => RESP::BulkString(s),
+            RESP::Error(s) | RESP::BlobError(s) => RESP::BlobError(s),
+            RESP::NullBulkString | RESP::NullArray | RESP::Null => RESP::Null,
+            RESP::Array(elements) => RESP::Array(all(elements)),
+            RESP::Set(elements) => RESP::Set(all(elements)),
+            RESP::Push(elements) => RESP::Push(all(elements)),
+            RESP::Map(pairs) => RESP::Map(
+                pairs
+                    .into_iter()
+                    .map(|(k, v)| (k.canonicalize(), v.canonicalize()))
+                    .collect(),
+            ),
+            RESP::Attribute(_, resp) => resp.canonicalize(),
+            // Negative zero is equal to zero, so is written as zero.
+            RESP::Double(0.0) => RESP::Double(0.0),
+            resp @ RESP::Integer(_)
+            | resp @ RESP::Boolean(_)
+            | resp @ RESP::Double(_)
+            | resp @ RESP::BigNumber(_) => resp,
+        }
+    }
+
+    fn shape(&self) -> Shape<'_, 'a> {
+        match self {
+            RESP::SimpleString(s) | RESP::BulkString(s) | RESP::VerbatimString(_, s) => {
+                Shape::String(s)
+            }
+            RESP::Error(s) | RESP::BlobError(s) => Shape::Error(s),
+            RESP::NullBulkString | RESP::NullArray | RESP::Null => Shape::Null,
+            RESP::Integer(i) => Shape::Integer(*i),
+            RESP::Boolean(b) => Shape::Boolean(*b),
+            RESP::Double(d) => Shape::Double(*d),
+            RESP::BigNumber(s) => Shape::BigNumber(s),
+            RESP::Array(elements) | RESP::Set(elements) | RESP::Push(elements) => {
+                Shape::Elements(elements.iter().collect())
+            }
+            RESP::Map(pairs) => Shape::Elements(pairs.iter().flat_map(|(k, v)| [k, v]).collect()),
+            RESP::Attribute(_, resp) => resp.shape(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow::Borrowed;
+    use std::collections::hash_map::DefaultHasher;
+
+    fn hash(resp: &RESP) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        resp.semantic_hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_semantically_eq() {
+        let ok = RESP::SimpleString(Borrowed("OK"));
+        let map = RESP::Map(vec![(
+            RESP::BulkString(Borrowed("field")),
+            RESP::Double(0.0),
+        )]);
+        let equal = vec![
+            (ok.clone(), RESP::BulkString(Borrowed("OK"))),
+            (
+                ok.clone(),
+                RESP::VerbatimString(Borrowed("txt"), Borrowed("OK")),
+            ),
+            (ok.clone(), RESP::Attribute(vec![], Box::new(ok.clone()))),
+            (
+                RESP::Error(Borrowed("ERR x")),
+                RESP::BlobError(Borrowed("ERR x")),
+            ),
+            (RESP::NullBulkString, RESP::Null),
+            (RESP::NullArray, RESP::Null),
+            (
+                RESP::Set(vec![ok.clone()]),
+                RESP::Array(vec![RESP::BulkString(Borrowed("OK"))]),
+            ),
+            (
+                map.clone(),
+                RESP::Array(vec![
+                    RESP::SimpleString(Borrowed("field")),
+                    RESP::Double(-0.0),
+                ]),
+            ),
+        ];
+        for (a, b) in equal {
+            assert!(a.semantically_eq(&b), "{:?} {:?}", a, b);
+            assert!(b.semantically_eq(&a), "{:?} {:?}", b, a);
+            assert_eq!(hash(&a), hash(&b), "{:?} {:?}", a, b);
+        }
+
+        let different = vec![
+            (ok.clone(), RESP::Error(Borrowed("OK"))),
+            (RESP::Integer(1), RESP::Boolean(true)),
+            (RESP::Integer(1), RESP::BulkString(Borrowed("1"))),
+            (RESP::Null, RESP::Array(vec![])),
+            (map, RESP::Array(vec![RESP::BulkString(Borrowed("field"))])),
+            (RESP::Double(f64::NAN), RESP::Double(f64::NAN)),
+        ];
+        for (a, b) in different {
+            assert!(!a.semantically_eq(&b), "{:?} {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let bulk = |s| RESP::BulkString(Borrowed(s));
+        let tests = vec![
+            (RESP::SimpleString(Borrowed("OK")), bulk("OK")),
+            (
+                RESP::VerbatimString(Borrowed("txt"), Borrowed("hi")),
+                bulk("hi"),
+            ),
+            (
+                RESP::Error(Borrowed("ERR x")),
+                RESP::BlobError(Borrowed("ERR x")),
+            ),
+            (RESP::NullBulkString, RESP::Null),
+            (RESP::Double(-0.0), RESP::Double(0.0)),
+            (
+                RESP::Attribute(
+                    vec![(bulk("ttl"), RESP::Integer(10))],
+                    Box::new(RESP::Set(vec![RESP::SimpleString(Borrowed("a"))])),
+                ),
+                RESP::Set(vec![bulk("a")]),
+            ),
+            (
+                RESP::Map(vec![
+                    (RESP::SimpleString(Borrowed("b")), RESP::NullArray),
+                    (bulk("a"), RESP::Integer(1)),
+                ]),
+                RESP::Map(vec![(bulk("b"), RESP::Null), (bulk("a"), RESP::Integer(1))]),
+            ),
+        ];
+        for (resp, expected) in tests {
+            assert_eq!(resp.clone().canonicalize(), expected, "{:?}", resp);
+            assert!(resp.semantically_eq(&expected));
+        }
+    }
+}
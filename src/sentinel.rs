@@ -0,0 +1,215 @@
+//! Decoding of Redis Sentinel replies and events.
+use crate::{ParseError, RESPOwned, RESP};
+use std::borrow::Cow::Borrowed;
+use std::collections::HashMap;
+
+const SWITCH_MASTER_CHANNEL: &str = "+switch-master";
+
+#[derive(Debug, PartialEq)]
+pub enum SentinelError {
+    Parse(ParseError),
+    /// The reply doesn't have the shape of the expected sentinel reply.
+    UnexpectedReply(RESPOwned),
+}
+
+impl From<ParseError> for SentinelError {
+    fn from(err: ParseError) -> SentinelError {
+        SentinelError::Parse(err)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MasterAddr {
+    pub ip: String,
+    pub port: u16,
+}
+
+impl MasterAddr {
+    fn new(ip: &str, port: &str) -> Result<MasterAddr, SentinelError> {
+        Ok(MasterAddr {
+            ip: ip.to_string(),
+            port: port.parse().map_err(ParseError::ParseIntError)?,
+        })
+    }
+}
+
+/// A master monitored by a sentinel, as listed by `SENTINEL masters`.
+#[derive(Debug, PartialEq)]
+pub struct Master {
+    pub name: String,
+    pub addr: MasterAddr,
+    pub flags: Vec<String>,
+    pub quorum: u64,
+    /// Every field of the reply, including those decoded above.
+    pub fields: HashMap<String, String>,
+}
+
+/// The `+switch-master` event, published when a failover promotes a new master.
+#[derive(Debug, PartialEq)]
+pub struct SwitchMaster {
+    pub name: String,
+    pub old: MasterAddr,
+    pub new: MasterAddr,
+}
+
+impl SwitchMaster {
+    /// Decodes the event's `<name> <old-ip> <old-port> <new-ip> <new-port>` payload.
+    pub fn from_payload(payload: &str) -> Result<SwitchMaster, SentinelError> {
+        match payload.split(' ').collect::<Vec<_>>().as_slice() {
+            [name, old_ip, old_port, new_ip, new_port] => Ok(SwitchMaster {
+                name: name.to_string(),
+                old: MasterAddr::new(old_ip, old_port)?,
+                new: MasterAddr::new(new_ip, new_port)?,
+            }),
+            _ => Err(unexpected(&RESP::BulkString(Borrowed(payload)))),
+        }
+    }
+}
+
+/// Decodes the reply to `SENTINEL get-master-addr-by-name`, which is null for unknown masters.
+pub fn parse_master_addr(reply: &RESP) -> Result<Option<MasterAddr>, SentinelError> {
+    match reply {
+        RESP::NullArray => Ok(None),
+        RESP::Array(arr) => match arr.as_slice() {
+            [ip, port] => Ok(Some(MasterAddr::new(string(ip)?, string(port)?)?)),
+            _ => Err(unexpected(reply)),
+        },
+        _ => Err(unexpected(reply)),
+    }
+}
+
+/// Decodes the reply to `SENTINEL masters`.
+pub fn parse_masters(reply: &RESP) -> Result<Vec<Master>, SentinelError> {
+    match reply {
+        RESP::Array(arr) => arr.iter().map(parse_master).collect(),
+        _ => Err(unexpected(reply)),
+    }
+}
+
+/// Decodes a single entry of `SENTINEL masters`, which is also the reply to `SENTINEL master`.
+pub fn parse_master(reply: &RESP) -> Result<Master, SentinelError> {
+    let arr = match reply {
+        RESP::Array(arr) if arr.len() % 2 == 0 => arr,
+        _ => return Err(unexpected(reply)),
+    };
+    let mut fields = HashMap::with_capacity(arr.len() / 2);
+    for pair in arr.chunks(2) {
+        fields.insert(string(&pair[0])?.to_string(), string(&pair[1])?.to_string());
+    }
+    let field = |name: &str| -> Result<&str, SentinelError> {
+        fields
+            .get(name)
+            .map(String::as_str)
+            .ok_or_else(|| unexpected(reply))
+    };
+    Ok(Master {
+        name: field("name")?.to_string(),
+        addr: MasterAddr::new(field("ip")?, field("port")?)?,
+        flags: field("flags")?.split(',').map(str::to_string).collect(),
+        quorum: field("quorum")?
+            .parse()
+            .map_err(ParseError::ParseIntError)?,
+        fields,
+    })
+}
+
+/// Decodes a pub/sub message, returning the `+switch-master` event it carries if any.
+///
+/// Both `message` (from `SUBSCRIBE`) and `pmessage` (from `PSUBSCRIBE`) pushes are accepted.
+pub fn parse_switch_master(message: &RESP) -> Result<Option<SwitchMaster>, SentinelError> {
+    let arr = match message {
+        RESP::Array(arr) => arr,
+        _ => return Err(unexpected(message)),
+    };
+    let (channel, payload) = match arr.as_slice() {
+        [kind, channel, payload] if string(kind)? == "message" => (channel, payload),
+        [kind, _, channel, payload] if string(kind)? == "pmessage" => (channel, payload),
+        _ => return Err(unexpected(message)),
+    };
+    if string(channel)? != SWITCH_MASTER_CHANNEL {
+        return Ok(None);
+    }
+    SwitchMaster::from_payload(string(payload)?).map(Some)
+}
+
+fn string<'a>(resp: &'a RESP) -> Result<&'a str, SentinelError> {
+    match resp {
+        RESP::SimpleString(s) | RESP::BulkString(s) => Ok(s),
+        _ => Err(unexpected(resp)),
+    }
+}
+
+fn unexpected(resp: &RESP) -> SentinelError {
+    SentinelError::UnexpectedReply(resp.clone().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn test_parse_master_addr() {
+        let test_cases: Vec<(&[u8], Option<MasterAddr>)> = vec![
+            (
+                b"*2\r\n$9\r\n127.0.0.1\r\n$4\r\n6379\r\n",
+                Some(MasterAddr {
+                    ip: "127.0.0.1".to_string(),
+                    port: 6379,
+                }),
+            ),
+            (b"*-1\r\n", None),
+        ];
+        for (bytes, expected) in test_cases {
+            let (_, reply) = parse(bytes).unwrap();
+            assert_eq!(parse_master_addr(&reply), Ok(expected));
+        }
+    }
+
+    #[test]
+    fn test_parse_masters() {
+        let reply = RESP::Array(vec![RESP::command(vec![
+            "name",
+            "mymaster",
+            "ip",
+            "10.0.0.1",
+            "port",
+            "6379",
+            "flags",
+            "master,disconnected",
+            "quorum",
+            "2",
+        ])]);
+        let masters = parse_masters(&reply).unwrap();
+        assert_eq!(masters.len(), 1);
+        assert_eq!(masters[0].name, "mymaster");
+        assert_eq!(masters[0].addr.port, 6379);
+        assert_eq!(masters[0].flags, vec!["master", "disconnected"]);
+        assert_eq!(masters[0].quorum, 2);
+    }
+
+    #[test]
+    fn test_parse_switch_master() {
+        let message = RESP::command(vec![
+            "message",
+            "+switch-master",
+            "mymaster 10.0.0.1 6379 10.0.0.2 6380",
+        ]);
+        assert_eq!(
+            parse_switch_master(&message),
+            Ok(Some(SwitchMaster {
+                name: "mymaster".to_string(),
+                old: MasterAddr {
+                    ip: "10.0.0.1".to_string(),
+                    port: 6379,
+                },
+                new: MasterAddr {
+                    ip: "10.0.0.2".to_string(),
+                    port: 6380,
+                },
+            }))
+        );
+        let message = RESP::command(vec!["message", "+sdown", "master mymaster 10.0.0.1 6379"]);
+        assert_eq!(parse_switch_master(&message), Ok(None));
+    }
+}
@@ -0,0 +1,158 @@
+//! A small framework for writing RESP servers.
+//!
+//! Each connection decodes requests and passes them to a `Handler`, writing back whatever it
+//! returns. Requests which can't be decoded get an `ERR Protocol error` reply, after which the
+//! connection is closed since there's no telling where the next request starts.
+use crate::connection::Connection;
+use crate::error_code::ErrorCode;
+use crate::{Error, ParseError, RESPOwned, RESP};
+use std::io::{self, Read, Write};
+use std::net::TcpListener;
+use std::thread;
+
+/// A command sent by a client, which is an array of one or more bulk strings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    args: Vec<String>,
+}
+
+impl Request {
+    /// Converts a decoded object into a request, returning the error to reply with if it isn't
+    /// one.
+    pub fn from_resp(resp: RESPOwned) -> Result<Request, RESPOwned> {
+        let arr = match resp {
+            RESP::Array(arr) if !arr.is_empty() => arr,
+            _ => return Err(protocol_error("expected a non-empty array")),
+        };
+        let args = arr
+            .into_iter()
+            .map(|arg| match arg {
+                RESP::BulkString(s) => Ok(s.into_owned()),
+                _ => Err(protocol_error("expected bulk strings")),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Request { args })
+    }
+
+    /// Returns the command's name, such as `GET`.
+    pub fn name(&self) -> &str {
+        &self.args[0]
+    }
+
+    /// Returns the command's arguments, not including its name.
+    pub fn args(&self) -> &[String] {
+        &self.args[1..]
+    }
+}
+
+pub trait Handler {
+    /// Returns the reply to a request.
+    fn handle(&mut self, request: Request) -> RESPOwned;
+}
+
+impl<F: FnMut(Request) -> RESPOwned> Handler for F {
+    fn handle(&mut self, request: Request) -> RESPOwned {
+        self(request)
+    }
+}
+
+/// Accepts connections forever, serving each on its own thread with a handler from
+/// `new_handler`.
+pub fn serve<F, H>(listener: TcpListener, new_handler: F) -> io::Result<()>
+where
+    F: Fn() -> H,
+    H: Handler + Send + 'static,
+{
+    loop {
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        let mut handler = new_handler();
+        thread::spawn(move || serve_connection(stream, &mut handler));
+    }
+}
+
+/// Serves requests from a single stream until the client disconnects.
+pub fn serve_connection<S, H>(stream: S, handler: &mut H) -> Result<(), Error>
+where
+    S: Read + Write,
+    H: Handler,
+{
+    let mut conn = Connection::new(stream);
+    loop {
+        let (reply, close) = match conn.recv() {
+            Ok(resp) => match Request::from_resp(resp) {
+                Ok(request) => (handler.handle(request), false),
+                Err(reply) => (reply, true),
+            },
+            Err(Error::Parse(err)) => (protocol_error(parse_error_message(&err)), true),
+            Err(Error::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        conn.send(&reply)?;
+        if close {
+            return Ok(());
+        }
+    }
+}
+
+fn protocol_error(message: &str) -> RESPOwned {
+    RESP::err_with_code(ErrorCode::Err, &format!("Protocol error: {}", message))
+}
+
+fn parse_error_message(err: &ParseError) -> &'static str {
+    match err {
+        ParseError::UnknownByte(_) => "unknown type byte",
+        ParseError::Utf8Error(_) => "invalid UTF-8",
+        ParseError::ParseIntError(_) => "invalid integer",
+        ParseError::ParseFloatError(_) => "invalid double",
+        ParseError::InvalidValue(_) => "invalid value",
+        ParseError::LengthOverflow => "invalid length",
+        ParseError::CLRFNotFound | ParseError::Incomplete => "incomplete request",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow::{Borrowed, Owned};
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_serve() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            serve(listener, || {
+                |request: Request| match request.name() {
+                    "PING" => RESP::SimpleString(Borrowed("PONG")),
+                    "ECHO" => RESP::BulkString(Owned(request.args()[0].clone())),
+                    _ => RESP::Error(Borrowed("ERR unknown command")),
+                }
+            })
+        });
+
+        let mut conn = Connection::connect(addr).unwrap();
+        let test_cases = vec![
+            (
+                RESP::command(vec!["PING"]),
+                RESP::SimpleString(Borrowed("PONG")),
+            ),
+            (
+                RESP::command(vec!["ECHO", "hi"]),
+                RESP::BulkString(Borrowed("hi")),
+            ),
+            (
+                RESP::command(vec!["NOPE"]),
+                RESP::Error(Borrowed("ERR unknown command")),
+            ),
+            (
+                RESP::Integer(1),
+                RESP::Error(Borrowed("ERR Protocol error: expected a non-empty array")),
+            ),
+        ];
+        for (request, reply) in test_cases {
+            assert_eq!(conn.call(&request).unwrap(), reply);
+        }
+        assert!(conn.recv().is_err());
+    }
+}
@@ -0,0 +1,121 @@
+//! An owned `RESP` object which is cheap to clone, for fanning one reply out to many consumers.
+use crate::RESP;
+use std::borrow::Cow::Borrowed;
+use std::sync::Arc;
+
+/// Mirrors `RESP`, but with every string and aggregate behind an `Arc`, so cloning any part of
+/// it never copies its contents.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RESPShared {
+    SimpleString(Arc<str>),
+    Error(Arc<str>),
+    Integer(i64),
+    BulkString(Arc<str>),
+    NullBulkString,
+    Array(Arc<[RESPShared]>),
+    NullArray,
+    Null,
+    Boolean(bool),
+    Double(f64),
+    BigNumber(Arc<str>),
+    BlobError(Arc<str>),
+    VerbatimString(Arc<str>, Arc<str>),
+    Map(Arc<[(RESPShared, RESPShared)]>),
+    Set(Arc<[RESPShared]>),
+    Attribute(Arc<[(RESPShared, RESPShared)]>, Arc<RESPShared>),
+    Push(Arc<[RESPShared]>),
+}
+
+impl RESPShared {
+    /// Returns a `RESP` object borrowing this one's strings, such as to `dump` it.
+    pub fn as_resp(&self) -> RESP<'_> {
+        fn all(elements: &[RESPShared]) -> Vec<RESP<'_>> {
+            elements.iter().map(RESPShared::as_resp).collect()
+        }
+        fn pairs(pairs: &[(RESPShared, RESPShared)]) -> Vec<(RESP<'_>, RESP<'_>)> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.as_resp(), v.as_resp()))
+                .collect()
+        }
+        match self {
+            RESPShared::SimpleString(s) => RESP::SimpleString(Borrowed(s)),
+            RESPShared::Error(s) => RESP::Error(Borrowed(s)),
+            RESPShared::Integer(i) => RESP::Integer(*i),
+            RESPShared::BulkString(s) => RESP::BulkString(Borrowed(s)),
+            RESPShared::NullBulkString => RESP::NullBulkString,
+            RESPShared::Array(arr) => RESP::Array(all(arr)),
+            RESPShared::NullArray => RESP::NullArray,
+            RESPShared::Null => RESP::Null,
+            RESPShared::Boolean(b) => RESP::Boolean(*b),
+            RESPShared::Double(d) => RESP::Double(*d),
+            RESPShared::BigNumber(s) => RESP::BigNumber(Borrowed(s)),
+            RESPShared::BlobError(s) => RESP::BlobError(Borrowed(s)),
+            RESPShared::VerbatimString(format, s) => {
+                RESP::VerbatimString(Borrowed(format), Borrowed(s))
+            }
+            RESPShared::Map(map) => RESP::Map(pairs(map)),
+            RESPShared::Set(set) => RESP::Set(all(set)),
+            RESPShared::Attribute(attrs, resp) => {
+                RESP::Attribute(pairs(attrs), Box::new(resp.as_resp()))
+            }
+            RESPShared::Push(arr) => RESP::Push(all(arr)),
+        }
+    }
+}
+
+impl From<&RESP<'_>> for RESPShared {
+    fn from(resp: &RESP) -> RESPShared {
+        let all = |elements: &[RESP]| elements.iter().map(RESPShared::from).collect();
+        let pairs = |pairs: &[(RESP, RESP)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (RESPShared::from(k), RESPShared::from(v)))
+                .collect()
+        };
+        match resp {
+            RESP::SimpleString(s) => RESPShared::SimpleString(Arc::from(&**s)),
+            RESP::Error(s) => RESPShared::Error(Arc::from(&**s)),
+            RESP::Integer(i) => RESPShared::Integer(*i),
+            RESP::BulkString(s) => RESPShared::BulkString(Arc::from(&**s)),
+            RESP::NullBulkString => RESPShared::NullBulkString,
+            RESP::Array(arr) => RESPShared::Array(all(arr)),
+            RESP::NullArray => RESPShared::NullArray,
+            RESP::Null => RESPShared::Null,
+            RESP::Boolean(b) => RESPShared::Boolean(*b),
+            RESP::Double(d) => RESPShared::Double(*d),
+            RESP::BigNumber(s) => RESPShared::BigNumber(Arc::from(&**s)),
+            RESP::BlobError(s) => RESPShared::BlobError(Arc::from(&**s)),
+            RESP::VerbatimString(format, s) => {
+                RESPShared::VerbatimString(Arc::from(&**format), Arc::from(&**s))
+            }
+            RESP::Map(map) => RESPShared::Map(pairs(map)),
+            RESP::Set(set) => RESPShared::Set(all(set)),
+            RESP::Attribute(attrs, resp) => {
+                RESPShared::Attribute(pairs(attrs), Arc::new(RESPShared::from(&**resp)))
+            }
+            RESP::Push(arr) => RESPShared::Push(all(arr)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vectors::vectors;
+
+    #[test]
+    fn test_shared() {
+        for vector in vectors() {
+            let shared = RESPShared::from(&vector.expected);
+            assert_eq!(shared.as_resp(), vector.expected, "{}", vector.name);
+        }
+
+        let shared = RESPShared::from(&RESP::command(vec!["PUBLISH", "news", "hello"]));
+        let clone = shared.clone();
+        match (&shared, &clone) {
+            (RESPShared::Array(a), RESPShared::Array(b)) => assert!(Arc::ptr_eq(a, b)),
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! A readable text format for `RESP` trees, so that expected replies can be kept in snapshot
+//! files rather than byte string literals.
+//!
+//! Each value takes up one line, naming its type followed by its contents. Strings are quoted
+//! and escaped as `cli::escape` does, and aggregates give their length, followed by their
+//! elements on the lines below indented by two more spaces. A map's keys and values alternate,
+//! and an attribute's pairs are followed by the value it describes:
+//!
+//! ```text
+//! # The reply to HGETALL.
+//! attribute 1
+//!   simple "ttl"
+//!   integer 3600
+//!   map 1
+//!     bulk "field"
+//!     bulk "has\r\nCRLF"
+//! ```
+//!
+//! Blank lines and lines starting with `#` are ignored, and a file may hold any number of values.
+use crate::cli::{escape, unescape};
+use crate::{format_double, RESPOwned, RESP};
+use std::borrow::Cow::Owned;
+use std::fmt;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnapshotError {
+    /// The line, counting from 1, isn't a value.
+    InvalidLine(usize),
+    /// The line isn't indented two spaces deeper than the aggregate it's in.
+    BadIndent(usize),
+    /// The text ends before an aggregate's elements do.
+    Incomplete,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::InvalidLine(line) => write!(f, "invalid value on line {}", line),
+            SnapshotError::BadIndent(line) => write!(f, "bad indentation on line {}", line),
+            SnapshotError::Incomplete => write!(f, "snapshot ends inside an aggregate"),
+        }
+    }
+}
+
+/// Writes a value as snapshot text, ending with a newline.
+pub fn serialize(resp: &RESP) -> String {
+    let mut out = String::new();
+    write_value(resp, 0, &mut out);
+    out
+}
+
+fn write_value(resp: &RESP, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+    let elements = |name: &str, elements: &[RESP], out: &mut String| {
+        writeln!(out, "{}{} {}", indent, name, elements.len()).unwrap();
+        for element in elements {
+            write_value(element, depth + 1, out);
+        }
+    };
+    let pairs = |name: &str, pairs: &[(RESP, RESP)], out: &mut String| {
+        writeln!(out, "{}{} {}", indent, name, pairs.len()).unwrap();
+        for (k, v) in pairs {
+            write_value(k, depth + 1, out);
+            write_value(v, depth + 1, out);
+        }
+    };
+    let line = match resp {
+        RESP::SimpleString(s) => format!("simple {}", escape(s)),
+        RESP::Error(s) => format!("error {}", escape(s)),
+        RESP::Integer(i) => format!("integer {}", i),
+        RESP::BulkString(s) => format!("bulk {}", escape(s)),
+        RESP::NullBulkString => "null-bulk".to_string(),
+        RESP::NullArray => "null-array".to_string(),
+        RESP::Null => "null".to_string(),
+        RESP::Boolean(b) => format!("boolean {}", b),
+        RESP::Double(d) => format!("double {}", format_double(*d)),
+        RESP::BigNumber(s) => format!("big-number {}", s),
+        RESP::BlobError(s) => format!("blob-error {}", escape(s)),
+        RESP::VerbatimString(format, s) => format!("verbatim {} {}", escape(format), escape(s)),
+        RESP::Array(arr) => return elements("array", arr, out),
+        RESP::Set(set) => return elements("set", set, out),
+        RESP::Push(arr) => return elements("push", arr, out),
+        RESP::Map(map) => return pairs("map", map, out),
+        RESP::Attribute(attrs, resp) => {
+            pairs("attribute", attrs, out);
+            return write_value(resp, depth + 1, out);
+        }
+    };
+    writeln!(out, "{}{}", indent, line).unwrap();
+}
+
+/// Reads every value in snapshot text.
+pub fn parse(text: &str) -> Result<Vec<RESPOwned>, SnapshotError> {
+    let mut lines = Lines {
+        lines: text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+            .map(|(i, line)| (i + 1, line))
+            .collect(),
+        next: 0,
+    };
+    let mut values = Vec::new();
+    while lines.next < lines.lines.len() {
+        values.push(lines.value(0)?);
+    }
+    Ok(values)
+}
+
+/// The lines of a snapshot which hold values, along with their line numbers.
+struct Lines<'t> {
+    lines: Vec<(usize, &'t str)>,
+    next: usize,
+}
+
+impl<'t> Lines<'t> {
+    /// Reads the value on the next line, which must be indented `depth` levels.
+    fn value(&mut self, depth: usize) -> Result<RESPOwned, SnapshotError> {
+        let &(number, line) = self.lines.get(self.next).ok_or(SnapshotError::Incomplete)?;
+        self.next += 1;
+        let indent = "  ".repeat(depth);
+        let line = match line.strip_prefix(&indent) {
+            Some(line) if !line.starts_with(' ') => line,
+            _ => return Err(SnapshotError::BadIndent(number)),
+        };
+        let invalid = SnapshotError::InvalidLine(number);
+        let (name, rest) = match line.find(' ') {
+            Some(i) => (&line[..i], &line[i + 1..]),
+            None => (line, ""),
+        };
+        let string = |s: &str| -> Result<String, SnapshotError> {
+            String::from_utf8(unescape(s).ok_or_else(|| invalid.clone())?)
+                .map_err(|_| invalid.clone())
+        };
+        let len = || rest.parse::<usize>().map_err(|_| invalid.clone());
+        let resp = match (name, rest) {
+            ("simple", s) => RESP::SimpleString(Owned(string(s)?)),
+            ("error", s) => RESP::Error(Owned(string(s)?)),
+            ("integer", i) => RESP::Integer(i.parse().map_err(|_| invalid.clone())?),
+            ("bulk", s) => RESP::BulkString(Owned(string(s)?)),
+            ("null-bulk", "") => RESP::NullBulkString,
+            ("null-array", "") => RESP::NullArray,
+            ("null", "") => RESP::Null,
+            ("boolean", "true") => RESP::Boolean(true),
+            ("boolean", "false") => RESP::Boolean(false),
+            ("double", d) => RESP::Double(d.parse().map_err(|_| invalid.clone())?),
+            ("big-number", s) if is_big_number(s) => RESP::BigNumber(Owned(s.to_string())),
+            ("blob-error", s) => RESP::BlobError(Owned(string(s)?)),
+            ("verbatim", rest) => {
+                let (format, s) = split_quoted(rest).ok_or_else(|| invalid.clone())?;
+                RESP::VerbatimString(Owned(string(format)?), Owned(string(s)?))
+            }
+            ("array", _) => RESP::Array(self.elements(len()?, depth)?),
+            ("set", _) => RESP::Set(self.elements(len()?, depth)?),
+            ("push", _) => RESP::Push(self.elements(len()?, depth)?),
+            ("map", _) => RESP::Map(self.pairs(len()?, depth)?),
+            ("attribute", _) => {
+                let attrs = self.pairs(len()?, depth)?;
+                RESP::Attribute(attrs, Box::new(self.value(depth + 1)?))
+            }
+            _ => return Err(invalid),
+        };
+        Ok(resp)
+    }
+
+    fn elements(&mut self, len: usize, depth: usize) -> Result<Vec<RESPOwned>, SnapshotError> {
+        (0..len).map(|_| self.value(depth + 1)).collect()
+    }
+
+    fn pairs(
+        &mut self,
+        len: usize,
+        depth: usize,
+    ) -> Result<Vec<(RESPOwned, RESPOwned)>, SnapshotError> {
+        (0..len)
+            .map(|_| Ok((self.value(depth + 1)?, self.value(depth + 1)?)))
+            .collect()
+    }
+}
+
+fn is_big_number(s: &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Splits a quoted string from the start of `s`, returning it and the rest after a space.
+fn split_quoted(s: &str) -> Option<(&str, &str)> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some((&s[..=i], s[i + 1..].strip_prefix(' ')?)),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::{gen, Profile};
+    use crate::vectors::vectors;
+    use std::borrow::Cow::Borrowed;
+
+    #[test]
+    fn test_snapshot() {
+        for vector in vectors() {
+            let text = serialize(&vector.expected);
+            assert_eq!(parse(&text), Ok(vec![vector.expected]), "{}", vector.name);
+        }
+        for seed in 0..50 {
+            let (_, resp) = gen(seed, Profile::Mixed);
+            assert_eq!(parse(&serialize(&resp)), Ok(vec![resp]));
+        }
+
+        let resp = RESP::Attribute(
+            vec![(RESP::SimpleString(Borrowed("ttl")), RESP::Integer(3600))],
+            Box::new(RESP::Map(vec![(
+                RESP::BulkString(Borrowed("field")),
+                RESP::VerbatimString(Borrowed("txt"), Borrowed("say \"hi\"\r\n")),
+            )])),
+        );
+        let text = "attribute 1\n  simple \"ttl\"\n  integer 3600\n  map 1\n    bulk \"field\"\n    verbatim \"txt\" \"say \\\"hi\\\"\\r\\n\"\n";
+        assert_eq!(serialize(&resp), text);
+        let commented = format!("# A reply.\n\n{}null\n", text);
+        assert_eq!(parse(&commented), Ok(vec![resp, RESP::Null]));
+
+        let tests = vec![
+            ("array 2\n  integer 1\n", Err(SnapshotError::Incomplete)),
+            ("array 1\ninteger 1\n", Err(SnapshotError::BadIndent(2))),
+            ("set 1\n   null\n", Err(SnapshotError::BadIndent(2))),
+            ("\nbulk unquoted\n", Err(SnapshotError::InvalidLine(2))),
+            ("boolean yes\n", Err(SnapshotError::InvalidLine(1))),
+            ("big-number 12a\n", Err(SnapshotError::InvalidLine(1))),
+            ("bulk \"\\xff\"\n", Err(SnapshotError::InvalidLine(1))),
+            ("double -inf\n", Ok(vec![RESP::Double(f64::NEG_INFINITY)])),
+            ("", Ok(vec![])),
+        ];
+        for (text, expected) in tests {
+            assert_eq!(parse(text), expected, "{:?}", text);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Parsing into the shape of a `RESP` object, but with byte ranges into the buffer in place of
+//! strings.
+//!
+//! Spans let tools point back into the original buffer, measure elements or splice frames
+//! without copying. Since strings aren't decoded, they aren't checked to be UTF-8.
+use crate::lexer::{Lexer, Token};
+use crate::ParseError;
+use std::ops::Range;
+
+/// A value along with the range of the buffer its whole frame takes up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub range: Range<usize>,
+    pub value: SpanValue,
+}
+
+/// Mirrors `RESP`, with the range of each string's contents in place of the string.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpanValue {
+    SimpleString(Range<usize>),
+    Error(Range<usize>),
+    Integer(i64),
+    BulkString(Range<usize>),
+    NullBulkString,
+    Array(Vec<Span>),
+    NullArray,
+    Null,
+    Boolean(bool),
+    Double(f64),
+    BigNumber(Range<usize>),
+    BlobError(Range<usize>),
+    /// The ranges of the format and the text.
+    VerbatimString(Range<usize>, Range<usize>),
+    Map(Vec<(Span, Span)>),
+    Set(Vec<Span>),
+    Attribute(Vec<(Span, Span)>, Box<Span>),
+    Push(Vec<Span>),
+}
+
+impl Span {
+    /// Returns the bytes of the frame.
+    pub fn bytes<'b>(&self, buf: &'b [u8]) -> &'b [u8] {
+        &buf[self.range.clone()]
+    }
+}
+
+/// Parses one frame from the start of `buf` into spans, returning the number of bytes read.
+pub fn parse_spans(buf: &[u8]) -> Result<(usize, Span), ParseError> {
+    let mut lexer = Lexer::new(buf);
+    let span = span(buf, &mut lexer)?;
+    Ok((lexer.offset(), span))
+}
+
+fn span(buf: &[u8], lexer: &mut Lexer) -> Result<Span, ParseError> {
+    let start = lexer.offset();
+    let token = lexer.next_token()?.ok_or(ParseError::Incomplete)?;
+    let range = |part: &[u8]| {
+        let start = part.as_ptr() as usize - buf.as_ptr() as usize;
+        start..start + part.len()
+    };
+    let value = match token {
+        Token::Simple(s) => SpanValue::SimpleString(range(s)),
+        Token::Error(s) => SpanValue::Error(range(s)),
+        Token::Int(i) => SpanValue::Integer(i),
+        Token::Bulk(s) => SpanValue::BulkString(range(s)),
+        Token::NullBulk => SpanValue::NullBulkString,
+        Token::ArrayStart(n) => SpanValue::Array(elements(buf, lexer, n)?),
+        Token::NullArray => SpanValue::NullArray,
+        Token::Null => SpanValue::Null,
+        Token::Boolean(b) => SpanValue::Boolean(b),
+        Token::Double(d) => SpanValue::Double(d),
+        Token::BigNumber(s) => SpanValue::BigNumber(range(s)),
+        Token::BlobError(s) => SpanValue::BlobError(range(s)),
+        Token::Verbatim(format, s) => SpanValue::VerbatimString(range(format), range(s)),
+        Token::MapStart(n) => SpanValue::Map(pairs(buf, lexer, n)?),
+        Token::SetStart(n) => SpanValue::Set(elements(buf, lexer, n)?),
+        Token::AttributeStart(n) => {
+            let attrs = pairs(buf, lexer, n)?;
+            SpanValue::Attribute(attrs, Box::new(span(buf, lexer)?))
+        }
+        Token::PushStart(n) => SpanValue::Push(elements(buf, lexer, n)?),
+    };
+    Ok(Span {
+        range: start..lexer.offset(),
+        value,
+    })
+}
+
+fn elements(buf: &[u8], lexer: &mut Lexer, count: usize) -> Result<Vec<Span>, ParseError> {
+    (0..count).map(|_| span(buf, lexer)).collect()
+}
+
+fn pairs(buf: &[u8], lexer: &mut Lexer, count: usize) -> Result<Vec<(Span, Span)>, ParseError> {
+    (0..count)
+        .map(|_| Ok((span(buf, lexer)?, span(buf, lexer)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_spans() {
+        let buf = b"*2\r\n$3\r\nfoo\r\n=7\r\ntxt:abc\r\n+extra\r\n";
+        let (n, span) = parse_spans(buf).unwrap();
+        assert_eq!(n, 26);
+        assert_eq!(
+            span,
+            Span {
+                range: 0..26,
+                value: SpanValue::Array(vec![
+                    Span {
+                        range: 4..13,
+                        value: SpanValue::BulkString(8..11),
+                    },
+                    Span {
+                        range: 13..26,
+                        value: SpanValue::VerbatimString(17..20, 21..24),
+                    },
+                ]),
+            }
+        );
+        if let SpanValue::Array(elements) = &span.value {
+            assert_eq!(elements[0].bytes(buf), b"$3\r\nfoo\r\n");
+        }
+
+        let (_, span) = parse_spans(b"|1\r\n+a\r\n:1\r\n_\r\n").unwrap();
+        assert!(matches!(span.value, SpanValue::Attribute(ref attrs, ref v)
+            if attrs[0].0.value == SpanValue::SimpleString(5..6) && v.range == (12..15)));
+
+        assert_eq!(parse_spans(b"*2\r\n:1\r\n"), Err(ParseError::Incomplete));
+    }
+}
@@ -0,0 +1,246 @@
+//! Pub/sub subscriptions over a blocking connection.
+use crate::connection::Connection;
+use crate::{Error, RESPOwned, RESP};
+use std::borrow::Cow::Borrowed;
+use std::collections::BTreeSet;
+use std::io::{Read, Write};
+
+/// A message published to a subscribed channel.
+#[derive(Debug, PartialEq)]
+pub enum Message {
+    /// A message to a channel subscribed with `SUBSCRIBE`.
+    Message { channel: String, payload: String },
+    /// A message to a channel matching a pattern subscribed with `PSUBSCRIBE`.
+    PMessage {
+        pattern: String,
+        channel: String,
+        payload: String,
+    },
+}
+
+/// Subscribes to channels and patterns, keeping track of which subscriptions the server has
+/// confirmed.
+///
+/// Subscriptions are remembered as they're requested, so that `reconnect` can restore them.
+pub struct Subscriber<S> {
+    conn: Connection<S>,
+    wanted_channels: BTreeSet<String>,
+    wanted_patterns: BTreeSet<String>,
+    channels: BTreeSet<String>,
+    patterns: BTreeSet<String>,
+}
+
+impl<S: Read + Write> Subscriber<S> {
+    pub fn new(conn: Connection<S>) -> Subscriber<S> {
+        Subscriber {
+            conn,
+            wanted_channels: BTreeSet::new(),
+            wanted_patterns: BTreeSet::new(),
+            channels: BTreeSet::new(),
+            patterns: BTreeSet::new(),
+        }
+    }
+
+    pub fn subscribe(&mut self, channels: &[&str]) -> Result<(), Error> {
+        self.wanted_channels
+            .extend(channels.iter().map(|c| c.to_string()));
+        self.send("SUBSCRIBE", channels)
+    }
+
+    pub fn psubscribe(&mut self, patterns: &[&str]) -> Result<(), Error> {
+        self.wanted_patterns
+            .extend(patterns.iter().map(|p| p.to_string()));
+        self.send("PSUBSCRIBE", patterns)
+    }
+
+    /// Unsubscribes from `channels`, or from every channel if it's empty.
+    pub fn unsubscribe(&mut self, channels: &[&str]) -> Result<(), Error> {
+        if channels.is_empty() {
+            self.wanted_channels.clear();
+        }
+        for channel in channels {
+            self.wanted_channels.remove(*channel);
+        }
+        self.send("UNSUBSCRIBE", channels)
+    }
+
+    /// Unsubscribes from `patterns`, or from every pattern if it's empty.
+    pub fn punsubscribe(&mut self, patterns: &[&str]) -> Result<(), Error> {
+        if patterns.is_empty() {
+            self.wanted_patterns.clear();
+        }
+        for pattern in patterns {
+            self.wanted_patterns.remove(*pattern);
+        }
+        self.send("PUNSUBSCRIBE", patterns)
+    }
+
+    /// Returns the channels the server has confirmed subscriptions to.
+    pub fn channels(&self) -> &BTreeSet<String> {
+        &self.channels
+    }
+
+    /// Returns the patterns the server has confirmed subscriptions to.
+    pub fn patterns(&self) -> &BTreeSet<String> {
+        &self.patterns
+    }
+
+    /// Replaces the connection, such as after the previous one failed, and restores every
+    /// subscription on it.
+    pub fn reconnect(&mut self, conn: Connection<S>) -> Result<(), Error> {
+        self.conn = conn;
+        self.channels.clear();
+        self.patterns.clear();
+        let channels: Vec<String> = self.wanted_channels.iter().cloned().collect();
+        let patterns: Vec<String> = self.wanted_patterns.iter().cloned().collect();
+        if !channels.is_empty() {
+            let channels: Vec<&str> = channels.iter().map(String::as_str).collect();
+            self.send("SUBSCRIBE", &channels)?;
+        }
+        if !patterns.is_empty() {
+            let patterns: Vec<&str> = patterns.iter().map(String::as_str).collect();
+            self.send("PSUBSCRIBE", &patterns)?;
+        }
+        Ok(())
+    }
+
+    /// Blocks until the next message arrives, recording any confirmations received meanwhile.
+    pub fn next_message(&mut self) -> Result<Message, Error> {
+        loop {
+            let resp = self.conn.recv()?;
+            if let Some(message) = self.handle(resp)? {
+                return Ok(message);
+            }
+        }
+    }
+
+    fn send(&mut self, command: &str, args: &[&str]) -> Result<(), Error> {
+        let command = RESP::command(
+            std::iter::once(command)
+                .chain(args.iter().copied())
+                .map(Borrowed),
+        );
+        self.conn.send(&command)
+    }
+
+    fn handle(&mut self, resp: RESPOwned) -> Result<Option<Message>, Error> {
+        let unexpected = |resp: &RESPOwned| Error::UnexpectedReply(resp.clone());
+        let elements = match &resp {
+            RESP::Array(elements) | RESP::Push(elements) => elements,
+            _ => return Err(unexpected(&resp)),
+        };
+        let strings: Option<Vec<Option<&str>>> = elements
+            .iter()
+            .map(|e| match e {
+                RESP::BulkString(s) | RESP::SimpleString(s) => Some(Some(&**s)),
+                RESP::NullBulkString | RESP::Null | RESP::Integer(_) => Some(None),
+                _ => None,
+            })
+            .collect();
+        let strings = strings.ok_or_else(|| unexpected(&resp))?;
+        match strings.as_slice() {
+            [Some("message"), Some(channel), Some(payload)] => Ok(Some(Message::Message {
+                channel: channel.to_string(),
+                payload: payload.to_string(),
+            })),
+            [Some("pmessage"), Some(pattern), Some(channel), Some(payload)] => {
+                Ok(Some(Message::PMessage {
+                    pattern: pattern.to_string(),
+                    channel: channel.to_string(),
+                    payload: payload.to_string(),
+                }))
+            }
+            [Some(kind), name, None] => {
+                let name = name.map(str::to_string);
+                match (*kind, name) {
+                    ("subscribe", Some(name)) => self.channels.insert(name),
+                    ("psubscribe", Some(name)) => self.patterns.insert(name),
+                    ("unsubscribe", Some(name)) => self.channels.remove(&name),
+                    ("punsubscribe", Some(name)) => self.patterns.remove(&name),
+                    // Unsubscribing when there are no subscriptions confirms a null name.
+                    ("unsubscribe", None) | ("punsubscribe", None) => false,
+                    _ => return Err(unexpected(&resp)),
+                };
+                Ok(None)
+            }
+            _ => Err(unexpected(&resp)),
+        }
+    }
+}
+
+impl<S: Read + Write> Iterator for Subscriber<S> {
+    type Item = Result<Message, Error>;
+
+    /// Returns the next message, ending when the connection is closed.
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_message() {
+            Err(Error::Io(ref err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => None,
+            message => Some(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    fn confirm(conn: &mut Connection<TcpStream>, kind: &str, count: i64) {
+        let request = conn.recv().unwrap();
+        let channel = match &request {
+            RESP::Array(args) => args[1].clone(),
+            _ => unreachable!(),
+        };
+        let reply = RESP::Array(vec![
+            RESP::BulkString(Borrowed(kind)),
+            channel,
+            RESP::Integer(count),
+        ]);
+        conn.send(&reply).unwrap();
+    }
+
+    #[test]
+    fn test_subscriber() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut conn = Connection::new(stream);
+            confirm(&mut conn, "subscribe", 1);
+            conn.send(&RESP::command(vec!["message", "news", "hello"]))
+                .unwrap();
+            drop(conn);
+
+            // The subscription is restored after reconnecting.
+            let (stream, _) = listener.accept().unwrap();
+            let mut conn = Connection::new(stream);
+            confirm(&mut conn, "subscribe", 1);
+            conn.send(&RESP::command(vec!["message", "news", "again"]))
+                .unwrap();
+        });
+
+        let mut subscriber = Subscriber::new(Connection::connect(addr).unwrap());
+        subscriber.subscribe(&["news"]).unwrap();
+        assert!(subscriber.channels().is_empty());
+        let message = Message::Message {
+            channel: "news".to_string(),
+            payload: "hello".to_string(),
+        };
+        assert_eq!(subscriber.next().unwrap().unwrap(), message);
+        assert!(subscriber.channels().contains("news"));
+        assert!(subscriber.next().is_none());
+
+        subscriber
+            .reconnect(Connection::connect(addr).unwrap())
+            .unwrap();
+        assert!(subscriber.channels().is_empty());
+        let message = Message::Message {
+            channel: "news".to_string(),
+            payload: "again".to_string(),
+        };
+        assert_eq!(subscriber.next().unwrap().unwrap(), message);
+        assert!(subscriber.channels().contains("news"));
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,362 @@
+//! Helpers for testing code which talks to a RESP server.
+use crate::connection::Connection;
+use crate::{parse, RESPOwned, RESP};
+use std::borrow::Cow::Borrowed;
+use std::collections::VecDeque;
+use std::fmt::{self, Write};
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+type ReplyFn = Box<dyn FnMut(&RESPOwned) -> RESPOwned + Send>;
+
+enum Reply {
+    Canned(RESPOwned),
+    With(ReplyFn),
+}
+
+struct Expectation {
+    request: RESPOwned,
+    reply: Reply,
+}
+
+#[derive(Default)]
+struct Script {
+    expectations: VecDeque<Expectation>,
+    unmatched: Vec<RESPOwned>,
+}
+
+/// A server on an ephemeral local port which expects requests in the order they're scripted.
+///
+/// Requests which don't match the next expectation are recorded and answered with an error, so
+/// that `verify` can report them.
+pub struct MockServer {
+    addr: SocketAddr,
+    script: Arc<Mutex<Script>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl MockServer {
+    pub fn start() -> io::Result<MockServer> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let script = Arc::new(Mutex::new(Script::default()));
+        let closed = Arc::new(AtomicBool::new(false));
+        let accept_script = script.clone();
+        let accept_closed = closed.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if accept_closed.load(Ordering::SeqCst) {
+                    return;
+                }
+                if let Ok(stream) = stream {
+                    let script = accept_script.clone();
+                    thread::spawn(move || serve(stream, &script));
+                }
+            }
+        });
+        Ok(MockServer {
+            addr,
+            script,
+            closed,
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Expects `request` next, replying to it with `reply`.
+    pub fn expect(&self, request: RESPOwned, reply: RESPOwned) -> &MockServer {
+        self.push(request, Reply::Canned(reply))
+    }
+
+    /// Expects `request` next, replying to it with whatever `reply` returns.
+    pub fn expect_with<F>(&self, request: RESPOwned, reply: F) -> &MockServer
+    where
+        F: FnMut(&RESPOwned) -> RESPOwned + Send + 'static,
+    {
+        self.push(request, Reply::With(Box::new(reply)))
+    }
+
+    /// Panics if any request didn't match its expectation, or if any expectation wasn't met.
+    pub fn verify(&self) {
+        let script = self.script.lock().unwrap();
+        assert!(
+            script.unmatched.is_empty(),
+            "unexpected requests: {:?}",
+            script.unmatched
+        );
+        let remaining: Vec<_> = script.expectations.iter().map(|e| &e.request).collect();
+        assert!(remaining.is_empty(), "requests never sent: {:?}", remaining);
+    }
+
+    fn push(&self, request: RESPOwned, reply: Reply) -> &MockServer {
+        let expectation = Expectation { request, reply };
+        self.script
+            .lock()
+            .unwrap()
+            .expectations
+            .push_back(expectation);
+        self
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::SeqCst);
+        // Wake the accept loop so that it sees it's closed.
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+fn serve(stream: TcpStream, script: &Mutex<Script>) {
+    let mut conn = Connection::new(stream);
+    while let Ok(request) = conn.recv() {
+        let reply = {
+            let mut script = script.lock().unwrap();
+            let matched = script
+                .expectations
+                .front()
+                .is_some_and(|e| e.request == request);
+            if matched {
+                match script.expectations.pop_front().unwrap().reply {
+                    Reply::Canned(reply) => reply,
+                    Reply::With(mut reply) => reply(&request),
+                }
+            } else {
+                script.unmatched.push(request);
+                RESP::Error(Borrowed("ERR unexpected request"))
+            }
+        };
+        if conn.send(&reply).is_err() {
+            return;
+        }
+    }
+}
+
+/// Something a value can be compared against in `assert_resp_eq!`: either another value or the
+/// wire bytes of one.
+pub trait Expected {
+    fn to_resp(&self) -> RESPOwned;
+}
+
+impl Expected for RESP<'_> {
+    fn to_resp(&self) -> RESPOwned {
+        self.clone().into_owned()
+    }
+}
+
+impl Expected for [u8] {
+    /// Panics if the bytes aren't exactly one frame.
+    fn to_resp(&self) -> RESPOwned {
+        match parse(self) {
+            Ok((len, resp)) if len == self.len() => resp.into_owned(),
+            Ok((len, _)) => panic!(
+                "{} trailing bytes after frame in {:?}",
+                self.len() - len,
+                self
+            ),
+            Err(err) => panic!("failed to parse {:?}: {:?}", self, err),
+        }
+    }
+}
+
+impl<const N: usize> Expected for [u8; N] {
+    fn to_resp(&self) -> RESPOwned {
+        self[..].to_resp()
+    }
+}
+
+impl Expected for Vec<u8> {
+    fn to_resp(&self) -> RESPOwned {
+        self[..].to_resp()
+    }
+}
+
+impl<T: Expected + ?Sized> Expected for &T {
+    fn to_resp(&self) -> RESPOwned {
+        (**self).to_resp()
+    }
+}
+
+/// Asserts that two values are equal, where either may also be given as wire bytes, printing a
+/// tree of the differences along with the path to each if they aren't.
+///
+/// ```
+/// use resp::{assert_resp_eq, RESP};
+///
+/// assert_resp_eq!(RESP::command(vec!["PING"]), b"*1\r\n$4\r\nPING\r\n");
+/// ```
+#[macro_export]
+macro_rules! assert_resp_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        $crate::testing::assert_resp_eq(&$left, &$right, None)
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        $crate::testing::assert_resp_eq(&$left, &$right, Some(format_args!($($arg)+)))
+    };
+}
+
+#[doc(hidden)]
+#[track_caller]
+pub fn assert_resp_eq<L, R>(left: &L, right: &R, message: Option<fmt::Arguments>)
+where
+    L: Expected + ?Sized,
+    R: Expected + ?Sized,
+{
+    let (left, right) = (left.to_resp(), right.to_resp());
+    if let Some(mut diff) = diff_tree(&left, &right) {
+        for entry in crate::diff(&left, &right) {
+            writeln!(diff, "at {}", entry).unwrap();
+        }
+        match message {
+            Some(message) => panic!("assertion `left == right` failed: {}\n{}", message, diff),
+            None => panic!("assertion `left == right` failed\n{}", diff),
+        }
+    }
+}
+
+/// Renders the two values as indented trees with the differing nodes marked `-` for `left` and
+/// `+` for `right`, or returns `None` if they're equal.
+pub fn diff_tree(left: &RESP, right: &RESP) -> Option<String> {
+    if left == right {
+        return None;
+    }
+    let mut out = String::new();
+    diff_node(left, right, 0, &mut out);
+    Some(out)
+}
+
+/// Returns a node's label, and its children along with how much deeper each is indented.
+fn node<'r>(resp: &'r RESP<'r>) -> (String, Vec<(usize, &'r RESP<'r>)>) {
+    let pairs = |pairs: &'r [(RESP<'r>, RESP<'r>)]| -> Vec<(usize, &'r RESP<'r>)> {
+        pairs.iter().flat_map(|(k, v)| [(1, k), (2, v)]).collect()
+    };
+    match resp {
+        RESP::Array(elements) => (format!("Array({})", elements.len()), ones(elements)),
+        RESP::Set(elements) => (format!("Set({})", elements.len()), ones(elements)),
+        RESP::Push(elements) => (format!("Push({})", elements.len()), ones(elements)),
+        RESP::Map(entries) => (format!("Map({})", entries.len()), pairs(entries)),
+        RESP::Attribute(attrs, inner) => {
+            let mut children = pairs(attrs);
+            children.push((1, inner));
+            (format!("Attribute({})", attrs.len()), children)
+        }
+        scalar => (format!("{:?}", scalar), Vec::new()),
+    }
+}
+
+fn ones<'r>(elements: &'r [RESP<'r>]) -> Vec<(usize, &'r RESP<'r>)> {
+    elements.iter().map(|e| (1, e)).collect()
+}
+
+fn is_same_aggregate(left: &RESP, right: &RESP) -> bool {
+    let aggregate = matches!(
+        left,
+        RESP::Array(_) | RESP::Set(_) | RESP::Push(_) | RESP::Map(_) | RESP::Attribute(..)
+    );
+    aggregate && std::mem::discriminant(left) == std::mem::discriminant(right)
+}
+
+fn diff_node(left: &RESP, right: &RESP, depth: usize, out: &mut String) {
+    let (left_label, left_children) = node(left);
+    let (right_label, right_children) = node(right);
+    if left == right {
+        render(' ', left, depth, out);
+    } else if is_same_aggregate(left, right) {
+        if left_label == right_label {
+            line(' ', &left_label, depth, out);
+        } else {
+            line('-', &left_label, depth, out);
+            line('+', &right_label, depth, out);
+        }
+        for i in 0..left_children.len().max(right_children.len()) {
+            match (left_children.get(i), right_children.get(i)) {
+                (Some((extra, l)), Some((_, r))) => diff_node(l, r, depth + extra, out),
+                (Some((extra, l)), None) => render('-', l, depth + extra, out),
+                (None, Some((extra, r))) => render('+', r, depth + extra, out),
+                (None, None) => unreachable!(),
+            }
+        }
+    } else {
+        render('-', left, depth, out);
+        render('+', right, depth, out);
+    }
+}
+
+fn render(sign: char, resp: &RESP, depth: usize, out: &mut String) {
+    let (label, children) = node(resp);
+    line(sign, &label, depth, out);
+    for (extra, child) in children {
+        render(sign, child, depth + extra, out);
+    }
+}
+
+fn line(sign: char, label: &str, depth: usize, out: &mut String) {
+    writeln!(out, "{} {}{}", sign, "  ".repeat(depth), label).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_server() {
+        let server = MockServer::start().unwrap();
+        server
+            .expect(
+                RESP::command(vec!["GET", "foo"]),
+                RESP::BulkString(Borrowed("bar")),
+            )
+            .expect_with(RESP::command(vec!["INCR", "n"]), |_| RESP::Integer(1));
+
+        let mut conn = Connection::connect(server.addr()).unwrap();
+        let reply = conn.call(&RESP::command(vec!["GET", "foo"])).unwrap();
+        assert_eq!(reply, RESP::BulkString(Borrowed("bar")));
+        let reply = conn.call(&RESP::command(vec!["INCR", "n"])).unwrap();
+        assert_eq!(reply, RESP::Integer(1));
+        server.verify();
+
+        let reply = conn.call(&RESP::command(vec!["PING"])).unwrap();
+        assert_eq!(reply, RESP::Error(Borrowed("ERR unexpected request")));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| server.verify()));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_diff_tree() {
+        let left = RESP::Map(vec![(
+            RESP::BulkString(Borrowed("list")),
+            RESP::Array(vec![RESP::Integer(1), RESP::Integer(2)]),
+        )]);
+        let right = RESP::Map(vec![(
+            RESP::BulkString(Borrowed("list")),
+            RESP::Array(vec![RESP::Integer(1), RESP::Integer(3), RESP::Null]),
+        )]);
+        let expected = "  Map(1)
+    BulkString(\"list\")
+-     Array(2)
++     Array(3)
+        Integer(1)
+-       Integer(2)
++       Integer(3)
++       Null
+";
+        assert_eq!(diff_tree(&left, &right).unwrap(), expected);
+        assert_eq!(diff_tree(&left, &left), None);
+
+        assert_resp_eq!(RESP::Integer(1), b":1\r\n");
+        let result = std::panic::catch_unwind(|| {
+            assert_resp_eq!(RESP::Integer(1), b":2\r\n", "reply to {}", "INCR");
+        });
+        let message = result.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(
+            *message,
+            "assertion `left == right` failed: reply to INCR\n- Integer(1)\n+ Integer(2)\n\
+             at <root>: Integer(1) != Integer(2)\n"
+        );
+    }
+}
@@ -0,0 +1,114 @@
+//! Logging of every frame a connection sends and receives, one line each, like watching a
+//! connection through `redis-cli --no-raw`.
+use crate::cli::escape_bytes;
+use crate::record::Direction;
+use std::io::{self, Write};
+
+/// Where and how much of each frame to log. See `Connection::set_trace`.
+pub struct Trace {
+    sink: Box<dyn Write + Send>,
+    max_len: usize,
+}
+
+impl Trace {
+    /// Logs frames to `sink`, truncating each to its first `max_len` bytes.
+    pub fn new<W: Write + Send + 'static>(sink: W, max_len: usize) -> Trace {
+        Trace {
+            sink: Box::new(sink),
+            max_len,
+        }
+    }
+
+    /// Logs frames to stderr, truncated to 128 bytes.
+    pub fn stderr() -> Trace {
+        Trace::new(io::stderr(), 128)
+    }
+
+    pub(crate) fn log(&mut self, direction: Direction, frame: &[u8]) -> io::Result<()> {
+        let line = trace_line(direction, frame, self.max_len);
+        writeln!(self.sink, "{}", line)
+    }
+}
+
+/// Formats a frame as its direction (`>` for sent, `<` for received), its length and its bytes
+/// escaped and quoted, followed by `...` if they were truncated to `max_len`.
+pub fn trace_line(direction: Direction, frame: &[u8], max_len: usize) -> String {
+    let shown = &frame[..frame.len().min(max_len)];
+    let ellipsis = if shown.len() < frame.len() { "..." } else { "" };
+    format!(
+        "{} {} {}{}",
+        direction.byte() as char,
+        frame.len(),
+        escape_bytes(shown),
+        ellipsis
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::Connection;
+    use crate::RESP;
+    use std::borrow::Cow::Borrowed;
+    use std::sync::{Arc, Mutex};
+
+    /// A sink which can be read after being handed to a `Trace`.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A stream which reads canned replies and discards what's written.
+    struct Replies<R>(R);
+
+    impl<R: io::Read> io::Read for Replies<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl<R> Write for Replies<R> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_trace() {
+        let test_cases: Vec<(Direction, &[u8], &str)> = vec![
+            (Direction::Sent, b"+OK\r\n", "> 5 \"+OK\\r\\n\""),
+            (
+                Direction::Received,
+                b"$5\r\nhello\r\n",
+                "< 11 \"$5\\r\\nhel\"...",
+            ),
+        ];
+        for (direction, frame, expected) in test_cases {
+            assert_eq!(trace_line(direction, frame, 7), expected);
+        }
+
+        let mut conn = Connection::new(Replies(&b"+PONG\r\n"[..]));
+        let sink = Shared::default();
+        conn.set_trace(Some(Trace::new(sink.clone(), 64)));
+        let reply = conn.call(&RESP::command(vec!["PING"])).unwrap();
+        assert_eq!(reply, RESP::SimpleString(Borrowed("PONG")));
+        conn.set_trace(None);
+        let log = String::from_utf8(sink.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            log,
+            "> 14 \"*1\\r\\n$4\\r\\nPING\\r\\n\"\n< 7 \"+PONG\\r\\n\"\n"
+        );
+    }
+}
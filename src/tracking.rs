@@ -0,0 +1,236 @@
+//! Support for client side caching, where the server tracks the keys a client has read and
+//! tells it when they change so it can drop them from its cache.
+use crate::replies::{integer, words, MapView, ReplyError};
+use crate::{RESPOwned, RESP};
+use std::borrow::Cow::{Borrowed, Owned};
+
+/// The channel RESP2 connections redirected to with `CLIENT TRACKING on REDIRECT` receive
+/// invalidations on.
+pub const INVALIDATE_CHANNEL: &str = "__redis__:invalidate";
+
+/// Keys which a client should drop from its cache.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Invalidation<'r> {
+    /// These keys have changed or been evicted.
+    Keys(Vec<&'r str>),
+    /// Every key should be dropped, such as after a `FLUSHALL`.
+    Flush,
+}
+
+impl<'r> Invalidation<'r> {
+    /// Decodes an invalidation message, or returns `None` if `message` isn't one.
+    ///
+    /// RESP3 connections receive these as `invalidate` pushes, and RESP2 connections as
+    /// pub/sub messages on `INVALIDATE_CHANNEL`. Either holds an array of keys, or a null for a
+    /// flush.
+    pub fn from_message(message: &'r RESP) -> Option<Invalidation<'r>> {
+        let elements = match message {
+            RESP::Array(elements) | RESP::Push(elements) => elements,
+            _ => return None,
+        };
+        let keys = match elements.as_slice() {
+            [kind, keys] if text(kind) == Some("invalidate") => keys,
+            [kind, channel, keys]
+                if text(kind) == Some("message") && text(channel) == Some(INVALIDATE_CHANNEL) =>
+            {
+                keys
+            }
+            _ => return None,
+        };
+        match keys {
+            RESP::Null | RESP::NullArray | RESP::NullBulkString => Some(Invalidation::Flush),
+            RESP::Array(keys) => keys
+                .iter()
+                .map(text)
+                .collect::<Option<_>>()
+                .map(Invalidation::Keys),
+            _ => None,
+        }
+    }
+}
+
+/// How the server should track keys for `CLIENT TRACKING on`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackingOptions {
+    /// Sends invalidations to the connection with this ID instead, such as a RESP2 connection
+    /// subscribed to `INVALIDATE_CHANNEL`.
+    pub redirect: Option<i64>,
+    /// Invalidates every key starting with one of `prefixes`, rather than only keys the client
+    /// has read.
+    pub bcast: bool,
+    pub prefixes: Vec<String>,
+    /// Only tracks keys read right after `CLIENT CACHING yes`.
+    pub optin: bool,
+    /// Tracks every key read except right after `CLIENT CACHING no`.
+    pub optout: bool,
+    /// Doesn't invalidate keys the client changed itself.
+    pub noloop: bool,
+}
+
+/// Returns the `CLIENT TRACKING on ...` command for `options`.
+pub fn tracking_on(options: &TrackingOptions) -> RESPOwned {
+    let mut args = vec![Borrowed("CLIENT"), Borrowed("TRACKING"), Borrowed("on")];
+    if let Some(id) = options.redirect {
+        args.push(Borrowed("REDIRECT"));
+        args.push(Owned(id.to_string()));
+    }
+    if options.bcast {
+        args.push(Borrowed("BCAST"));
+    }
+    for prefix in &options.prefixes {
+        args.push(Borrowed("PREFIX"));
+        args.push(Owned(prefix.clone()));
+    }
+    let flags = [
+        (options.optin, "OPTIN"),
+        (options.optout, "OPTOUT"),
+        (options.noloop, "NOLOOP"),
+    ];
+    for (_, flag) in flags.iter().filter(|(set, _)| *set) {
+        args.push(Borrowed(*flag));
+    }
+    RESP::command(args)
+}
+
+/// Returns the `CLIENT TRACKING off` command.
+pub fn tracking_off() -> RESPOwned {
+    RESP::command(vec!["CLIENT", "TRACKING", "off"])
+}
+
+/// Returns the `CLIENT CACHING yes` or `no` command, which opts the next command's keys in to
+/// or out of tracking.
+pub fn caching(track: bool) -> RESPOwned {
+    RESP::command(vec!["CLIENT", "CACHING", if track { "yes" } else { "no" }])
+}
+
+/// The state of tracking for a connection, from `CLIENT TRACKINGINFO`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackingInfo<'r> {
+    /// Such as `on`, `bcast` or `broken_redirect`.
+    pub flags: Vec<&'r str>,
+    /// The ID of the connection invalidations are sent to, `0` if they aren't redirected and
+    /// `-1` if tracking is off.
+    pub redirect: i64,
+    pub prefixes: Vec<&'r str>,
+}
+
+/// Decodes the map `CLIENT TRACKINGINFO` replies with.
+pub fn parse_tracking_info<'r>(reply: &'r RESP) -> Result<TrackingInfo<'r>, ReplyError> {
+    let mut info = TrackingInfo::default();
+    for (key, value) in MapView::new(reply)?.iter() {
+        match text(key) {
+            Some("flags") => info.flags = words(value)?,
+            Some("redirect") => info.redirect = integer(value)?,
+            Some("prefixes") => info.prefixes = words(value)?,
+            _ => {}
+        }
+    }
+    Ok(info)
+}
+
+/// Returns whether `message` is the `tracking-redir-broken` push, which the server sends once
+/// the connection invalidations were redirected to has closed.
+pub fn is_redirect_broken(message: &RESP) -> bool {
+    match message {
+        RESP::Push(elements) => elements
+            .first()
+            .is_some_and(|kind| text(kind) == Some("tracking-redir-broken")),
+        _ => false,
+    }
+}
+
+fn text<'r>(resp: &'r RESP) -> Option<&'r str> {
+    match resp {
+        RESP::SimpleString(s) | RESP::BulkString(s) => Some(s),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn test_invalidation() {
+        let test_cases: Vec<(&[u8], Option<Invalidation>)> = vec![
+            (
+                b">2\r\n$10\r\ninvalidate\r\n*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n",
+                Some(Invalidation::Keys(vec!["foo", "bar"])),
+            ),
+            (
+                b">2\r\n$10\r\ninvalidate\r\n_\r\n",
+                Some(Invalidation::Flush),
+            ),
+            (
+                b"*3\r\n$7\r\nmessage\r\n$20\r\n__redis__:invalidate\r\n*1\r\n$3\r\nfoo\r\n",
+                Some(Invalidation::Keys(vec!["foo"])),
+            ),
+            (
+                b"*3\r\n$7\r\nmessage\r\n$20\r\n__redis__:invalidate\r\n*-1\r\n",
+                Some(Invalidation::Flush),
+            ),
+            (b"*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n*0\r\n", None),
+            (b">3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$2\r\nhi\r\n", None),
+            (b"*2\r\n$7\r\nmessage\r\n*0\r\n", None),
+            (b">2\r\n$10\r\ninvalidate\r\n*1\r\n:1\r\n", None),
+            (b"+OK\r\n", None),
+        ];
+        for (buf, expected) in test_cases {
+            let (_, message) = parse(buf).unwrap();
+            assert_eq!(
+                Invalidation::from_message(&message),
+                expected,
+                "{:?}",
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn test_tracking_commands() {
+        let options = TrackingOptions {
+            redirect: Some(7),
+            bcast: true,
+            prefixes: vec!["user:".to_string(), "item:".to_string()],
+            noloop: true,
+            ..TrackingOptions::default()
+        };
+        assert_eq!(
+            tracking_on(&options),
+            RESP::command(vec![
+                "CLIENT", "TRACKING", "on", "REDIRECT", "7", "BCAST", "PREFIX", "user:", "PREFIX",
+                "item:", "NOLOOP",
+            ])
+        );
+        assert_eq!(
+            tracking_on(&TrackingOptions::default()),
+            RESP::command(vec!["CLIENT", "TRACKING", "on"])
+        );
+        assert_eq!(
+            tracking_off(),
+            RESP::command(vec!["CLIENT", "TRACKING", "off"])
+        );
+        assert_eq!(
+            caching(false),
+            RESP::command(vec!["CLIENT", "CACHING", "no"])
+        );
+
+        let (_, reply) = parse(
+            b"%3\r\n$5\r\nflags\r\n~2\r\n+on\r\n+bcast\r\n$8\r\nredirect\r\n:7\r\n\
+              $8\r\nprefixes\r\n*1\r\n$5\r\nuser:\r\n",
+        )
+        .unwrap();
+        assert_eq!(
+            parse_tracking_info(&reply),
+            Ok(TrackingInfo {
+                flags: vec!["on", "bcast"],
+                redirect: 7,
+                prefixes: vec!["user:"],
+            })
+        );
+        let (_, push) = parse(b">2\r\n$21\r\ntracking-redir-broken\r\n:7\r\n").unwrap();
+        assert!(is_redirect_broken(&push));
+        assert!(!is_redirect_broken(&reply));
+    }
+}
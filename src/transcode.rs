@@ -0,0 +1,173 @@
+//! Re-encoding of a stream of frames, for sanitizing traffic as it passes between two peers.
+//!
+//! Every frame is parsed and written out again, so whatever leniency it was accepted with, such
+//! as bare `\n` line endings, leaves as the canonical encoding, and anything which can't be
+//! parsed or breaks a limit stops the stream rather than being passed on.
+use crate::decoder::Decoder;
+use crate::{dump_with, DumpError, DumpOptions, ParseError, ParseOptions, RESP};
+use std::fmt;
+use std::io::{self, Read, Write};
+
+/// How `transcode` reads and writes frames, and the limits it enforces on them.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TranscodeOptions {
+    pub parse: ParseOptions,
+    pub dump: DumpOptions,
+    /// The most bytes a frame may take up as read.
+    pub max_frame_len: Option<usize>,
+    /// The most aggregates a value may be nested in, where a top level scalar is nested in 0.
+    pub max_depth: Option<usize>,
+}
+
+#[derive(Debug)]
+pub enum TranscodeError {
+    Io(io::Error),
+    Parse(ParseError),
+    Dump(DumpError),
+    /// A frame takes up at least this many bytes, more than `max_frame_len` allows.
+    TooLarge(usize),
+    /// A frame is nested this deep, more than `max_depth` allows.
+    TooDeep(usize),
+}
+
+impl fmt::Display for TranscodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TranscodeError::Io(err) => write!(f, "io error: {}", err),
+            TranscodeError::Parse(err) => write!(f, "parse error: {:?}", err),
+            TranscodeError::Dump(err) => write!(f, "dump error: {:?}", err),
+            TranscodeError::TooLarge(len) => write!(f, "frame of at least {} bytes", len),
+            TranscodeError::TooDeep(depth) => write!(f, "frame nested {} deep", depth),
+        }
+    }
+}
+
+impl From<io::Error> for TranscodeError {
+    fn from(err: io::Error) -> TranscodeError {
+        TranscodeError::Io(err)
+    }
+}
+
+impl From<ParseError> for TranscodeError {
+    fn from(err: ParseError) -> TranscodeError {
+        TranscodeError::Parse(err)
+    }
+}
+
+impl From<DumpError> for TranscodeError {
+    fn from(err: DumpError) -> TranscodeError {
+        TranscodeError::Dump(err)
+    }
+}
+
+/// Copies frames from `r` to `w` until `r` ends, re-encoding each, and returns the number of
+/// frames copied.
+///
+/// Frames are written out as soon as everything read so far has been decoded. If `r` ends part
+/// way through a frame, it fails with `Incomplete`.
+pub fn transcode<R: Read, W: Write>(
+    mut r: R,
+    mut w: W,
+    options: TranscodeOptions,
+) -> Result<u64, TranscodeError> {
+    let mut decoder = Decoder::with_options(options.parse);
+    let mut out = Vec::new();
+    let mut frames = 0;
+    loop {
+        while let Some((n, resp)) = decoder.decode()? {
+            check_len(n, options)?;
+            if let Some(max) = options.max_depth {
+                let depth = depth(&resp);
+                if depth > max {
+                    return Err(TranscodeError::TooDeep(depth));
+                }
+            }
+            let start = out.len();
+            out.resize(start + resp.encoded_len_with(options.dump), 0);
+            dump_with(&resp, &mut out[start..], options.dump)?;
+            frames += 1;
+        }
+        // Stop before buffering the rest of a frame which is already too large.
+        check_len(decoder.buffered().len(), options)?;
+        if !out.is_empty() {
+            w.write_all(&out)?;
+            w.flush()?;
+            out.clear();
+        }
+        if decoder.read_from(&mut r)? == 0 {
+            if decoder.buffered().is_empty() {
+                return Ok(frames);
+            }
+            return Err(ParseError::Incomplete.into());
+        }
+    }
+}
+
+fn check_len(len: usize, options: TranscodeOptions) -> Result<(), TranscodeError> {
+    match options.max_frame_len {
+        Some(max) if len > max => Err(TranscodeError::TooLarge(len)),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the number of aggregates nested in each other at the deepest point of `resp`.
+fn depth(resp: &RESP) -> usize {
+    let deepest = |elements: &mut dyn Iterator<Item = &RESP>| elements.map(depth).max();
+    match resp {
+        RESP::Array(elements) | RESP::Set(elements) | RESP::Push(elements) => {
+            1 + deepest(&mut elements.iter()).unwrap_or(0)
+        }
+        RESP::Map(pairs) => 1 + deepest(&mut pairs.iter().flat_map(|(k, v)| [k, v])).unwrap_or(0),
+        RESP::Attribute(attrs, value) => {
+            let attrs = 1 + deepest(&mut attrs.iter().flat_map(|(k, v)| [k, v])).unwrap_or(0);
+            attrs.max(depth(value))
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(input: &[u8], options: TranscodeOptions) -> Result<Vec<u8>, TranscodeError> {
+        let mut out = Vec::new();
+        transcode(input, &mut out, options)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_transcode() {
+        let lenient = TranscodeOptions {
+            parse: ParseOptions {
+                lenient_line_endings: true,
+                ..ParseOptions::default()
+            },
+            ..TranscodeOptions::default()
+        };
+        let input = b"+OK\n*2\n$3\nfoo\r\n:1\r\n";
+        assert_eq!(
+            run(input, lenient).unwrap(),
+            b"+OK\r\n*2\r\n$3\r\nfoo\r\n:1\r\n"
+        );
+        let mut out = Vec::new();
+        assert_eq!(transcode(&input[..], &mut out, lenient).unwrap(), 2);
+
+        let limited = TranscodeOptions {
+            max_frame_len: Some(8),
+            max_depth: Some(1),
+            ..TranscodeOptions::default()
+        };
+        let test_cases: Vec<(&[u8], &str)> = vec![
+            (b"$20\r\n0123456789", "frame of at least 15 bytes"),
+            (b"*1\r\n*0\r\n", "frame nested 2 deep"),
+            (b"+OK\r\n+O", "parse error: Incomplete"),
+            (b"?\r\n", "parse error: UnknownByte(63)"),
+        ];
+        for (input, expected) in test_cases {
+            let err = run(input, limited).unwrap_err();
+            assert_eq!(err.to_string(), expected, "{:?}", input);
+        }
+        assert_eq!(run(b"*1\r\n:1\r\n", limited).unwrap(), b"*1\r\n:1\r\n");
+    }
+}
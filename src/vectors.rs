@@ -0,0 +1,118 @@
+//! A corpus of wire frames along with the values they decode to, for testing codecs.
+//!
+//! The frames are checked in as `.bin` files under `test-vectors/`. Enable the `test-vectors`
+//! feature to use the corpus in another crate's tests.
+use crate::{RESPOwned, RESP};
+use std::borrow::Cow::Borrowed;
+
+/// One frame of the corpus.
+#[derive(Debug, Clone)]
+pub struct Vector {
+    /// The name of the fixture file, without the `.bin` extension.
+    pub name: &'static str,
+    pub wire: &'static [u8],
+    pub expected: RESPOwned,
+}
+
+macro_rules! vector {
+    ($name:literal, $expected:expr) => {
+        Vector {
+            name: $name,
+            wire: include_bytes!(concat!("../test-vectors/", $name, ".bin")),
+            expected: $expected,
+        }
+    };
+}
+
+fn simple(s: &'static str) -> RESPOwned {
+    RESP::SimpleString(Borrowed(s))
+}
+
+fn bulk(s: &'static str) -> RESPOwned {
+    RESP::BulkString(Borrowed(s))
+}
+
+/// Returns every vector in the corpus.
+pub fn vectors() -> Vec<Vector> {
+    vec![
+        vector!("simple_string", simple("OK")),
+        vector!("error", RESP::Error(Borrowed("ERR unknown command 'FOO'"))),
+        vector!("integer", RESP::Integer(i64::MIN)),
+        vector!("bulk_string", bulk("has\r\nCRLF")),
+        vector!("empty_bulk_string", bulk("")),
+        vector!("null_bulk_string", RESP::NullBulkString),
+        vector!("empty_array", RESP::Array(vec![])),
+        vector!("null_array", RESP::NullArray),
+        vector!(
+            "nested_array",
+            RESP::Array(vec![
+                RESP::Integer(1),
+                RESP::Array(vec![simple("a"), RESP::NullBulkString]),
+                RESP::Error(Borrowed("ERR x")),
+            ])
+        ),
+        vector!("null", RESP::Null),
+        vector!("boolean", RESP::Boolean(false)),
+        vector!("double", RESP::Double(-1.5)),
+        vector!("double_inf", RESP::Double(f64::INFINITY)),
+        vector!(
+            "big_number",
+            RESP::BigNumber(Borrowed("3492890328409238509324850943850943825024385"))
+        ),
+        vector!(
+            "blob_error",
+            RESP::BlobError(Borrowed("SYNTAX invalid syntax"))
+        ),
+        vector!(
+            "verbatim_string",
+            RESP::VerbatimString(Borrowed("txt"), Borrowed("Some string"))
+        ),
+        vector!(
+            "map",
+            RESP::Map(vec![
+                (simple("first"), RESP::Integer(1)),
+                (simple("second"), RESP::Boolean(true)),
+            ])
+        ),
+        vector!("set", RESP::Set(vec![simple("orange"), simple("apple")])),
+        vector!(
+            "attribute",
+            RESP::Attribute(
+                vec![(
+                    simple("key-popularity"),
+                    RESP::Map(vec![
+                        (bulk("a"), RESP::Double(0.1923)),
+                        (bulk("b"), RESP::Double(0.0012)),
+                    ]),
+                )],
+                Box::new(RESP::Array(vec![
+                    RESP::Integer(2039123),
+                    RESP::Integer(9543892),
+                ])),
+            )
+        ),
+        vector!(
+            "push",
+            RESP::Push(vec![bulk("message"), bulk("news"), bulk("hello")])
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dump, parse};
+
+    #[test]
+    fn test_vectors() {
+        for vector in vectors() {
+            let (len, resp) = parse(vector.wire).unwrap();
+            assert_eq!(len, vector.wire.len(), "{}", vector.name);
+            assert_eq!(resp, vector.expected, "{}", vector.name);
+
+            let mut buf = vec![0; vector.wire.len()];
+            assert_eq!(dump(&vector.expected, &mut buf), Ok(vector.wire.len()));
+            assert_eq!(buf, vector.wire, "{}", vector.name);
+        }
+    }
+}
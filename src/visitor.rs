@@ -0,0 +1,122 @@
+//! A push parser which calls back into a visitor for each value rather than building `RESP`
+//! objects.
+use crate::lexer::{Lexer, Token};
+use crate::ParseError;
+
+/// Receives the values of a frame as `parse_visit` parses them.
+pub trait Visitor<'a> {
+    /// Called with each value's token, along with its path: the index of each aggregate it's
+    /// nested in, ending with its own index in its parent.
+    ///
+    /// Maps count their keys and values as separate elements. An attribute's keys and values
+    /// are followed by the value it describes.
+    fn visit(&mut self, path: &[usize], token: Token<'a>);
+
+    /// Called after the last element of an aggregate.
+    fn end(&mut self) {}
+}
+
+/// Parses one frame from the start of `buf`, passing each value to `visitor`, returning the
+/// number of bytes read.
+pub fn parse_visit<'a, V: Visitor<'a>>(
+    buf: &'a [u8],
+    visitor: &mut V,
+) -> Result<usize, ParseError> {
+    let mut lexer = Lexer::new(buf);
+    // The number of elements left in each aggregate being visited.
+    let mut remaining = Vec::new();
+    let mut path = Vec::new();
+    loop {
+        let token = lexer.next_token()?.ok_or(ParseError::Incomplete)?;
+        visitor.visit(&path, token);
+        let children = match token {
+            Token::ArrayStart(n) | Token::SetStart(n) | Token::PushStart(n) => Some(n),
+            Token::MapStart(n) => Some(2 * n),
+            Token::AttributeStart(n) => Some(2 * n + 1),
+            _ => None,
+        };
+        match children {
+            Some(0) => visitor.end(),
+            Some(n) => {
+                remaining.push(n);
+                path.push(0);
+                continue;
+            }
+            None => {}
+        }
+        // The value is complete, so move on to its next sibling, ending any aggregates that
+        // it completes.
+        loop {
+            match remaining.last_mut() {
+                None => return Ok(lexer.offset()),
+                Some(1) => {
+                    remaining.pop();
+                    path.pop();
+                    visitor.end();
+                }
+                Some(n) => {
+                    *n -= 1;
+                    *path.last_mut().unwrap() += 1;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Collects the second element of each entry in an array of arrays.
+    struct Seconds<'a>(Vec<&'a [u8]>);
+
+    impl<'a> Visitor<'a> for Seconds<'a> {
+        fn visit(&mut self, path: &[usize], token: Token<'a>) {
+            if let ([_, 1], Token::Bulk(bytes)) = (path, token) {
+                self.0.push(bytes);
+            }
+        }
+    }
+
+    /// Records every callback.
+    #[derive(Default)]
+    struct Trace(Vec<String>);
+
+    impl<'a> Visitor<'a> for Trace {
+        fn visit(&mut self, path: &[usize], token: Token<'a>) {
+            self.0.push(format!("{:?} {:?}", path, token));
+        }
+
+        fn end(&mut self) {
+            self.0.push("end".to_string());
+        }
+    }
+
+    #[test]
+    fn test_parse_visit() {
+        let buf = b"*2\r\n*2\r\n$1\r\na\r\n$1\r\nb\r\n*3\r\n$1\r\nc\r\n$1\r\nd\r\n:1\r\n+next\r\n";
+        let mut seconds = Seconds(Vec::new());
+        assert_eq!(parse_visit(buf, &mut seconds), Ok(buf.len() - 7));
+        assert_eq!(seconds.0, vec![b"b", b"d"]);
+
+        let mut trace = Trace::default();
+        parse_visit(b"%1\r\n+k\r\n*0\r\n", &mut trace).unwrap();
+        assert_eq!(
+            trace.0,
+            vec![
+                "[] MapStart(1)",
+                "[0] Simple([107])",
+                "[1] ArrayStart(0)",
+                "end",
+                "end"
+            ]
+        );
+
+        let mut trace = Trace::default();
+        assert_eq!(
+            parse_visit(b"*2\r\n:1\r\n", &mut trace),
+            Err(ParseError::Incomplete)
+        );
+    }
+}
@@ -0,0 +1,225 @@
+//! Coalescing of encoded frames into large writes.
+use crate::metrics::Metrics;
+use crate::{dump_append, Error, RESP};
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+/// When a `FrameWriter` writes out what it has buffered, besides on an explicit `flush`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlushPolicy {
+    /// Flush once at least this many bytes are buffered.
+    pub max_bytes: Option<usize>,
+    /// Flush once at least this many frames are buffered.
+    pub max_frames: Option<usize>,
+}
+
+impl Default for FlushPolicy {
+    fn default() -> FlushPolicy {
+        FlushPolicy {
+            max_bytes: Some(64 * 1024),
+            max_frames: None,
+        }
+    }
+}
+
+impl FlushPolicy {
+    /// A policy which only flushes explicitly.
+    pub fn explicit() -> FlushPolicy {
+        FlushPolicy {
+            max_bytes: None,
+            max_frames: None,
+        }
+    }
+}
+
+/// Buffers encoded frames, writing them out together according to a `FlushPolicy`.
+pub struct FrameWriter<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+    frames: usize,
+    policy: FlushPolicy,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl<W: Write> FrameWriter<W> {
+    pub fn new(inner: W, policy: FlushPolicy) -> FrameWriter<W> {
+        FrameWriter {
+            inner,
+            buf: Vec::new(),
+            frames: 0,
+            policy,
+            metrics: None,
+        }
+    }
+
+    /// Records each written frame in `metrics`.
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Buffers a frame, flushing if the policy says so.
+    pub fn write_frame(&mut self, resp: &RESP) -> Result<(), Error> {
+        let n = dump_append(resp, &mut self.buf)?;
+        if let Some(metrics) = &self.metrics {
+            metrics.record_encoded(n);
+        }
+        self.frames += 1;
+        let full = self.policy.max_bytes.is_some_and(|n| self.buf.len() >= n)
+            || self.policy.max_frames.is_some_and(|n| self.frames >= n);
+        if full {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes out everything buffered in a single write.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if !self.buf.is_empty() {
+            self.inner.write_all(&self.buf)?;
+            self.buf.clear();
+            self.frames = 0;
+        }
+        self.inner.flush()
+    }
+
+    /// Writes out everything buffered, followed by a bulk string of `len` bytes copied from
+    /// `body`. See `write_bulk_from`.
+    pub fn write_bulk_from<R: Read>(&mut self, len: u64, body: &mut R) -> io::Result<()> {
+        self.flush()?;
+        write_bulk_from(&mut self.inner, len, body)
+    }
+
+    /// Returns the number of bytes and frames buffered.
+    pub fn buffered(&self) -> (usize, usize) {
+        (self.buf.len(), self.frames)
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// Writes a bulk string of `len` bytes copied from `body` as it's read, so that large values
+/// never have to be held in memory.
+///
+/// Fails with `UnexpectedEof` if `body` ends before `len` bytes, leaving the stream corrupt.
+pub fn write_bulk_from<W: Write, R: Read>(w: &mut W, len: u64, body: &mut R) -> io::Result<()> {
+    write!(w, "${}\r\n", len)?;
+    if io::copy(&mut body.take(len), w)? < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    w.write_all(b"\r\n")
+}
+
+/// Writes a bulk string of `len` bytes made up of `chunks`, such as the pieces of a value being
+/// generated.
+///
+/// Fails with `InvalidInput` if the chunks don't add up to `len` bytes, leaving the stream
+/// corrupt.
+pub fn write_bulk_chunks<W, I>(w: &mut W, len: u64, chunks: I) -> io::Result<()>
+where
+    W: Write,
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "chunks don't match length");
+    write!(w, "${}\r\n", len)?;
+    let mut written = 0;
+    for chunk in chunks {
+        let chunk = chunk.as_ref();
+        written += chunk.len() as u64;
+        if written > len {
+            return Err(invalid());
+        }
+        w.write_all(chunk)?;
+    }
+    if written < len {
+        return Err(invalid());
+    }
+    w.write_all(b"\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records the size of each write.
+    #[derive(Default)]
+    struct Writes(Vec<usize>);
+
+    impl Write for Writes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.push(buf.len());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_flush_policies() {
+        let test_cases = vec![
+            (FlushPolicy::explicit(), vec![50]),
+            (
+                FlushPolicy {
+                    max_bytes: None,
+                    max_frames: Some(4),
+                },
+                vec![20, 20, 10],
+            ),
+            (
+                FlushPolicy {
+                    max_bytes: Some(12),
+                    max_frames: None,
+                },
+                vec![15, 15, 15, 5],
+            ),
+        ];
+        for (policy, writes) in test_cases {
+            let mut writer = FrameWriter::new(Writes::default(), policy);
+            for i in 0..10 {
+                writer.write_frame(&RESP::Integer(10 + i)).unwrap();
+            }
+            assert_eq!(writer.into_inner().unwrap().0, writes);
+        }
+    }
+
+    #[test]
+    fn test_write_bulk() {
+        let mut out = Vec::new();
+        write_bulk_from(&mut out, 5, &mut &b"hello world"[..]).unwrap();
+        write_bulk_chunks(&mut out, 6, vec!["foo", "", "bar"]).unwrap();
+        assert_eq!(out, b"$5\r\nhello\r\n$6\r\nfoobar\r\n");
+
+        let err = write_bulk_from(&mut Vec::new(), 5, &mut &b"hi"[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        for chunks in [vec!["ab"], vec!["abc", "d"]] {
+            let err = write_bulk_chunks(&mut Vec::new(), 3, chunks).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+
+        let mut writer = FrameWriter::new(Vec::new(), FlushPolicy::explicit());
+        writer.write_frame(&RESP::Integer(1)).unwrap();
+        writer.write_bulk_from(2, &mut &b"ok"[..]).unwrap();
+        assert_eq!(writer.get_ref(), b":1\r\n$2\r\nok\r\n");
+    }
+
+    #[test]
+    fn test_write_frame_error() {
+        let mut writer = FrameWriter::new(Vec::new(), FlushPolicy::explicit());
+        let invalid = RESP::Array(vec![RESP::Integer(1), RESP::SimpleString("a\r\nb".into())]);
+        assert!(writer.write_frame(&invalid).is_err());
+        writer
+            .write_frame(&RESP::SimpleString("OK".into()))
+            .unwrap();
+        assert_eq!(writer.into_inner().unwrap(), b"+OK\r\n");
+    }
+}
@@ -0,0 +1,165 @@
+//! Conformance tests against a live server, checking that every reply type parses and dumps back
+//! to exactly the bytes the server sent.
+//!
+//! These are ignored by default. Run them with a server at `REDIS_URL`:
+//!
+//! ```text
+//! REDIS_URL=redis://127.0.0.1:6379 cargo test --test conformance -- --ignored
+//! ```
+use resp::connection::{Endpoint, Stream};
+use resp::{dump, parse, ParseError, RESP};
+use std::borrow::Cow::Borrowed;
+use std::io::{Read, Write};
+
+fn connect() -> Stream {
+    let url = std::env::var("REDIS_URL").expect("REDIS_URL must be set");
+    Endpoint::parse(&url).connect().unwrap()
+}
+
+/// Prefixes a key so that concurrent runs don't collide.
+fn key(name: &str) -> String {
+    format!("resp-conformance:{}:{}", std::process::id(), name)
+}
+
+/// Sends `args` as a command, returning the raw bytes of the reply.
+fn raw_call(stream: &mut Stream, args: &[&str]) -> Vec<u8> {
+    let command = RESP::command(args.iter().copied());
+    let mut buf = vec![0; command.encoded_len()];
+    dump(&command, &mut buf).unwrap();
+    stream.write_all(&buf).unwrap();
+
+    let mut reply = Vec::new();
+    let mut chunk = [0; 8192];
+    loop {
+        match parse(&reply) {
+            Ok((len, _)) => {
+                assert_eq!(len, reply.len(), "trailing bytes after reply");
+                return reply;
+            }
+            Err(ParseError::Incomplete) | Err(ParseError::CLRFNotFound) => {}
+            Err(err) => panic!("failed to parse {:?}: {:?}", reply, err),
+        }
+        let n = stream.read(&mut chunk).unwrap();
+        assert!(n > 0, "connection closed mid-reply");
+        reply.extend_from_slice(&chunk[..n]);
+    }
+}
+
+/// Calls `args`, checks the reply round-trips through `parse` and `dump`, and passes it to
+/// `check`.
+fn round_trip(stream: &mut Stream, args: &[&str], check: impl FnOnce(&RESP)) {
+    let raw = raw_call(stream, args);
+    let (_, reply) = parse(&raw).unwrap();
+    assert_eq!(reply.encoded_len(), raw.len(), "{:?}", args);
+    let mut dumped = vec![0; raw.len()];
+    dump(&reply, &mut dumped).unwrap();
+    assert_eq!(dumped, raw, "{:?}", args);
+    check(&reply);
+}
+
+#[test]
+#[ignore]
+fn test_resp2_replies() {
+    let mut stream = connect();
+    let (string, list, stream_key) = (key("string"), key("list"), key("stream"));
+    raw_call(&mut stream, &["DEL", &string, &list, &stream_key]);
+
+    round_trip(&mut stream, &["PING"], |r| {
+        assert_eq!(*r, RESP::SimpleString(Borrowed("PONG")))
+    });
+    round_trip(&mut stream, &["SET", &string, ""], |r| {
+        assert_eq!(*r, RESP::SimpleString(Borrowed("OK")))
+    });
+    round_trip(&mut stream, &["GET", &string], |r| {
+        assert_eq!(*r, RESP::BulkString(Borrowed("")))
+    });
+    round_trip(&mut stream, &["GET", &key("missing")], |r| {
+        assert_eq!(*r, RESP::NullBulkString)
+    });
+    round_trip(&mut stream, &["APPEND", &string, "a\r\nb"], |r| {
+        assert_eq!(*r, RESP::Integer(4))
+    });
+    round_trip(
+        &mut stream,
+        &["DECRBY", &key("counter"), "9223372036854775807"],
+        |r| assert_eq!(*r, RESP::Integer(-i64::MAX)),
+    );
+    round_trip(&mut stream, &["NOT-A-COMMAND"], |r| {
+        assert!(matches!(r, RESP::Error(e) if e.starts_with("ERR")))
+    });
+    round_trip(&mut stream, &["BLPOP", &list, "0.01"], |r| {
+        assert_eq!(*r, RESP::NullArray)
+    });
+    round_trip(&mut stream, &["LRANGE", &list, "0", "-1"], |r| {
+        assert_eq!(*r, RESP::Array(vec![]))
+    });
+
+    let values: Vec<String> = (0..10_000).map(|i| i.to_string()).collect();
+    let mut rpush = vec!["RPUSH", list.as_str()];
+    rpush.extend(values.iter().map(String::as_str));
+    round_trip(&mut stream, &rpush, |r| {
+        assert_eq!(*r, RESP::Integer(10_000))
+    });
+    round_trip(&mut stream, &["LRANGE", &list, "0", "-1"], |r| {
+        assert_eq!(*r, RESP::command(values.iter().map(String::as_str)))
+    });
+
+    raw_call(&mut stream, &["XADD", &stream_key, "1-1", "field", "value"]);
+    round_trip(&mut stream, &["XRANGE", &stream_key, "-", "+"], |r| {
+        let entry = RESP::Array(vec![
+            RESP::BulkString(Borrowed("1-1")),
+            RESP::command(vec!["field", "value"]),
+        ]);
+        assert_eq!(*r, RESP::Array(vec![entry]))
+    });
+
+    raw_call(
+        &mut stream,
+        &["DEL", &string, &list, &stream_key, &key("counter")],
+    );
+}
+
+#[test]
+#[ignore]
+fn test_resp3_replies() {
+    let mut stream = connect();
+    let (hash, set, zset) = (key("hash"), key("set"), key("zset"));
+    raw_call(&mut stream, &["DEL", &hash, &set, &zset]);
+
+    round_trip(&mut stream, &["HELLO", "3"], |r| {
+        assert!(matches!(r, RESP::Map(pairs) if !pairs.is_empty()))
+    });
+    round_trip(&mut stream, &["GET", &key("missing")], |r| {
+        assert_eq!(*r, RESP::Null)
+    });
+
+    raw_call(&mut stream, &["HSET", &hash, "field", "value"]);
+    round_trip(&mut stream, &["HGETALL", &hash], |r| {
+        let pair = (
+            RESP::BulkString(Borrowed("field")),
+            RESP::BulkString(Borrowed("value")),
+        );
+        assert_eq!(*r, RESP::Map(vec![pair]))
+    });
+    round_trip(&mut stream, &["HGETALL", &key("missing")], |r| {
+        assert_eq!(*r, RESP::Map(vec![]))
+    });
+
+    raw_call(&mut stream, &["SADD", &set, "member"]);
+    round_trip(&mut stream, &["SMEMBERS", &set], |r| {
+        assert_eq!(*r, RESP::Set(vec![RESP::BulkString(Borrowed("member"))]))
+    });
+
+    raw_call(&mut stream, &["ZADD", &zset, "1.5", "a", "inf", "b"]);
+    round_trip(&mut stream, &["ZSCORE", &zset, "a"], |r| {
+        assert_eq!(*r, RESP::Double(1.5))
+    });
+    round_trip(&mut stream, &["ZSCORE", &zset, "b"], |r| {
+        assert_eq!(*r, RESP::Double(f64::INFINITY))
+    });
+    round_trip(&mut stream, &["SISMEMBER", &set, "member"], |r| {
+        assert_eq!(*r, RESP::Integer(1))
+    });
+
+    raw_call(&mut stream, &["DEL", &hash, &set, &zset]);
+}
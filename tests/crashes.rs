@@ -0,0 +1,63 @@
+//! Replays inputs which once made the parser panic, or are shaped like ones that could, through
+//! every entry point which parses untrusted bytes.
+//!
+//! To add a crasher, save the input which reproduces it as a file in `tests/crashes/`.
+use resp::decoder::Decoder;
+use resp::lazy::ArrayParser;
+use resp::lexer::Lexer;
+use resp::mapped::parse_mmap;
+use resp::span::parse_spans;
+use resp::{parse, parse_split, parse_with, peek_len, skip_value, ParseOptions};
+use std::fs;
+use std::panic;
+use std::path::Path;
+
+fn replay(input: &[u8]) {
+    let lenient = ParseOptions {
+        lossy_utf8: true,
+        lenient_line_endings: true,
+        big_integers: true,
+        lenient_integers: true,
+        ..ParseOptions::default()
+    };
+    let _ = parse(input);
+    let _ = parse_with(input, lenient);
+    let _ = skip_value(input);
+    let _ = peek_len(input);
+    let _ = parse_spans(input);
+    let _ = Lexer::new(input).count();
+    let _ = ArrayParser::new(input);
+    let _ = parse_mmap(input).count();
+    for split in 0..=input.len() {
+        let (head, tail) = input.split_at(split);
+        let _ = parse_split(head, tail);
+    }
+
+    let mut decoder = Decoder::new();
+    for b in input {
+        decoder.feed(&[*b]);
+        if decoder.decode().is_err() {
+            break;
+        }
+    }
+    let _ = Decoder::from_chunks(input.chunks(3)).count();
+}
+
+#[test]
+fn test_crashes() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/crashes");
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+    let crashed: Vec<_> = paths
+        .iter()
+        .filter(|path| {
+            let input = fs::read(path).unwrap();
+            panic::catch_unwind(|| replay(&input)).is_err()
+        })
+        .collect();
+    assert!(crashed.is_empty(), "inputs panicked: {:?}", crashed);
+}
@@ -0,0 +1,478 @@
+//! Differential fuzzing of `parse` against a reference parser written straight from the spec,
+//! flagging inputs on which they disagree about whether a frame is accepted, rejected or
+//! incomplete, or about how long an accepted frame is.
+//!
+//! The reference parser is deliberately simple rather than fast, so that it's easy to check
+//! against the spec by eye. Divergences are either gaps in this crate's compliance or places
+//! where it's deliberately lenient, which are listed in `LENIENCIES`, and only the former fail the
+//! harness. It's ignored by default, since it's slow with many iterations. Run it with:
+//!
+//! ```text
+//! DIFFERENTIAL_ITERATIONS=1000000 cargo test --test differential -- --ignored --nocapture
+//! ```
+use resp::{parse, ParseError};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// What a parser made of an input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Verdict {
+    Accept(usize),
+    Incomplete,
+    Reject,
+}
+
+impl Verdict {
+    fn name(self) -> &'static str {
+        match self {
+            Verdict::Accept(_) => "accept",
+            Verdict::Incomplete => "incomplete",
+            Verdict::Reject => "reject",
+        }
+    }
+}
+
+fn verdict(input: &[u8]) -> Verdict {
+    match parse(input) {
+        Ok((n, _)) => Verdict::Accept(n),
+        Err(ParseError::Incomplete) | Err(ParseError::CLRFNotFound) => Verdict::Incomplete,
+        Err(_) => Verdict::Reject,
+    }
+}
+
+/// A parser for the grammar in the RESP3 spec, which only decides where a frame ends.
+struct Reference<'a> {
+    buf: &'a [u8],
+    offset: usize,
+}
+
+/// Why the reference parser stopped.
+enum Stop {
+    Incomplete,
+    Reject,
+}
+
+impl<'a> Reference<'a> {
+    fn verdict(buf: &'a [u8]) -> Verdict {
+        let mut reference = Reference { buf, offset: 0 };
+        match reference.value() {
+            Ok(()) => Verdict::Accept(reference.offset),
+            Err(Stop::Incomplete) => Verdict::Incomplete,
+            Err(Stop::Reject) => Verdict::Reject,
+        }
+    }
+
+    fn value(&mut self) -> Result<(), Stop> {
+        let kind = self.take(1)?[0];
+        match kind {
+            b'+' | b'-' => self.text().map(|_| ()),
+            b':' => self.integer(true).map(|_| ()),
+            b'(' => self.integer(false).map(|_| ()),
+            b',' => self.double(),
+            b'_' => self.exact(b""),
+            b'#' => match self.line()? {
+                b"t" | b"f" => Ok(()),
+                _ => Err(Stop::Reject),
+            },
+            b'$' => match self.length(true)? {
+                None => Ok(()),
+                Some(n) => self.blob(n).map(|_| ()),
+            },
+            b'!' => {
+                let n = self.length(false)?.unwrap();
+                self.blob(n).map(|_| ())
+            }
+            b'=' => {
+                let n = self.length(false)?.unwrap();
+                match self.blob(n)? {
+                    blob if blob.len() >= 4 && blob[3] == b':' => Ok(()),
+                    _ => Err(Stop::Reject),
+                }
+            }
+            b'*' => match self.length(true)? {
+                None => Ok(()),
+                Some(n) => self.values(n),
+            },
+            b'~' | b'>' => {
+                let n = self.length(false)?.unwrap();
+                self.values(n)
+            }
+            b'%' => {
+                let n = self.length(false)?.unwrap();
+                self.values(2 * n)
+            }
+            b'|' => {
+                let n = self.length(false)?.unwrap();
+                self.values(2 * n)?;
+                self.value()
+            }
+            _ => Err(Stop::Reject),
+        }
+    }
+
+    fn values(&mut self, n: u64) -> Result<(), Stop> {
+        for _ in 0..n {
+            self.value()?;
+        }
+        Ok(())
+    }
+
+    fn take(&mut self, n: u64) -> Result<&'a [u8], Stop> {
+        let rest = &self.buf[self.offset..];
+        if (rest.len() as u64) < n {
+            return Err(Stop::Incomplete);
+        }
+        let n = n as usize;
+        self.offset += n;
+        Ok(&rest[..n])
+    }
+
+    /// Reads up to the next `\r\n`. Lines can't contain a `\r` or `\n` of their own.
+    fn line(&mut self) -> Result<&'a [u8], Stop> {
+        let rest = &self.buf[self.offset..];
+        let end = match rest.iter().position(|&b| b == b'\r' || b == b'\n') {
+            Some(end) => end,
+            None => return Err(Stop::Incomplete),
+        };
+        match rest.get(end..end + 2) {
+            Some(b"\r\n") => {}
+            Some(_) => return Err(Stop::Reject),
+            None if rest[end] == b'\r' => return Err(Stop::Incomplete),
+            None => return Err(Stop::Reject),
+        }
+        self.offset += end + 2;
+        Ok(&rest[..end])
+    }
+
+    /// Reads a line which must be UTF-8. The spec doesn't require it, but this crate holds
+    /// strings as `str`, so anything else is a known divergence rather than an interesting one.
+    fn text(&mut self) -> Result<&'a [u8], Stop> {
+        let line = self.line()?;
+        std::str::from_utf8(line).map_err(|_| Stop::Reject)?;
+        Ok(line)
+    }
+
+    fn exact(&mut self, expected: &[u8]) -> Result<(), Stop> {
+        match self.line()? {
+            line if line == expected => Ok(()),
+            _ => Err(Stop::Reject),
+        }
+    }
+
+    /// Reads a decimal integer, which must fit in an `i64` if `bounded`.
+    ///
+    /// The spec allows a `+` sign, and doesn't forbid leading zeros, but Redis only ever sends
+    /// `0` or `-?[1-9][0-9]*`, which is all this crate accepts without `lenient_integers`.
+    fn integer(&mut self, bounded: bool) -> Result<(), Stop> {
+        let line = self.line()?;
+        let digits = match line.first() {
+            Some(b'-') => &line[1..],
+            _ => line,
+        };
+        let canonical = line == b"0" || (!digits.is_empty() && digits[0] != b'0');
+        if !canonical || !digits.iter().all(u8::is_ascii_digit) {
+            return Err(Stop::Reject);
+        }
+        if bounded {
+            let text = std::str::from_utf8(line).unwrap();
+            text.parse::<i64>().map_err(|_| Stop::Reject)?;
+        }
+        Ok(())
+    }
+
+    /// Reads `[+|-]integral[.fractional][(E|e)[+|-]exponent]`, or `inf`, `-inf` or `nan`.
+    fn double(&mut self) -> Result<(), Stop> {
+        let line = self.line()?;
+        if line == b"inf" || line == b"-inf" || line == b"nan" {
+            return Ok(());
+        }
+        let mut rest = match line.first() {
+            Some(b'+') | Some(b'-') => &line[1..],
+            _ => line,
+        };
+        let digits = |rest: &mut &[u8]| {
+            let n = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+            *rest = &rest[n..];
+            n
+        };
+        if digits(&mut rest) == 0 {
+            return Err(Stop::Reject);
+        }
+        if let Some(b'.') = rest.first() {
+            rest = &rest[1..];
+            if digits(&mut rest) == 0 {
+                return Err(Stop::Reject);
+            }
+        }
+        if let Some(b'e') | Some(b'E') = rest.first() {
+            rest = &rest[1..];
+            if let Some(b'+') | Some(b'-') = rest.first() {
+                rest = &rest[1..];
+            }
+            if digits(&mut rest) == 0 {
+                return Err(Stop::Reject);
+            }
+        }
+        match rest {
+            b"" => Ok(()),
+            _ => Err(Stop::Reject),
+        }
+    }
+
+    /// Reads a length line, which is `-1` for a null if `nullable`.
+    fn length(&mut self, nullable: bool) -> Result<Option<u64>, Stop> {
+        let line = self.line()?;
+        if nullable && line == b"-1" {
+            return Ok(None);
+        }
+        let canonical = line == b"0" || (!line.is_empty() && line[0] != b'0');
+        if !canonical || !line.iter().all(u8::is_ascii_digit) {
+            return Err(Stop::Reject);
+        }
+        let text = std::str::from_utf8(line).unwrap();
+        text.parse().map(Some).map_err(|_| Stop::Reject)
+    }
+
+    /// Reads a string of `n` bytes followed by `\r\n`, which must be UTF-8 like `text`.
+    fn blob(&mut self, n: u64) -> Result<&'a [u8], Stop> {
+        let blob = self.take(n)?;
+        match self.take(2)? {
+            b"\r\n" => {}
+            _ => return Err(Stop::Reject),
+        }
+        std::str::from_utf8(blob).map_err(|_| Stop::Reject)?;
+        Ok(blob)
+    }
+}
+
+/// Valid frames of every type, which are mutated into the inputs.
+const SEEDS: &[&[u8]] = &[
+    b"+OK\r\n",
+    b"-ERR unknown command\r\n",
+    b":0\r\n",
+    b":-42\r\n",
+    b"$5\r\nhello\r\n",
+    b"$0\r\n\r\n",
+    b"$-1\r\n",
+    b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n",
+    b"*-1\r\n",
+    b"*0\r\n",
+    b"_\r\n",
+    b"#t\r\n",
+    b",1.5\r\n",
+    b",-2e10\r\n",
+    b",inf\r\n",
+    b"(3492890328409238509324850943850943825024385\r\n",
+    b"!21\r\nSYNTAX invalid syntax\r\n",
+    b"=15\r\ntxt:Some string\r\n",
+    b"%2\r\n+first\r\n:1\r\n+second\r\n:2\r\n",
+    b"~2\r\n+orange\r\n+apple\r\n",
+    b"|1\r\n+ttl\r\n:3600\r\n$3\r\nbar\r\n",
+    b">3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\nhello\r\n",
+];
+
+/// Bytes which are likely to change how a frame parses.
+const INTERESTING: &[u8] = b"+-:$*_#,(!=%~|>\r\n0123456789.eEtfinax\xff";
+
+/// An xorshift64 generator, so that a divergence can be reproduced from its iteration.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+/// Applies a few random edits to a seed.
+fn mutate(rng: &mut Rng, seed: &[u8]) -> Vec<u8> {
+    let mut input = seed.to_vec();
+    for _ in 0..1 + rng.below(3) {
+        let at = rng.below(input.len() + 1);
+        let byte = INTERESTING[rng.below(INTERESTING.len())];
+        match rng.below(4) {
+            0 => input.insert(at, byte),
+            1 if at < input.len() => input[at] = byte,
+            2 if at < input.len() => {
+                input.remove(at);
+            }
+            _ => input.truncate(at),
+        }
+    }
+    input
+}
+
+/// A place where this crate is deliberately more lenient than the spec.
+struct Leniency {
+    name: &'static str,
+    /// What `parse` makes of inputs the spec rejects.
+    verdicts: &'static [&'static str],
+    /// Whether an input has what the leniency allows.
+    applies: fn(&[u8]) -> bool,
+}
+
+/// The known divergences, which the harness doesn't fail on.
+const LENIENCIES: &[Leniency] = &[
+    Leniency {
+        name: "negative lengths other than -1 are nulls",
+        verdicts: &["accept"],
+        applies: |input| {
+            lines(input).any(|line| match line {
+                [b'$', b'-', rest @ ..] | [b'*', b'-', rest @ ..] => {
+                    rest != b"1" && is_digits(rest) && rest[0] != b'0'
+                }
+                _ => false,
+            })
+        },
+    },
+    Leniency {
+        name: "doubles are anything Rust parses as an f64, such as .5 or infinity",
+        verdicts: &["accept"],
+        applies: |input| {
+            lines(input).any(|line| match line {
+                [b',', rest @ ..] => {
+                    std::str::from_utf8(rest).is_ok_and(|s| s.parse::<f64>().is_ok())
+                }
+                _ => false,
+            })
+        },
+    },
+    Leniency {
+        name: "big numbers may have leading zeros",
+        verdicts: &["accept"],
+        applies: |input| {
+            lines(input).any(|line| match line {
+                [b'(', b'-', digits @ ..] | [b'(', digits @ ..] => {
+                    digits.len() > 1 && digits[0] == b'0' && is_digits(digits)
+                }
+                _ => false,
+            })
+        },
+    },
+    Leniency {
+        name: "lines end at the first \\r\\n, so may hold a lone \\r or \\n",
+        verdicts: &["accept", "incomplete"],
+        applies: |input| {
+            input.iter().enumerate().any(|(i, &b)| match b {
+                b'\r' => input.get(i + 1).is_some_and(|&next| next != b'\n'),
+                b'\n' => i == 0 || input[i - 1] != b'\r',
+                _ => false,
+            })
+        },
+    },
+];
+
+/// Splits an input into lines, including lines inside strings.
+fn lines(input: &[u8]) -> impl Iterator<Item = &[u8]> {
+    input
+        .split(|&b| b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+}
+
+fn is_digits(s: &[u8]) -> bool {
+    !s.is_empty() && s.iter().all(u8::is_ascii_digit)
+}
+
+/// Returns the leniency which explains `parse` reaching `ours` where the spec rejects `input`.
+fn leniency(input: &[u8], ours: Verdict) -> Option<&'static str> {
+    LENIENCIES
+        .iter()
+        .find(|leniency| leniency.verdicts.contains(&ours.name()) && (leniency.applies)(input))
+        .map(|leniency| leniency.name)
+}
+
+fn corpus() -> Vec<Vec<u8>> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/crashes");
+    let mut inputs: Vec<_> = SEEDS.iter().map(|seed| seed.to_vec()).collect();
+    for entry in fs::read_dir(dir).unwrap() {
+        inputs.push(fs::read(entry.unwrap().path()).unwrap());
+    }
+    inputs
+}
+
+#[test]
+#[ignore]
+fn test_differential() {
+    let iterations: usize = std::env::var("DIFFERENTIAL_ITERATIONS")
+        .map(|n| n.parse().expect("DIFFERENTIAL_ITERATIONS must be a number"))
+        .unwrap_or(100_000);
+    let mut inputs = corpus();
+    let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+    for _ in 0..iterations {
+        let seed = SEEDS[rng.below(SEEDS.len())];
+        inputs.push(mutate(&mut rng, seed));
+    }
+
+    // Group unexplained divergences by the verdicts and the type byte, keeping the shortest
+    // example of each, since one gap tends to show up in thousands of inputs.
+    let mut divergences = BTreeMap::new();
+    for input in &inputs {
+        let (ours, reference) = (verdict(input), Reference::verdict(input));
+        if ours == reference {
+            continue;
+        }
+        if reference == Verdict::Reject && leniency(input, ours).is_some() {
+            continue;
+        }
+        let kind = format!(
+            "{} vs {} on {:?}",
+            ours.name(),
+            reference.name(),
+            input[0] as char
+        );
+        let (count, example) = divergences.entry(kind).or_insert((0, input));
+        *count += 1;
+        if input.len() < example.len() {
+            *example = input;
+        }
+    }
+    let report: Vec<_> = divergences
+        .iter()
+        .map(|(kind, (count, example))| {
+            let example = String::from_utf8_lossy(example).escape_debug().to_string();
+            format!("{} ({} inputs), such as \"{}\"", kind, count, example)
+        })
+        .collect();
+    assert!(report.is_empty(), "{}", report.join("\n"));
+}
+
+#[test]
+fn test_reference() {
+    for seed in SEEDS {
+        assert_eq!(Reference::verdict(seed), Verdict::Accept(seed.len()));
+        assert_eq!(verdict(seed), Verdict::Accept(seed.len()));
+    }
+    let cases: &[(&[u8], Verdict)] = &[
+        (b"*2\r\n:1\r\n", Verdict::Incomplete),
+        (b"$3\r\nfo", Verdict::Incomplete),
+        (b"$3\r\nfoo\r\n+extra\r\n", Verdict::Accept(9)),
+        (b":01\r\n", Verdict::Reject),
+        (b"$01\r\nx\r\n", Verdict::Reject),
+        (b"+a\rb\r\n", Verdict::Reject),
+        (b",1.\r\n", Verdict::Reject),
+        (b"?\r\n", Verdict::Reject),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(Reference::verdict(input), *expected, "{:?}", input);
+    }
+
+    let lenient: &[(&[u8], Option<&str>)] = &[
+        (b"$-7\r\n", Some(LENIENCIES[0].name)),
+        (b"*1\r\n*-9\r\n", Some(LENIENCIES[0].name)),
+        (b",.5\r\n", Some(LENIENCIES[1].name)),
+        (b"(04\r\n", Some(LENIENCIES[2].name)),
+        (b"+a\nb\r\n", Some(LENIENCIES[3].name)),
+        (b"+\n", Some(LENIENCIES[3].name)),
+        (b"$-0\r\n", None),
+        (b"%-1\r\n", None),
+        (b"(\r\n", None),
+        (b"!2\r\nSYNT", None),
+    ];
+    for (input, expected) in lenient {
+        assert_eq!(Reference::verdict(input), Verdict::Reject, "{:?}", input);
+        assert_eq!(leniency(input, verdict(input)), *expected, "{:?}", input);
+    }
+}